@@ -1,15 +1,83 @@
 // src/event.rs
-use serde::{Deserialize, Serialize};
+use crate::keycode::KeyCode;
+use serde::{ser, Deserialize, Serialize, Serializer};
+use std::num::NonZeroU8;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Event {
     MouseMove { x: i32, y: i32 },
-    MouseButton { button: u8, pressed: bool },
-    KeyPress { code: u16, name: String },  // Platform-independent key codes
-    KeyRelease { code: u16, name: String },
+    MouseButton { button: MouseButton, pressed: bool },
+    // `code` is the USB HID usage, the same physical key everywhere; `keysym` and
+    // `text` are what it produced under the sender's layout and modifiers, 0 and
+    // empty if unknown
+    KeyPress { code: KeyCode, keysym: u32, text: String },
+    KeyRelease { code: KeyCode, keysym: u32 },
     ScreenSwitch { to_screen: String },    // Screen identifier
     Heartbeat,
     Error(String),
+    MouseMoveRelative { dx: i32, dy: i32 },  // Pointer delta, for clients in relative mode
+    Scroll { dx: f64, dy: f64 },  // In wheel clicks, positive is right and down; fractions from smooth scrolling
+}
+
+/// A mouse button, whatever the platform numbers it. On the wire it is the
+/// X11 button number, as older builds sent it, so buttons X11 has no number
+/// for cannot be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "u8")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+    /// Further buttons, counting from 1 after `Forward`. X11 numbers them
+    /// from 10, so only the first 246 have an X11 number.
+    Extra(NonZeroU8),
+}
+
+impl MouseButton {
+    /// `None` for 0 and for the wheel, X11 buttons 4 to 7.
+    pub fn from_x11(button: u8) -> Option<Self> {
+        match button {
+            1 => Some(MouseButton::Left),
+            2 => Some(MouseButton::Middle),
+            3 => Some(MouseButton::Right),
+            8 => Some(MouseButton::Back),
+            9 => Some(MouseButton::Forward),
+            10.. => NonZeroU8::new(button - 9).map(MouseButton::Extra),
+            _ => None,
+        }
+    }
+
+    /// `None` for extra buttons past X11's last number, 255.
+    pub fn to_x11(self) -> Option<u8> {
+        match self {
+            MouseButton::Left => Some(1),
+            MouseButton::Middle => Some(2),
+            MouseButton::Right => Some(3),
+            MouseButton::Back => Some(8),
+            MouseButton::Forward => Some(9),
+            MouseButton::Extra(extra) => 9u8.checked_add(extra.get()),
+        }
+    }
+}
+
+impl Serialize for MouseButton {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.to_x11() {
+            Some(button) => serializer.serialize_u8(button),
+            None => Err(ser::Error::custom(format!("{:?} has no X11 button number", self))),
+        }
+    }
+}
+
+impl TryFrom<u8> for MouseButton {
+    type Error = String;
+
+    fn try_from(button: u8) -> Result<Self, Self::Error> {
+        MouseButton::from_x11(button).ok_or_else(|| format!("{} is not a mouse button", button))
+    }
 }
 
 // Platform detection
@@ -24,21 +92,105 @@ pub mod platform {
 }
 
 impl Event {
+    /// From an X11 button number; the wheel buttons are `Scroll` instead.
     pub fn new_mouse_button(button: u8, pressed: bool) -> Option<Self> {
-        // Only allow buttons 1-5
-        if (1..=5).contains(&button) {
-            Some(Event::MouseButton { button, pressed })
+        MouseButton::from_x11(button).map(|button| Event::MouseButton { button, pressed })
+    }
+
+    pub fn new_scroll(dx: f64, dy: f64) -> Option<Self> {
+        // Only finite amounts that actually scroll
+        if dx.is_finite() && dy.is_finite() && (dx != 0.0 || dy != 0.0) {
+            Some(Event::Scroll { dx, dy })
         } else {
             None
         }
     }
 
-    pub fn new_key_press(code: u16, name: String) -> Option<Self> {
-        // Don't allow empty names
-        if name.is_empty() {
-            None
-        } else {
-            Some(Event::KeyPress { code, name })
-        }
+    pub fn new_key_press(code: KeyCode, keysym: u32, text: String) -> Option<Self> {
+        // Only keys we can translate on the other end
+        code.name().map(|_| Event::KeyPress { code, keysym, text })
+    }
+}
+
+/// Identifies where events were captured, so receivers can keep sequence
+/// numbers from different sources apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeviceId(pub u32);
+
+impl DeviceId {
+    /// A fresh id, for a source that keeps no state between runs.
+    pub fn random() -> Self {
+        Self(rand::random())
     }
 }
+
+/// An `Event` with when and where it was captured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub event: Event,
+    /// Counts up from 1 per source; 0 for events that came without timing.
+    pub seq: u64,
+    /// Capture time in microseconds since the Unix epoch, on the source's clock.
+    pub captured_at: i64,
+    pub source: DeviceId,
+}
+
+impl TimedEvent {
+    /// Stamps `event` as captured now, as number `seq` of the default source.
+    pub fn new(event: Event, seq: u64) -> Self {
+        Self { event, seq, captured_at: wall_clock(), source: DeviceId::default() }
+    }
+
+    /// An event from a peer that does not send timing, as received now.
+    pub fn untimed(event: Event) -> Self {
+        Self::new(event, 0)
+    }
+
+    pub fn is_sequenced(&self) -> bool {
+        self.seq != 0
+    }
+
+    /// Time since capture, given how many microseconds the source's clock
+    /// is ahead of ours (see `network::latency`).
+    pub fn age(&self, clock_offset: i64) -> Duration {
+        let age = wall_clock() - (self.captured_at - clock_offset);
+        Duration::from_micros(age.max(0) as u64)
+    }
+}
+
+/// Hands out sequence numbers and capture times for one source.
+#[derive(Debug, Clone)]
+pub struct EventClock {
+    source: DeviceId,
+    next_seq: u64,
+}
+
+impl EventClock {
+    pub fn new(source: DeviceId) -> Self {
+        Self { source, next_seq: 1 }
+    }
+
+    pub fn source(&self) -> DeviceId {
+        self.source
+    }
+
+    /// Stamps `event` as the next one captured from this source.
+    pub fn stamp(&mut self, event: Event) -> TimedEvent {
+        self.sequence(TimedEvent::untimed(event))
+    }
+
+    /// Numbers an event that was stamped earlier, keeping its capture time.
+    pub fn sequence(&mut self, event: TimedEvent) -> TimedEvent {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        TimedEvent { seq, source: self.source, ..event }
+    }
+}
+
+/// Microseconds since the Unix epoch.
+pub(crate) fn wall_clock() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_micros() as i64)
+        .unwrap_or_default()
+}
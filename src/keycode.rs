@@ -0,0 +1,249 @@
+//! Keys as USB HID usages.
+//!
+//! A `KeyCode` names a physical key by its usage ID on the HID Keyboard/Keypad
+//! page (0x07), which is the same on every platform whatever its keycode
+//! numbering or layout: `KeyCode(0x04)` is the key labelled A on a US
+//! keyboard, and still the key right of Caps Lock on an AZERTY one.
+//!
+//! Media and browser keys that only have a usage on the Consumer page
+//! (0x0C) are `0xC000` plus that usage, such as `KeyCode(0xC0CD)` for Play/
+//! Pause; the Keyboard/Keypad page ends at 0xE7, so the two never overlap.
+//!
+//! `KEYS` translates usages to and from Linux evdev codes and the XKB key
+//! names X11 keymaps give their keycodes (`AC01` for that same key), so the
+//! X11 platform can go through its `xkb::Keymap` either way. Keys missing
+//! from the table have no translation and are not sent.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyCode(pub u16);
+
+struct Key {
+    usage: u16,
+    evdev: u16,
+    xkb: &'static str,
+    name: &'static str,
+}
+
+const fn key(usage: u16, evdev: u16, xkb: &'static str, name: &'static str) -> Key {
+    Key { usage, evdev, xkb, name }
+}
+
+/// Every key we translate: HID usage, evdev code, XKB key name and a
+/// readable name. Linux gives Non-US # the same evdev code as Backslash,
+/// so evdev code 43 only ever translates to the first of the two.
+const KEYS: &[Key] = &[
+    key(0x04, 30, "AC01", "A"),
+    key(0x05, 48, "AB05", "B"),
+    key(0x06, 46, "AB03", "C"),
+    key(0x07, 32, "AC03", "D"),
+    key(0x08, 18, "AD03", "E"),
+    key(0x09, 33, "AC04", "F"),
+    key(0x0A, 34, "AC05", "G"),
+    key(0x0B, 35, "AC06", "H"),
+    key(0x0C, 23, "AD08", "I"),
+    key(0x0D, 36, "AC07", "J"),
+    key(0x0E, 37, "AC08", "K"),
+    key(0x0F, 38, "AC09", "L"),
+    key(0x10, 50, "AB07", "M"),
+    key(0x11, 49, "AB06", "N"),
+    key(0x12, 24, "AD09", "O"),
+    key(0x13, 25, "AD10", "P"),
+    key(0x14, 16, "AD01", "Q"),
+    key(0x15, 19, "AD04", "R"),
+    key(0x16, 31, "AC02", "S"),
+    key(0x17, 20, "AD05", "T"),
+    key(0x18, 22, "AD07", "U"),
+    key(0x19, 47, "AB04", "V"),
+    key(0x1A, 17, "AD02", "W"),
+    key(0x1B, 45, "AB02", "X"),
+    key(0x1C, 21, "AD06", "Y"),
+    key(0x1D, 44, "AB01", "Z"),
+    key(0x1E, 2, "AE01", "1"),
+    key(0x1F, 3, "AE02", "2"),
+    key(0x20, 4, "AE03", "3"),
+    key(0x21, 5, "AE04", "4"),
+    key(0x22, 6, "AE05", "5"),
+    key(0x23, 7, "AE06", "6"),
+    key(0x24, 8, "AE07", "7"),
+    key(0x25, 9, "AE08", "8"),
+    key(0x26, 10, "AE09", "9"),
+    key(0x27, 11, "AE10", "0"),
+    key(0x28, 28, "RTRN", "Enter"),
+    key(0x29, 1, "ESC", "Escape"),
+    key(0x2A, 14, "BKSP", "Backspace"),
+    key(0x2B, 15, "TAB", "Tab"),
+    key(0x2C, 57, "SPCE", "Space"),
+    key(0x2D, 12, "AE11", "Minus"),
+    key(0x2E, 13, "AE12", "Equal"),
+    key(0x2F, 26, "AD11", "LeftBracket"),
+    key(0x30, 27, "AD12", "RightBracket"),
+    key(0x31, 43, "BKSL", "Backslash"),
+    key(0x32, 43, "AC12", "NonUsHash"),
+    key(0x33, 39, "AC10", "Semicolon"),
+    key(0x34, 40, "AC11", "Apostrophe"),
+    key(0x35, 41, "TLDE", "Grave"),
+    key(0x36, 51, "AB08", "Comma"),
+    key(0x37, 52, "AB09", "Period"),
+    key(0x38, 53, "AB10", "Slash"),
+    key(0x39, 58, "CAPS", "CapsLock"),
+    key(0x3A, 59, "FK01", "F1"),
+    key(0x3B, 60, "FK02", "F2"),
+    key(0x3C, 61, "FK03", "F3"),
+    key(0x3D, 62, "FK04", "F4"),
+    key(0x3E, 63, "FK05", "F5"),
+    key(0x3F, 64, "FK06", "F6"),
+    key(0x40, 65, "FK07", "F7"),
+    key(0x41, 66, "FK08", "F8"),
+    key(0x42, 67, "FK09", "F9"),
+    key(0x43, 68, "FK10", "F10"),
+    key(0x44, 87, "FK11", "F11"),
+    key(0x45, 88, "FK12", "F12"),
+    key(0x46, 99, "PRSC", "PrintScreen"),
+    key(0x47, 70, "SCLK", "ScrollLock"),
+    key(0x48, 119, "PAUS", "Pause"),
+    key(0x49, 110, "INS", "Insert"),
+    key(0x4A, 102, "HOME", "Home"),
+    key(0x4B, 104, "PGUP", "PageUp"),
+    key(0x4C, 111, "DELE", "Delete"),
+    key(0x4D, 107, "END", "End"),
+    key(0x4E, 109, "PGDN", "PageDown"),
+    key(0x4F, 106, "RGHT", "Right"),
+    key(0x50, 105, "LEFT", "Left"),
+    key(0x51, 108, "DOWN", "Down"),
+    key(0x52, 103, "UP", "Up"),
+    key(0x53, 69, "NMLK", "NumLock"),
+    key(0x54, 98, "KPDV", "KeypadDivide"),
+    key(0x55, 55, "KPMU", "KeypadMultiply"),
+    key(0x56, 74, "KPSU", "KeypadMinus"),
+    key(0x57, 78, "KPAD", "KeypadPlus"),
+    key(0x58, 96, "KPEN", "KeypadEnter"),
+    key(0x59, 79, "KP1", "Keypad1"),
+    key(0x5A, 80, "KP2", "Keypad2"),
+    key(0x5B, 81, "KP3", "Keypad3"),
+    key(0x5C, 75, "KP4", "Keypad4"),
+    key(0x5D, 76, "KP5", "Keypad5"),
+    key(0x5E, 77, "KP6", "Keypad6"),
+    key(0x5F, 71, "KP7", "Keypad7"),
+    key(0x60, 72, "KP8", "Keypad8"),
+    key(0x61, 73, "KP9", "Keypad9"),
+    key(0x62, 82, "KP0", "Keypad0"),
+    key(0x63, 83, "KPDL", "KeypadPeriod"),
+    key(0x64, 86, "LSGT", "NonUsBackslash"),
+    key(0x65, 127, "COMP", "Menu"),
+    key(0x66, 116, "POWR", "Power"),
+    key(0x67, 117, "KPEQ", "KeypadEqual"),
+    key(0x68, 183, "FK13", "F13"),
+    key(0x69, 184, "FK14", "F14"),
+    key(0x6A, 185, "FK15", "F15"),
+    key(0x6B, 186, "FK16", "F16"),
+    key(0x6C, 187, "FK17", "F17"),
+    key(0x6D, 188, "FK18", "F18"),
+    key(0x6E, 189, "FK19", "F19"),
+    key(0x6F, 190, "FK20", "F20"),
+    key(0x70, 191, "FK21", "F21"),
+    key(0x71, 192, "FK22", "F22"),
+    key(0x72, 193, "FK23", "F23"),
+    key(0x73, 194, "FK24", "F24"),
+    key(0x74, 134, "OPEN", "Execute"),
+    key(0x75, 138, "HELP", "Help"),
+    key(0x76, 130, "PROP", "Props"),
+    key(0x77, 132, "FRNT", "Select"),
+    key(0x78, 128, "STOP", "Stop"),
+    key(0x79, 129, "AGAI", "Again"),
+    key(0x7A, 131, "UNDO", "Undo"),
+    key(0x7B, 137, "CUT", "Cut"),
+    key(0x7C, 133, "COPY", "Copy"),
+    key(0x7D, 135, "PAST", "Paste"),
+    key(0x7E, 136, "FIND", "Find"),
+    key(0x7F, 113, "MUTE", "Mute"),
+    key(0x80, 115, "VOL+", "VolumeUp"),
+    key(0x81, 114, "VOL-", "VolumeDown"),
+    key(0x85, 121, "I129", "KeypadComma"),
+    key(0x87, 89, "AB11", "Ro"),
+    key(0x88, 93, "HKTG", "KatakanaHiragana"),
+    key(0x89, 124, "AE13", "Yen"),
+    key(0x8A, 92, "HENK", "Henkan"),
+    key(0x8B, 94, "MUHE", "Muhenkan"),
+    key(0x90, 122, "HNGL", "Hangul"),
+    key(0x91, 123, "HJCV", "Hanja"),
+    key(0xE0, 29, "LCTL", "LeftControl"),
+    key(0xE1, 42, "LFSH", "LeftShift"),
+    key(0xE2, 56, "LALT", "LeftAlt"),
+    key(0xE3, 125, "LWIN", "LeftMeta"),
+    key(0xE4, 97, "RCTL", "RightControl"),
+    key(0xE5, 54, "RTSH", "RightShift"),
+    key(0xE6, 100, "RALT", "RightAlt"),
+    key(0xE7, 126, "RWIN", "RightMeta"),
+    // Consumer page
+    key(0xC06F, 225, "I233", "BrightnessUp"),
+    key(0xC070, 224, "I232", "BrightnessDown"),
+    key(0xC0B5, 163, "I171", "NextTrack"),
+    key(0xC0B6, 165, "I173", "PreviousTrack"),
+    key(0xC0B7, 166, "I174", "MediaStop"),
+    key(0xC0B8, 161, "I169", "Eject"),
+    key(0xC0CD, 164, "I172", "PlayPause"),
+    key(0xC18A, 155, "I163", "Mail"),
+    key(0xC192, 140, "I148", "Calculator"),
+    key(0xC221, 217, "I225", "Search"),
+    key(0xC223, 172, "I180", "BrowserHome"),
+    key(0xC224, 158, "I166", "BrowserBack"),
+    key(0xC225, 159, "I167", "BrowserForward"),
+    key(0xC227, 173, "I181", "BrowserRefresh"),
+    key(0xC22A, 156, "I164", "Bookmarks"),
+];
+
+impl KeyCode {
+    pub fn from_evdev(code: u16) -> Option<Self> {
+        KEYS.iter().find(|key| key.evdev == code).map(|key| KeyCode(key.usage))
+    }
+
+    pub fn to_evdev(self) -> Option<u16> {
+        self.entry().map(|key| key.evdev)
+    }
+
+    /// From the name an XKB keymap gives the key, such as `AC01`.
+    pub fn from_xkb_name(name: &str) -> Option<Self> {
+        KEYS.iter().find(|key| key.xkb == name).map(|key| KeyCode(key.usage))
+    }
+
+    pub fn xkb_name(self) -> Option<&'static str> {
+        self.entry().map(|key| key.xkb)
+    }
+
+    /// From a readable name as `name` returns it, such as `LeftShift`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEYS.iter().find(|key| key.name == name).map(|key| KeyCode(key.usage))
+    }
+
+    /// What the key is labelled on a US keyboard, roughly.
+    pub fn name(self) -> Option<&'static str> {
+        self.entry().map(|key| key.name)
+    }
+
+    fn entry(self) -> Option<&'static Key> {
+        KEYS.iter().find(|key| key.usage == self.0)
+    }
+}
+
+impl fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "0x{:04X}", self.0),
+        }
+    }
+}
+
+/// Parses the names `name` gives, such as `ScrollLock`.
+impl FromStr for KeyCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        KeyCode::from_name(s).ok_or_else(|| format!("unknown key '{}'", s))
+    }
+}
@@ -1,4 +1,5 @@
 pub mod event;
+pub mod keycode;
 pub mod network;
 pub mod platform;
 
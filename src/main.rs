@@ -1,19 +1,828 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::auth::Role;
+use rust_barrier::network::barrier::client::BarrierClient;
+use rust_barrier::network::barrier::server as barrier_server;
+use rust_barrier::network::barrier::Dialect;
+use rust_barrier::network::codec::{CodecKind, EventCodec, JsonCodec};
+use rust_barrier::network::discovery::{self, Announcer, Beacon, DiscoveryConfig};
+use rust_barrier::network::handshake::{Capabilities, Hello, ScreenInfo, Session};
+use rust_barrier::network::heartbeat::HeartbeatConfig;
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::latency::{LatencyConfig, LatencyReport};
+use rust_barrier::network::outbound::{OutboundConfig, OverflowPolicy};
+#[cfg(feature = "quic")]
+use rust_barrier::network::quic::{QuicConnection, QuicEndpoint, QuicIncoming};
+use rust_barrier::network::reconnect::{BackoffConfig, Reconnector, SessionState};
+use rust_barrier::network::sequence::{Delivery, SequenceTracker};
+use rust_barrier::network::tls::{self, Fingerprint, KnownServers, TlsConfig, TlsIdentity};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use rust_barrier::platform::x11::{X11Error, X11Platform};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::Interval;
 
 #[derive(Parser)]
 #[command(version, about)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Capture local input and share it with connected clients
     #[arg(short, long)]
     server: bool,
+    /// Address to listen on (server) or connect to (client)
     #[arg(short, long, default_value = "127.0.0.1")]
     ip: String,
     #[arg(short, long, default_value = "8080")]
     port: u16,
+    /// Another address of the server, tried in order when --ip is unreachable (repeatable)
+    #[arg(long = "fallback-ip")]
+    fallback_ips: Vec<String>,
+    /// X display number to capture from or inject into
     #[arg(long, default_value = "0")]
     display: u32,
+    /// Preferred event encoding: json, cbor, bincode or msgpack
+    #[arg(long, default_value = "json")]
+    codec: CodecKind,
+    /// Screen name announced to the peer (defaults to the host name)
+    #[arg(long)]
+    name: Option<String>,
+    /// Shared secret both sides must know; prefer the environment variable
+    #[arg(long, env = "RUST_BARRIER_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+    /// Encrypt the connection with TLS
+    #[arg(long)]
+    tls: bool,
+    /// Where the TLS certificate is kept (defaults to ~/.config/rust-barrier)
+    #[arg(long)]
+    tls_dir: Option<PathBuf>,
+    /// Only accept peers presenting this certificate fingerprint (repeatable);
+    /// without one a client trusts the server it first connects to
+    #[arg(long = "peer-fingerprint")]
+    peer_fingerprints: Vec<Fingerprint>,
+    /// Use QUIC instead of TCP; implies --tls certificates
+    #[arg(long)]
+    quic: bool,
+    /// Send pointer motion over UDP so packet loss does not stall it;
+    /// datagrams are neither encrypted nor authenticated
+    #[arg(long, conflicts_with_all = ["tls", "secret"])]
+    udp_motion: bool,
+    /// Ask for pointer deltas instead of positions, for games and 3D tools
+    #[arg(long)]
+    relative_motion: bool,
+    /// Key that moves control to the next client screen, and back here after the last
+    #[arg(long, default_value = "ScrollLock", requires = "server")]
+    switch_key: KeyCode,
+    /// Events the server may queue for a slow client
+    #[arg(long, default_value = "1024")]
+    send_queue: usize,
+    /// What to do when a client's queue is full: drop-motion or disconnect
+    #[arg(long, default_value = "drop-motion")]
+    on_overflow: OverflowPolicy,
+    /// Milliseconds of silence before sending a heartbeat
+    #[arg(long, default_value = "1000")]
+    heartbeat_interval: u64,
+    /// Milliseconds without hearing from the peer before dropping it
+    #[arg(long, default_value = "3000")]
+    heartbeat_timeout: u64,
+    /// Join a Barrier, Synergy or Input Leap server instead (usually --port 24800)
+    #[arg(long, conflicts_with_all = ["server", "tls", "quic"])]
+    barrier: bool,
+    /// Also accept Barrier and Input Leap clients on this port (usually 24800), without TLS
+    #[arg(long, requires = "server")]
+    barrier_port: Option<u16>,
+    /// Greet clients on --barrier-port as Synergy 1 does, for Synergy 1 clients instead
+    #[arg(long, requires = "barrier_port")]
+    synergy: bool,
+    /// Milliseconds between latency pings, 0 to turn them off
+    #[arg(long, default_value = "1000")]
+    ping_interval: u64,
+    /// Print round-trip times every this many seconds, 0 to stay quiet
+    #[arg(long, default_value = "0")]
+    latency_report: u64,
+    /// Announce this server on the local network so clients can find it
+    #[arg(long, requires = "server")]
+    announce: bool,
+    /// Run this command and serve it as a client over its stdin and stdout,
+    /// e.g. "ssh host rust-barrier --stdio" (repeatable)
+    #[arg(long = "tunnel", requires = "server")]
+    tunnels: Vec<String>,
+    /// Talk to the server over stdin and stdout, as started by its --tunnel
+    #[arg(long, conflicts_with_all = ["server", "barrier", "tls", "quic", "udp_motion", "connect_to", "latency_report"])]
+    stdio: bool,
+    /// Find the server announcing this name on the local network instead of using --ip
+    #[arg(long, conflicts_with_all = ["server", "barrier", "ip", "fallback_ips"])]
+    connect_to: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the servers announcing themselves on the local network
+    Discover {
+        /// Seconds to listen for
+        #[arg(long, default_value = "3")]
+        wait: u64,
+    },
+}
+
+impl Args {
+    fn name(&self) -> String {
+        self.name.clone()
+            .unwrap_or_else(|| gethostname::gethostname().to_string_lossy().into_owned())
+    }
+
+    /// Loads our certificate and prints its fingerprint for out-of-band checks.
+    fn tls_config(&self) -> Result<Option<TlsConfig>, Box<dyn Error>> {
+        if !self.tls && !self.quic {
+            return Ok(None);
+        }
+        if self.quic && !cfg!(feature = "quic") {
+            return Err("QUIC support was not compiled in".into());
+        }
+        let dir = match &self.tls_dir {
+            Some(dir) => dir.clone(),
+            None => default_config_dir().ok_or("cannot find a config directory, pass --tls-dir")?,
+        };
+        let identity = TlsIdentity::load_or_generate(&dir, &self.name())?;
+        println!("TLS certificate fingerprint: {}", identity.fingerprint());
+        let config = self.peer_fingerprints.iter()
+            .fold(TlsConfig::new(identity), |config, fingerprint| config.pin(*fingerprint))
+            .trust_on_first_use(KnownServers::new(dir.join("known_servers")));
+        Ok(Some(config))
+    }
+
+    /// `host:port` strings for the client to try, most preferred first.
+    fn server_addresses(&self) -> Vec<String> {
+        std::iter::once(&self.ip)
+            .chain(&self.fallback_ips)
+            .map(|ip| if ip.contains(':') {
+                format!("[{}]:{}", ip, self.port)
+            } else {
+                format!("{}:{}", ip, self.port)
+            })
+            .collect()
+    }
+
+    fn heartbeat(&self) -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: Duration::from_millis(self.heartbeat_interval),
+            timeout: Duration::from_millis(self.heartbeat_timeout),
+        }
+    }
+
+    fn latency(&self) -> Option<LatencyConfig> {
+        (self.ping_interval > 0).then(|| LatencyConfig {
+            ping_interval: Duration::from_millis(self.ping_interval),
+            ..LatencyConfig::default()
+        })
+    }
+
+    fn latency_report(&self) -> Option<Interval> {
+        (self.latency_report > 0).then(|| tokio::time::interval(Duration::from_secs(self.latency_report)))
+    }
+
+    fn outbound(&self) -> OutboundConfig {
+        OutboundConfig {
+            capacity: self.send_queue,
+            overflow: self.on_overflow,
+            ..OutboundConfig::default()
+        }
+    }
+
+    fn hello(&self, screen: ScreenInfo) -> Hello {
+        let mut codecs = vec![self.codec];
+        codecs.extend(CodecKind::available().into_iter().filter(|codec| *codec != self.codec));
+        Hello {
+            screen,
+            capabilities: Capabilities {
+                codecs,
+                // QUIC already keeps motion clear of lost packets
+                udp_motion: self.udp_motion && !self.quic,
+                // The server captures both kinds, so the client decides
+                relative_motion: self.relative_motion || self.server,
+                auth: self.secret.is_some(),
+                timed_events: true,
+                ..Capabilities::default()
+            },
+            ..Hello::new(self.name())
+        }
+    }
+}
+
+fn default_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rust-barrier"))
+}
+
+fn format_latency(report: &LatencyReport) -> String {
+    format!(
+        "rtt {:.1?} (p50 {:.1?}, p95 {:.1?}, p99 {:.1?}), clock offset {:+.1}ms",
+        report.latest,
+        report.p50,
+        report.p95,
+        report.p99,
+        report.clock_offset as f64 / 1000.0
+    )
+}
+
+/// Ticks `interval`, or never if there is none.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// A server's certificate was either pinned or is the one first seen at
+/// its address; anything else fails the handshake.
+fn report_server_fingerprint(config: &TlsConfig, fingerprint: &Fingerprint) {
+    match config.known_servers().filter(|_| !config.is_pinned(fingerprint)) {
+        Some(known) => println!(
+            "Server certificate {} is the one first seen at this address, remembered in {}",
+            fingerprint,
+            known.path().display()
+        ),
+        None => println!("Server certificate {} is pinned", fingerprint),
+    }
 }
 
-fn main() {
-    let _args = Args::parse();
-    println!("CLI is not implemented in test environment");
+fn report_client_fingerprint(config: &TlsConfig, fingerprint: &Fingerprint) {
+    if config.is_pinned(fingerprint) {
+        println!("Client certificate {} is pinned", fingerprint);
+    } else {
+        println!(
+            "warning: client certificate {} is not pinned; verify it and pass --peer-fingerprint",
+            fingerprint
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = Args::parse();
+    let result = if let Some(Command::Discover { wait }) = args.command {
+        run_discover(Duration::from_secs(wait)).await
+    } else if args.server {
+        run_server(&args).await
+    } else if args.barrier {
+        run_barrier_client(&args).await
+    } else {
+        match find_server(&mut args).await {
+            Ok(()) => run_client(&args).await,
+            Err(e) => Err(e),
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run_discover(wait: Duration) -> Result<(), Box<dyn Error>> {
+    let servers = discovery::discover(DiscoveryConfig::default(), wait).await?;
+    if servers.is_empty() {
+        println!("No servers found");
+    }
+    for server in servers {
+        match server.fingerprint {
+            Some(fingerprint) => println!("{}\t{}\tTLS {}", server.name, server.addr, fingerprint),
+            None => println!("{}\t{}", server.name, server.addr),
+        }
+    }
+    Ok(())
+}
+
+/// Points `--ip` and `--port` at the server named by `--connect-to`, if any.
+async fn find_server(args: &mut Args) -> Result<(), Box<dyn Error>> {
+    let Some(name) = &args.connect_to else {
+        return Ok(());
+    };
+    println!("Looking for {} on the local network", name);
+    let server = discovery::find(name, DiscoveryConfig::default(), Duration::from_secs(10))
+        .await
+        .map_err(|e| format!("no server named {} found: {}", name, e))?;
+    println!("Found {} at {}", server.name, server.addr);
+    args.ip = server.addr.ip().to_string();
+    args.port = server.addr.port();
+    Ok(())
+}
+
+/// Announces the server every few seconds; failing to do so is not fatal.
+fn spawn_announcer(args: &Args, tls: Option<&TlsConfig>) -> Result<(), Box<dyn Error>> {
+    let beacon = Beacon {
+        name: args.name(),
+        port: args.port,
+        fingerprint: tls.map(|config| config.identity().fingerprint()),
+    };
+    let announcer = Announcer::new(&beacon, DiscoveryConfig::default())?;
+    tokio::spawn(async move {
+        if let Err(e) = announcer.run().await {
+            eprintln!("warning: stopped announcing the server: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Everything a client task needs besides its socket.
+#[derive(Clone)]
+struct ClientSetup {
+    tls: Option<TlsConfig>,
+    hello: Hello,
+    secret: Option<String>,
+    heartbeat: HeartbeatConfig,
+    latency: Option<LatencyConfig>,
+}
+
+async fn run_server(args: &Args) -> Result<(), Box<dyn Error>> {
+    // Fail early if the codec was not compiled in
+    args.codec.codec()?;
+    let tls = args.tls_config()?;
+    let listener = Listener::bind(args, tls.as_ref()).await?;
+    if args.announce {
+        spawn_announcer(args, tls.as_ref())?;
+    }
+
+    let hub = Hub::with_outbound(args.name(), args.outbound());
+    let mut hub_events = hub.subscribe();
+    let capture_hub = hub.clone();
+    let display = args.display;
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let switch_key = args.switch_key;
+    // The X11 connection blocks in `wait_for_event`, so capture runs on its
+    // own thread and hands events straight to the hub. Input is only
+    // grabbed while a client screen is active; the switch key is grabbed
+    // on its own so it works from the local screen too.
+    let mut capture = tokio::task::spawn_blocking(move || {
+        let platform = X11Platform::open_display(display)?;
+        platform.grab_key(switch_key)?;
+        let (width, height) = platform.screen_size();
+        let _ = ready_tx.send((ScreenInfo { width, height }, platform.input_grab()));
+        platform.run_event_loop(|event| match event {
+            Event::KeyPress { code, .. } if code == switch_key => {
+                capture_hub.switch_next();
+            }
+            Event::KeyRelease { code, .. } if code == switch_key => {}
+            event => {
+                capture_hub.dispatch(event);
+            }
+        })
+    });
+
+    let (screen, grab) = tokio::select! {
+        ready = ready_rx => ready?,
+        finished = &mut capture => {
+            finished??;
+            return Ok(());
+        }
+    };
+    let setup = ClientSetup {
+        tls,
+        hello: args.hello(screen),
+        secret: args.secret.clone(),
+        heartbeat: args.heartbeat(),
+        latency: args.latency(),
+    };
+    for command in &args.tunnels {
+        tokio::spawn(serve_tunnel(hub.clone(), command.clone(), setup.clone()));
+    }
+    let serve = listener.serve(&hub, setup);
+    tokio::pin!(serve);
+    let serve_barrier = serve_barrier(args, &hub);
+    tokio::pin!(serve_barrier);
+    let mut report = args.latency_report();
+
+    loop {
+        tokio::select! {
+            served = &mut serve => return Ok(served?),
+            served = &mut serve_barrier => return served,
+            _ = tick(&mut report) => {
+                for screen in hub.screens() {
+                    if let Some(latency) = hub.latency(&screen) {
+                        println!("{}: {}", screen, format_latency(&latency));
+                    }
+                }
+            }
+            event = hub_events.recv() => match event {
+                Ok(HubEvent::Joined { screen, addr }) => println!("Client {} connected from {}", screen, addr),
+                Ok(HubEvent::Rejected { addr, reason }) => eprintln!("Client {} rejected: {}", addr, reason),
+                Ok(HubEvent::Left { screen, reason }) => eprintln!("Client {} lost: {}", screen, reason),
+                Ok(HubEvent::Switched { to: Some(screen) }) => {
+                    println!("Switched to {}", screen);
+                    grab.grab()?;
+                }
+                Ok(HubEvent::Switched { to: None }) => {
+                    println!("Returning control to the local screen");
+                    grab.ungrab()?;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            finished = &mut capture => {
+                finished??;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Serves stock Barrier or Synergy 1 clients as well, if `--barrier-port`
+/// was given.
+async fn serve_barrier(args: &Args, hub: &Hub) -> Result<(), Box<dyn Error>> {
+    let Some(port) = args.barrier_port else {
+        return std::future::pending().await;
+    };
+    let listener = TcpListener::bind((args.ip.as_str(), port)).await?;
+    let dialect = if args.synergy { Dialect::Synergy } else { Dialect::Barrier };
+    println!("Listening for {:?} clients on {}", dialect, listener.local_addr()?);
+    Ok(barrier_server::serve(hub, listener, dialect).await?)
+}
+
+/// How long to wait before starting a tunnel command again.
+const TUNNEL_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs `command` with the shell and serves it as a client over its stdin
+/// and stdout, starting it again whenever it exits.
+async fn serve_tunnel(hub: Hub, command: String, setup: ClientSetup) {
+    loop {
+        if let Err(e) = run_tunnel(&hub, &command, setup.clone()).await {
+            eprintln!("Tunnel `{}` failed: {}", command, e);
+        }
+        tokio::time::sleep(TUNNEL_RESTART_DELAY).await;
+    }
+}
+
+async fn run_tunnel(hub: &Hub, command: &str, setup: ClientSetup) -> Result<(), NetworkError> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+        unreachable!("both pipes were requested");
+    };
+    let conn = NetworkConnection::from_halves(stdout, stdin, JsonCodec);
+    let (conn, session) = accept_client(conn, setup, None).await?;
+    // Tunnelled clients have no address of their own
+    hub.serve_client(conn, session, SocketAddr::from(([0, 0, 0, 0], 0))).await;
+    Ok(())
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(feature = "quic")]
+    Quic(QuicEndpoint),
+}
+
+impl Listener {
+    async fn bind(args: &Args, tls: Option<&TlsConfig>) -> Result<Self, Box<dyn Error>> {
+        let listener = match (args.quic, tls) {
+            #[cfg(feature = "quic")]
+            (true, Some(config)) => {
+                let addr = tokio::net::lookup_host((args.ip.as_str(), args.port)).await?
+                    .next()
+                    .ok_or("listen address did not resolve")?;
+                Listener::Quic(QuicEndpoint::server(addr, config)?)
+            }
+            _ => Listener::Tcp(TcpListener::bind((args.ip.as_str(), args.port)).await?),
+        };
+        let local_addr = match &listener {
+            Listener::Tcp(listener) => listener.local_addr()?,
+            #[cfg(feature = "quic")]
+            Listener::Quic(endpoint) => endpoint.local_addr()?,
+        };
+        println!("Listening on {}", local_addr);
+        Ok(listener)
+    }
+
+    async fn serve(self, hub: &Hub, setup: ClientSetup) -> Result<(), NetworkError> {
+        match self {
+            Listener::Tcp(listener) => {
+                hub.serve(listener, move |stream| accept_tcp(stream, setup.clone())).await
+            }
+            #[cfg(feature = "quic")]
+            Listener::Quic(endpoint) => {
+                hub.serve(endpoint, move |incoming| accept_quic(incoming, setup.clone())).await
+            }
+        }
+    }
+}
+
+async fn accept_tcp(
+    stream: TcpStream,
+    setup: ClientSetup,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    let peer_ip = stream.peer_addr()?.ip();
+    let conn = match &setup.tls {
+        Some(config) => {
+            let (conn, fingerprint) = tls::accept(stream, config).await?;
+            report_client_fingerprint(config, &fingerprint);
+            conn
+        }
+        None => NetworkConnection::new(stream),
+    };
+    accept_client(conn, setup, Some(peer_ip)).await
+}
+
+#[cfg(feature = "quic")]
+async fn accept_quic(
+    incoming: QuicIncoming,
+    setup: ClientSetup,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    let (conn, quic) = incoming.establish().await?;
+    if let Some(config) = &setup.tls {
+        report_client_fingerprint(config, &quic.peer_fingerprint()?);
+    }
+    accept_client(conn, setup, None).await
+}
+
+/// The protocol part of accepting a client, over any transport. Motion
+/// goes over UDP to `motion_peer` if that was negotiated.
+async fn accept_client(
+    mut conn: NetworkConnection,
+    setup: ClientSetup,
+    motion_peer: Option<IpAddr>,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    conn.set_heartbeat(setup.heartbeat);
+    conn.set_identity(setup.hello.name.clone());
+    let session = conn.server_handshake(setup.hello).await?;
+    if let Some(secret) = setup.secret {
+        conn.set_credentials(secret.as_bytes());
+        conn.authenticate(Role::Server).await?;
+    }
+    if let (true, Some(peer_ip)) = (session.udp_motion, motion_peer) {
+        conn.send_motion_to(peer_ip).await?;
+    }
+    if let Some(latency) = setup.latency {
+        conn.set_latency(latency);
+    }
+    let codec = session.codec.codec()?;
+    Ok((conn.into_codec(codec), session))
+}
+
+async fn connect_tcp(
+    stream: TcpStream,
+    args: &Args,
+    tls: &Option<TlsConfig>,
+    hello: Hello,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    let local_ip = stream.local_addr()?.ip();
+    let conn = match tls {
+        Some(config) => {
+            let (conn, fingerprint) = tls::connect(stream, config).await?;
+            report_server_fingerprint(config, &fingerprint);
+            conn
+        }
+        None => NetworkConnection::new(stream),
+    };
+    connect_server(conn, args, hello, Some(local_ip)).await
+}
+
+#[cfg(feature = "quic")]
+async fn connect_quic(
+    conn: NetworkConnection,
+    quic: QuicConnection,
+    args: &Args,
+    tls: &Option<TlsConfig>,
+    hello: Hello,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    if let Some(config) = tls {
+        report_server_fingerprint(config, &quic.peer_fingerprint()?);
+    }
+    connect_server(conn, args, hello, None).await
+}
+
+/// The protocol part of connecting to the server, over any transport.
+/// Motion is received over UDP on `motion_ip` if that was negotiated.
+async fn connect_server(
+    mut conn: NetworkConnection,
+    args: &Args,
+    hello: Hello,
+    motion_ip: Option<IpAddr>,
+) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+    conn.set_heartbeat(args.heartbeat());
+    conn.set_identity(args.name());
+    let session = conn.client_handshake(hello).await?;
+    if let Some(secret) = &args.secret {
+        conn.set_credentials(secret.as_bytes());
+        conn.authenticate(Role::Client).await?;
+    }
+    if let (true, Some(local_ip)) = (session.udp_motion, motion_ip) {
+        conn.listen_for_motion(local_ip).await?;
+    }
+    if let Some(latency) = args.latency() {
+        conn.set_latency(latency);
+    }
+    let codec = session.codec.codec()?;
+    Ok((conn.into_codec(codec), session))
+}
+
+enum Dialer {
+    Tcp,
+    #[cfg(feature = "quic")]
+    Quic(QuicEndpoint),
+}
+
+impl Dialer {
+    fn new(args: &Args, tls: Option<&TlsConfig>) -> Result<Self, Box<dyn Error>> {
+        match (args.quic, tls) {
+            #[cfg(feature = "quic")]
+            (true, Some(config)) => Ok(Dialer::Quic(QuicEndpoint::client(([0, 0, 0, 0], 0).into(), config)?)),
+            _ => Ok(Dialer::Tcp),
+        }
+    }
+
+    async fn connect(
+        &self,
+        reconnector: &mut Reconnector,
+        args: &Args,
+        tls: &Option<TlsConfig>,
+        hello: &Hello,
+    ) -> Result<(NetworkConnection<Box<dyn EventCodec>>, Session), NetworkError> {
+        let on_failure = |address: &str, e: &NetworkError| eprintln!("Could not connect to {}: {}", address, e);
+        match self {
+            Dialer::Tcp => {
+                reconnector.connect(|stream| connect_tcp(stream, args, tls, hello.clone()), on_failure).await
+            }
+            #[cfg(feature = "quic")]
+            Dialer::Quic(endpoint) => {
+                reconnector
+                    .connect_via(endpoint, |(conn, quic)| connect_quic(conn, quic, args, tls, hello.clone()), on_failure)
+                    .await
+            }
+        }
+    }
+}
+
+async fn run_client(args: &Args) -> Result<(), Box<dyn Error>> {
+    args.codec.codec()?;
+    if args.stdio {
+        return run_stdio_client(args).await;
+    }
+    let tls = args.tls_config()?;
+    let platform = X11Platform::open_display(args.display)?;
+    let (width, height) = platform.screen_size();
+    let hello = args.hello(ScreenInfo { width, height });
+    let dialer = Dialer::new(args, tls.as_ref())?;
+
+    let mut reconnector = Reconnector::new(args.server_addresses(), BackoffConfig::default());
+    let mut state = SessionState::default();
+    // Outlives the connection, so events replayed after a reconnect are
+    // recognized
+    let mut sequence = SequenceTracker::new();
+    loop {
+        let (mut conn, session) = dialer.connect(&mut reconnector, args, &tls, &hello).await?;
+        println!(
+            "Connected to {} at {} using {}",
+            session.peer.name,
+            reconnector.connected_address().unwrap_or_default(),
+            session.codec
+        );
+
+        let error = relay_events(&mut conn, &platform, &mut state, &mut sequence, args.latency_report()).await?;
+        match error {
+            NetworkError::Connection(_) => println!("Server closed the connection, reconnecting"),
+            e => eprintln!("Lost connection to server: {}, reconnecting", e),
+        }
+        // The server cannot send the releases any more
+        for event in state.release_held() {
+            inject(&platform, &event)?;
+        }
+    }
+}
+
+/// Like `run_client`, over stdin and stdout, which are left to whoever started
+/// us; stdout carries the protocol, so messages go to stderr. There is no
+/// reconnecting, since the server starts us again.
+async fn run_stdio_client(args: &Args) -> Result<(), Box<dyn Error>> {
+    let platform = X11Platform::open_display(args.display)?;
+    let (width, height) = platform.screen_size();
+    let hello = args.hello(ScreenInfo { width, height });
+    let (mut conn, session) = connect_server(NetworkConnection::stdio(), args, hello, None).await?;
+    eprintln!("Connected to {} over stdio using {}", session.peer.name, session.codec);
+
+    let mut state = SessionState::default();
+    let error = relay_events(&mut conn, &platform, &mut state, &mut SequenceTracker::new(), None).await?;
+    for event in state.release_held() {
+        inject(&platform, &event)?;
+    }
+    match error {
+        NetworkError::Connection(_) => Ok(()),
+        e => Err(e.into()),
+    }
+}
+
+/// Injects `event`, only warning about one this display cannot reproduce.
+fn inject(platform: &X11Platform, event: &Event) -> Result<(), X11Error> {
+    match platform.simulate_event(event) {
+        Err(e @ X11Error::Unsupported(_)) => {
+            eprintln!("warning: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Injects events from the server until the connection fails, and returns
+/// why it failed. Events the server sent before are skipped.
+async fn relay_events(
+    conn: &mut NetworkConnection<Box<dyn EventCodec>>,
+    platform: &X11Platform,
+    state: &mut SessionState,
+    sequence: &mut SequenceTracker,
+    mut report: Option<Interval>,
+) -> Result<NetworkError, X11Error> {
+    // Capture to injection, since the last report
+    let mut slowest = Duration::ZERO;
+    loop {
+        let next_heartbeat = conn.next_heartbeat().expect("heartbeat enabled");
+        tokio::select! {
+            received = conn.receive_timed() => match received {
+                Ok(timed) => {
+                    if sequence.observe(&timed) == Delivery::Duplicate {
+                        continue;
+                    }
+                    if let Event::Error(message) = &timed.event {
+                        eprintln!("Server: {}", message);
+                    }
+                    inject(platform, &timed.event)?;
+                    state.observe(&timed.event);
+                    if timed.is_sequenced() {
+                        let offset = conn.latency_stats().clock_offset().unwrap_or_default();
+                        slowest = slowest.max(timed.age(offset));
+                    }
+                }
+                Err(e) if e.is_violation() => eprintln!("warning: dropped an event from the server: {}", e),
+                Err(e) => return Ok(e),
+            },
+            _ = tokio::time::sleep_until(next_heartbeat) => {
+                if let Err(e) = conn.send_heartbeat_if_due().await {
+                    return Ok(e);
+                }
+            }
+            _ = tick(&mut report) => {
+                if let Some(latency) = conn.latency() {
+                    println!("Server: {}", format_latency(&latency));
+                }
+                println!(
+                    "Events: slowest capture to injection {:.1?}, {} missing, {} duplicates dropped",
+                    slowest,
+                    sequence.missing(),
+                    sequence.duplicates()
+                );
+                slowest = Duration::ZERO;
+            }
+        }
+    }
+}
+
+/// Like `run_client`, against a Barrier server.
+async fn run_barrier_client(args: &Args) -> Result<(), Box<dyn Error>> {
+    let platform = X11Platform::open_display(args.display)?;
+    let (width, height) = platform.screen_size();
+    let screen = ScreenInfo { width, height };
+
+    let mut reconnector = Reconnector::new(args.server_addresses(), BackoffConfig::default());
+    let mut state = SessionState::default();
+    loop {
+        let mut client = reconnector
+            .connect(
+                |stream| BarrierClient::connect(stream, args.name(), screen),
+                |address, e| eprintln!("Could not connect to {}: {}", address, e),
+            )
+            .await?;
+        let (major, minor) = client.server_version();
+        println!(
+            "Connected to Barrier server at {} (protocol {}.{}) as {}",
+            reconnector.connected_address().unwrap_or_default(),
+            major,
+            minor,
+            client.name()
+        );
+
+        let error = loop {
+            match client.receive_event().await {
+                Ok(event) => {
+                    inject(&platform, &event)?;
+                    state.observe(&event);
+                }
+                Err(e) => break e,
+            }
+        };
+        eprintln!("Lost connection to Barrier server: {}, reconnecting", error);
+        for event in state.release_held() {
+            inject(&platform, &event)?;
+        }
+    }
 }
@@ -0,0 +1,164 @@
+//! Pre-shared-key mutual authentication.
+//!
+//! Both peers run the same exchange at the same time:
+//!
+//! 1. send an `AuthChallenge` frame: a fresh random nonce followed by our identity
+//! 2. send an `AuthProof` frame: HMAC-SHA256, keyed with the shared secret,
+//!    over our role and the whole transcript: the client's identity and
+//!    nonce, then the server's
+//! 3. check the peer's proof, made in the other role, against the same transcript
+//!
+//! The secret never crosses the wire, and because each side contributes a
+//! fresh nonce a recorded proof is useless on a later connection. The role
+//! label stops a proof from being reflected back at its maker, even through
+//! a second connection where the attacker plays the other role: a server
+//! never accepts a proof made as a server. Peers claiming our own identity
+//! are turned away for the same reason.
+
+use super::codec::EventCodec;
+use super::frame::{Frame, FrameType};
+use super::handshake::HandshakeError;
+use super::NetworkConnection;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+pub const NONCE_LEN: usize = 32;
+pub const MAX_IDENTITY_LEN: usize = 255;
+const PROOF_CONTEXT: &[u8] = b"rust-barrier auth v2";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Which end of the connection we are, for `authenticate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl Role {
+    fn label(self) -> &'static [u8] {
+        match self {
+            Role::Client => b"client",
+            Role::Server => b"server",
+        }
+    }
+
+    fn peer(self) -> Role {
+        match self {
+            Role::Client => Role::Server,
+            Role::Server => Role::Client,
+        }
+    }
+}
+
+/// One side's identity and nonce.
+struct Party<'a> {
+    id: &'a str,
+    nonce: &'a [u8],
+}
+
+pub(crate) struct AuthState {
+    id: String,
+    secret: Option<Vec<u8>>,
+    peer_identity: Option<String>,
+}
+
+impl Default for AuthState {
+    fn default() -> Self {
+        Self {
+            id: gethostname::gethostname().to_string_lossy().into_owned(),
+            secret: None,
+            peer_identity: None,
+        }
+    }
+}
+
+/// The proof `prover` gives, over the transcript in a fixed order.
+fn proof_mac(secret: &[u8], prover: Role, client: &Party, server: &Party) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(PROOF_CONTEXT);
+    mac.update(prover.label());
+    for party in [client, server] {
+        mac.update(&[party.id.len() as u8]);
+        mac.update(party.id.as_bytes());
+        mac.update(party.nonce);
+    }
+    mac
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    /// Our identity, sent to the peer during `authenticate`.
+    ///
+    /// Defaults to the host name.
+    pub fn id(&self) -> &str {
+        &self.auth.id
+    }
+
+    pub fn set_identity(&mut self, id: impl Into<String>) {
+        self.auth.id = id.into();
+    }
+
+    /// Sets the shared secret both sides must know.
+    pub fn set_credentials(&mut self, secret: &[u8]) {
+        self.auth.secret = Some(secret.to_vec());
+    }
+
+    /// The peer's identity once `authenticate` has succeeded.
+    pub fn peer_identity(&self) -> Option<&str> {
+        self.auth.peer_identity.as_deref()
+    }
+
+    /// Proves knowledge of the shared secret to the peer and checks its proof.
+    ///
+    /// Both ends must call this concurrently, each in its own `role`.
+    pub async fn authenticate(&mut self, role: Role) -> Result<(), HandshakeError> {
+        self.auth.peer_identity = None;
+        let secret = self.auth.secret.clone().ok_or(HandshakeError::MissingCredentials)?;
+        if self.auth.id.len() > MAX_IDENTITY_LEN {
+            return Err(HandshakeError::Malformed("identity too long".into()));
+        }
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let mut challenge = nonce.to_vec();
+        challenge.extend_from_slice(self.auth.id.as_bytes());
+        self.send_frame(FrameType::AuthChallenge, &challenge).await?;
+
+        let frame = self.expect_frame(FrameType::AuthChallenge).await?;
+        if frame.payload.len() < NONCE_LEN || frame.payload.len() > NONCE_LEN + MAX_IDENTITY_LEN {
+            return Err(HandshakeError::Malformed("bad challenge length".into()));
+        }
+        let (peer_nonce, peer_id) = frame.payload.split_at(NONCE_LEN);
+        let peer_id = String::from_utf8(peer_id.to_vec())
+            .map_err(|_| HandshakeError::Malformed("identity is not UTF-8".into()))?;
+        // A reflected challenge would let the peer replay our own proof
+        if peer_nonce == nonce || peer_id == self.auth.id {
+            return Err(HandshakeError::AuthenticationFailed);
+        }
+
+        let ours = Party { id: &self.auth.id, nonce: &nonce };
+        let theirs = Party { id: &peer_id, nonce: peer_nonce };
+        let (client, server) = match role {
+            Role::Client => (&ours, &theirs),
+            Role::Server => (&theirs, &ours),
+        };
+        let proof = proof_mac(&secret, role, client, server).finalize().into_bytes();
+        let expected = proof_mac(&secret, role.peer(), client, server);
+        self.send_frame(FrameType::AuthProof, &proof).await?;
+
+        let frame = self.expect_frame(FrameType::AuthProof).await?;
+        expected.verify_slice(&frame.payload).map_err(|_| HandshakeError::AuthenticationFailed)?;
+
+        self.auth.peer_identity = Some(peer_id);
+        Ok(())
+    }
+
+    async fn expect_frame(&mut self, expected: FrameType) -> Result<Frame, HandshakeError> {
+        let frame = self.read_frame().await?;
+        if frame.kind != expected {
+            return Err(HandshakeError::UnexpectedFrame { expected, got: frame.kind });
+        }
+        Ok(frame)
+    }
+}
@@ -0,0 +1,186 @@
+//! Joining a Barrier server as one of its screens.
+//!
+//! `BarrierClient` answers the server's hello, screen info queries and
+//! keepalives itself and hands everything else over as `Event`s:
+//!
+//! - `CINN` becomes `Event::ScreenSwitch` to our own name followed by the
+//!   entry position, and `COUT` a switch to the empty name, since Barrier
+//!   does not say which screen became active.
+//! - Absolute and relative motion, buttons and keys map one to one. Keys
+//!   are looked up from the Barrier key id as typed on a US layout (see
+//!   `key_code`), or else from the button if the server sent an X keycode.
+//!   Keys we find neither way are dropped. The id also gives the keysym,
+//!   and the text for printable characters.
+//! - Wheel motion becomes `Event::Scroll`, in clicks of 120 units.
+//!
+//! The server is considered gone after `KEEPALIVES_UNTIL_DEATH` keepalive
+//! intervals without a message.
+
+use super::{key_code, Dialect, key_text, keysym, mouse_button, BarrierConnection, Message, KEEPALIVES_UNTIL_DEATH, KEEPALIVE_INTERVAL, PROTOCOL_MAJOR, PROTOCOL_MINOR};
+use crate::event::Event;
+use crate::keycode::KeyCode;
+use crate::network::handshake::{HandshakeError, ScreenInfo};
+use crate::network::{NetworkError, Result};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// One wheel notch in Barrier's units.
+const WHEEL_STEP: f64 = 120.0;
+
+pub struct BarrierClient {
+    conn: BarrierConnection,
+    name: String,
+    screen: ScreenInfo,
+    cursor: (i16, i16),
+    dialect: Dialect,
+    server_version: (u16, u16),
+    pending: VecDeque<Event>,
+    timeout: Duration,
+}
+
+impl BarrierClient {
+    /// Answers the server's hello on `stream` as the screen `name`.
+    pub async fn connect(stream: TcpStream, name: impl Into<String>, screen: ScreenInfo) -> Result<Self> {
+        Self::handshake(BarrierConnection::new(stream), name.into(), screen).await
+    }
+
+    /// Like `connect`, over an existing message stream.
+    pub async fn handshake(mut conn: BarrierConnection, name: String, screen: ScreenInfo) -> Result<Self> {
+        let (dialect, server_version) = match conn.receive().await? {
+            Message::Hello { dialect, major, minor } if major == PROTOCOL_MAJOR => (dialect, (major, minor)),
+            Message::Hello { major, minor, .. } => {
+                return Err(rejected(format!(
+                    "server speaks Barrier protocol {}.{}, we speak {}.x",
+                    major, minor, PROTOCOL_MAJOR
+                )));
+            }
+            other => return Err(HandshakeError::Malformed(format!("expected a Barrier hello, got {:?}", other)).into()),
+        };
+        // Answer in the server's own words, or Synergy 1 hangs up
+        conn.send(&Message::HelloBack { dialect, major: PROTOCOL_MAJOR, minor: PROTOCOL_MINOR, name: name.clone() }).await?;
+        Ok(Self {
+            conn,
+            name,
+            screen,
+            cursor: (screen.width as i16 / 2, screen.height as i16 / 2),
+            dialect,
+            server_version,
+            pending: VecDeque::new(),
+            timeout: KEEPALIVE_INTERVAL * KEEPALIVES_UNTIL_DEATH,
+        })
+    }
+
+    /// Whether the server greeted us as Barrier or as Synergy 1.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// The protocol version the server announced.
+    pub fn server_version(&self) -> (u16, u16) {
+        self.server_version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Waits for the next input event, answering protocol messages on the
+    /// way. Fails once the server says goodbye, refuses us or goes quiet.
+    ///
+    /// Not cancel safe: a reply may be cut short.
+    pub async fn receive_event(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            let message = tokio::time::timeout(self.timeout, self.conn.receive())
+                .await
+                .map_err(|_| NetworkError::Timeout(self.timeout))??;
+            self.handle(message).await?;
+        }
+    }
+
+    async fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::QueryInfo => {
+                let info = Message::Info {
+                    x: 0,
+                    y: 0,
+                    width: self.screen.width as i16,
+                    height: self.screen.height as i16,
+                    warp: 0,
+                    cursor_x: self.cursor.0,
+                    cursor_y: self.cursor.1,
+                };
+                self.conn.send(&info).await?;
+            }
+            Message::KeepAlive => self.conn.send(&Message::KeepAlive).await?,
+            Message::Enter { x, y, .. } => {
+                self.cursor = (x, y);
+                self.pending.push_back(Event::ScreenSwitch { to_screen: self.name.clone() });
+                self.pending.push_back(Event::MouseMove { x: x.into(), y: y.into() });
+            }
+            Message::Leave => self.pending.push_back(Event::ScreenSwitch { to_screen: String::new() }),
+            Message::MouseMove { x, y } => {
+                self.cursor = (x, y);
+                self.pending.push_back(Event::MouseMove { x: x.into(), y: y.into() });
+            }
+            Message::MouseMoveRelative { dx, dy } => {
+                self.cursor = (self.cursor.0.saturating_add(dx), self.cursor.1.saturating_add(dy));
+                self.pending.push_back(Event::MouseMoveRelative { dx: dx.into(), dy: dy.into() });
+            }
+            Message::MouseDown { button } | Message::MouseUp { button } => {
+                if let Some(button) = mouse_button(button) {
+                    let pressed = matches!(message, Message::MouseDown { .. });
+                    self.pending.push_back(Event::MouseButton { button, pressed });
+                }
+            }
+            Message::Wheel { dx, dy } => {
+                // Barrier scrolls up for positive `dy`
+                if let Some(scroll) = Event::new_scroll(dx as f64 / WHEEL_STEP, -(dy as f64) / WHEEL_STEP) {
+                    self.pending.push_back(scroll);
+                }
+            }
+            Message::KeyDown { id, button, .. } => {
+                if let Some(code) = keyboard_key(id, button) {
+                    self.pending.push_back(Event::KeyPress { code, keysym: keysym(id), text: key_text(id) });
+                }
+            }
+            Message::KeyRepeat { id, count, button, .. } => {
+                if let Some(code) = keyboard_key(id, button) {
+                    for _ in 0..count {
+                        self.pending.push_back(Event::KeyPress { code, keysym: keysym(id), text: key_text(id) });
+                    }
+                }
+            }
+            Message::KeyUp { id, button, .. } => {
+                if let Some(code) = keyboard_key(id, button) {
+                    self.pending.push_back(Event::KeyRelease { code, keysym: keysym(id) });
+                }
+            }
+            Message::Close => return Err(NetworkError::Connection("Barrier server closed the connection".to_string())),
+            Message::Incompatible { major, minor } => {
+                return Err(rejected(format!("server needs protocol version {}.{}", major, minor)));
+            }
+            Message::Busy => return Err(rejected(format!("a screen named {} is already connected", self.name))),
+            Message::UnknownClient => return Err(rejected(format!("server has no screen named {}", self.name))),
+            Message::Bad => return Err(rejected("server reported a protocol error".to_string())),
+            Message::Hello { .. } | Message::HelloBack { .. } | Message::Info { .. } => {
+                return Err(HandshakeError::Malformed(format!("unexpected {:?}", message)).into());
+            }
+            // Options, clipboard and screen saver messages are not supported
+            Message::InfoAck | Message::NoOp | Message::ResetOptions | Message::SetOptions(_) | Message::Other { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+fn rejected(reason: String) -> NetworkError {
+    HandshakeError::Rejected(reason).into()
+}
+
+/// The key for a key message: from its id, or its button as an X keycode.
+fn keyboard_key(id: u16, button: u16) -> Option<KeyCode> {
+    key_code(id).or_else(|| button.checked_sub(8).and_then(KeyCode::from_evdev))
+}
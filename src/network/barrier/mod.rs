@@ -0,0 +1,538 @@
+//! The Barrier wire protocol, also spoken by Synergy 1 and Input Leap.
+//!
+//! Every message is a 32-bit big-endian length followed by that many
+//! bytes. Apart from the opening hello, each starts with a four letter
+//! code (`DMMV`, `CALV`, ...) and packs its fields as big-endian integers
+//! of one, two or four bytes; strings are a 32-bit length and the bytes.
+//!
+//! The server speaks first with `Barrier` (`Synergy` for Synergy 1) and its
+//! protocol version, and the client answers with the same word, its own
+//! version and screen name. The server then
+//! asks for the screen size (`QINF`, answered with `DINF`), sends `CALV`
+//! keepalives that the client echoes, and streams input while the client's
+//! screen is active, between `CINN` (enter) and `COUT` (leave).
+//!
+//! `BarrierConnection` reads and writes `Message`s; `client` turns a
+//! connection into a stream of `Event`s and `server` does the reverse.
+
+pub mod client;
+pub mod server;
+
+use super::frame::DEFAULT_MAX_FRAME_SIZE;
+use super::handshake::HandshakeError;
+use super::{BoxedReader, BoxedWriter, NetworkError, Result};
+use crate::event::MouseButton;
+use crate::keycode::KeyCode;
+use bytes::{Buf, BufMut, BytesMut};
+use std::num::NonZeroU8;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const PROTOCOL_MAJOR: u16 = 1;
+pub const PROTOCOL_MINOR: u16 = 6;
+/// The port Barrier servers listen on by default.
+pub const DEFAULT_PORT: u16 = 24800;
+/// How often a Barrier server sends `CALV`.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(3);
+/// Keepalives that may go missing before the peer is considered gone.
+pub const KEEPALIVES_UNTIL_DEATH: u32 = 3;
+
+/// The word that opens the hellos, which is all that tells the programs
+/// apart; the protocol is otherwise the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// Barrier and Input Leap.
+    #[default]
+    Barrier,
+    /// Synergy 1, which only answers a hello that says `Synergy`.
+    Synergy,
+}
+
+impl Dialect {
+    const ALL: [Dialect; 2] = [Dialect::Barrier, Dialect::Synergy];
+
+    fn hello(self) -> &'static [u8] {
+        match self {
+            Dialect::Barrier => b"Barrier",
+            Dialect::Synergy => b"Synergy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Server greeting with its protocol version.
+    Hello { dialect: Dialect, major: u16, minor: u16 },
+    /// Client reply with its version and screen name.
+    HelloBack { dialect: Dialect, major: u16, minor: u16, name: String },
+    /// `QINF`: the server wants the client's screen info.
+    QueryInfo,
+    /// `DINF`: screen position and size, and where the cursor is.
+    Info { x: i16, y: i16, width: i16, height: i16, warp: i16, cursor_x: i16, cursor_y: i16 },
+    /// `CIAK`: the server took the screen info.
+    InfoAck,
+    /// `CALV`
+    KeepAlive,
+    /// `CNOP`
+    NoOp,
+    /// `CINN`: the cursor entered the client screen at `x`, `y`.
+    Enter { x: i16, y: i16, seq: u32, modifiers: u16 },
+    /// `COUT`: the cursor left the client screen.
+    Leave,
+    /// `CBYE`
+    Close,
+    /// `DKDN`: `id` is a Barrier key id, `button` the server's scan code.
+    KeyDown { id: u16, modifiers: u16, button: u16 },
+    /// `DKRP`
+    KeyRepeat { id: u16, modifiers: u16, count: u16, button: u16 },
+    /// `DKUP`
+    KeyUp { id: u16, modifiers: u16, button: u16 },
+    /// `DMDN`
+    MouseDown { button: u8 },
+    /// `DMUP`
+    MouseUp { button: u8 },
+    /// `DMMV`: absolute position.
+    MouseMove { x: i16, y: i16 },
+    /// `DMRM`: relative motion.
+    MouseMoveRelative { dx: i16, dy: i16 },
+    /// `DMWM`: wheel motion, 120 per notch; positive `dy` scrolls up.
+    Wheel { dx: i16, dy: i16 },
+    /// `CROP`: reset options to their defaults.
+    ResetOptions,
+    /// `DSOP`: option id and value pairs, flattened.
+    SetOptions(Vec<u32>),
+    /// `EICV`: the server does not speak our protocol version.
+    Incompatible { major: u16, minor: u16 },
+    /// `EBSY`: a client with our name is already connected.
+    Busy,
+    /// `EUNK`: the server has no screen with our name.
+    UnknownClient,
+    /// `EBAD`: the server could not make sense of something we sent.
+    Bad,
+    /// A message we do not handle, such as clipboard data.
+    Other { code: [u8; 4], body: Vec<u8> },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Writer::default();
+        match self {
+            Message::Hello { dialect, major, minor } => out.raw(dialect.hello()).u16(*major).u16(*minor),
+            Message::HelloBack { dialect, major, minor, name } => {
+                out.raw(dialect.hello()).u16(*major).u16(*minor).string(name)
+            }
+            Message::QueryInfo => out.raw(b"QINF"),
+            Message::Info { x, y, width, height, warp, cursor_x, cursor_y } => out
+                .raw(b"DINF")
+                .i16(*x).i16(*y).i16(*width).i16(*height).i16(*warp).i16(*cursor_x).i16(*cursor_y),
+            Message::InfoAck => out.raw(b"CIAK"),
+            Message::KeepAlive => out.raw(b"CALV"),
+            Message::NoOp => out.raw(b"CNOP"),
+            Message::Enter { x, y, seq, modifiers } => out.raw(b"CINN").i16(*x).i16(*y).u32(*seq).u16(*modifiers),
+            Message::Leave => out.raw(b"COUT"),
+            Message::Close => out.raw(b"CBYE"),
+            Message::KeyDown { id, modifiers, button } => out.raw(b"DKDN").u16(*id).u16(*modifiers).u16(*button),
+            Message::KeyRepeat { id, modifiers, count, button } => {
+                out.raw(b"DKRP").u16(*id).u16(*modifiers).u16(*count).u16(*button)
+            }
+            Message::KeyUp { id, modifiers, button } => out.raw(b"DKUP").u16(*id).u16(*modifiers).u16(*button),
+            Message::MouseDown { button } => out.raw(b"DMDN").u8(*button),
+            Message::MouseUp { button } => out.raw(b"DMUP").u8(*button),
+            Message::MouseMove { x, y } => out.raw(b"DMMV").i16(*x).i16(*y),
+            Message::MouseMoveRelative { dx, dy } => out.raw(b"DMRM").i16(*dx).i16(*dy),
+            Message::Wheel { dx, dy } => out.raw(b"DMWM").i16(*dx).i16(*dy),
+            Message::ResetOptions => out.raw(b"CROP"),
+            Message::SetOptions(options) => {
+                let out = out.raw(b"DSOP").u32(options.len() as u32);
+                options.iter().fold(out, |out, option| out.u32(*option))
+            }
+            Message::Incompatible { major, minor } => out.raw(b"EICV").u16(*major).u16(*minor),
+            Message::Busy => out.raw(b"EBSY"),
+            Message::UnknownClient => out.raw(b"EUNK"),
+            Message::Bad => out.raw(b"EBAD"),
+            Message::Other { code, body } => out.raw(code).raw(body),
+        };
+        out.0
+    }
+
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        if let Some(dialect) = Dialect::ALL.into_iter().find(|dialect| body.starts_with(dialect.hello())) {
+            let mut fields = Reader(&body[dialect.hello().len()..]);
+            let (major, minor) = (fields.u16()?, fields.u16()?);
+            return if fields.0.is_empty() {
+                Ok(Message::Hello { dialect, major, minor })
+            } else {
+                Ok(Message::HelloBack { dialect, major, minor, name: fields.string()? })
+            };
+        }
+        if body.len() < 4 {
+            return Err(malformed(format!("{} byte message", body.len())));
+        }
+        let code: [u8; 4] = body[..4].try_into().unwrap();
+        let mut fields = Reader(&body[4..]);
+        let message = match &code {
+            b"QINF" => Message::QueryInfo,
+            b"DINF" => Message::Info {
+                x: fields.i16()?,
+                y: fields.i16()?,
+                width: fields.i16()?,
+                height: fields.i16()?,
+                warp: fields.i16()?,
+                cursor_x: fields.i16()?,
+                cursor_y: fields.i16()?,
+            },
+            b"CIAK" => Message::InfoAck,
+            b"CALV" => Message::KeepAlive,
+            b"CNOP" => Message::NoOp,
+            b"CINN" => Message::Enter { x: fields.i16()?, y: fields.i16()?, seq: fields.u32()?, modifiers: fields.u16()? },
+            b"COUT" => Message::Leave,
+            b"CBYE" => Message::Close,
+            b"DKDN" => Message::KeyDown { id: fields.u16()?, modifiers: fields.u16()?, button: fields.u16()? },
+            b"DKRP" => Message::KeyRepeat {
+                id: fields.u16()?,
+                modifiers: fields.u16()?,
+                count: fields.u16()?,
+                button: fields.u16()?,
+            },
+            b"DKUP" => Message::KeyUp { id: fields.u16()?, modifiers: fields.u16()?, button: fields.u16()? },
+            b"DMDN" => Message::MouseDown { button: fields.u8()? },
+            b"DMUP" => Message::MouseUp { button: fields.u8()? },
+            b"DMMV" => Message::MouseMove { x: fields.i16()?, y: fields.i16()? },
+            b"DMRM" => Message::MouseMoveRelative { dx: fields.i16()?, dy: fields.i16()? },
+            b"DMWM" => Message::Wheel { dx: fields.i16()?, dy: fields.i16()? },
+            b"CROP" => Message::ResetOptions,
+            b"DSOP" => {
+                let count = fields.u32()?;
+                Message::SetOptions((0..count).map(|_| fields.u32()).collect::<Result<_>>()?)
+            }
+            b"EICV" => Message::Incompatible { major: fields.u16()?, minor: fields.u16()? },
+            b"EBSY" => Message::Busy,
+            b"EUNK" => Message::UnknownClient,
+            b"EBAD" => Message::Bad,
+            _ => return Ok(Message::Other { code, body: body[4..].to_vec() }),
+        };
+        Ok(message)
+    }
+}
+
+/// A stream of Barrier messages.
+pub struct BarrierConnection {
+    reader: BoxedReader,
+    writer: BoxedWriter,
+    buf: BytesMut,
+    max_message_size: usize,
+}
+
+impl BarrierConnection {
+    pub fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self::from_halves(Box::new(reader), Box::new(writer))
+    }
+
+    pub(crate) fn from_halves(reader: BoxedReader, writer: BoxedWriter) -> Self {
+        Self {
+            reader,
+            writer,
+            buf: BytesMut::with_capacity(8 * 1024),
+            max_message_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<()> {
+        let body = message.encode();
+        if body.len() > self.max_message_size {
+            return Err(NetworkError::FrameTooLarge { size: body.len(), max: self.max_message_size });
+        }
+        let mut data = Vec::with_capacity(4 + body.len());
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(&body);
+        self.writer.write_all(&data).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Waits for the next message. Cancel safe.
+    pub async fn receive(&mut self) -> Result<Message> {
+        loop {
+            if self.buf.len() >= 4 {
+                let len = u32::from_be_bytes(self.buf[..4].try_into().unwrap()) as usize;
+                if len > self.max_message_size {
+                    return Err(NetworkError::FrameTooLarge { size: len, max: self.max_message_size });
+                }
+                if self.buf.len() >= 4 + len {
+                    self.buf.advance(4);
+                    let body = self.buf.split_to(len);
+                    return Message::decode(&body);
+                }
+            }
+            if self.reader.read_buf(&mut self.buf).await? == 0 {
+                return Err(if self.buf.is_empty() {
+                    NetworkError::Connection("Connection closed by peer".to_string())
+                } else {
+                    NetworkError::TruncatedFrame { expected: 4, received: self.buf.len() }
+                });
+            }
+        }
+    }
+}
+
+/// Modifier bits in `CINN` and key messages.
+pub mod modifiers {
+    pub const SHIFT: u16 = 0x0001;
+    pub const CONTROL: u16 = 0x0002;
+    pub const ALT: u16 = 0x0004;
+    pub const META: u16 = 0x0008;
+    pub const SUPER: u16 = 0x0010;
+    pub const ALT_GR: u16 = 0x0020;
+    pub const CAPS_LOCK: u16 = 0x1000;
+    pub const NUM_LOCK: u16 = 0x2000;
+    pub const SCROLL_LOCK: u16 = 0x4000;
+}
+
+/// Barrier key ids for keys without a character, which mirror the X keysyms
+/// with 0xEF00 in place of 0xFF00, by keysym name.
+const SPECIAL_KEYS: &[(u16, &str)] = &[
+    (0xEF08, "BackSpace"),
+    (0xEF09, "Tab"),
+    (0xEF0D, "Return"),
+    (0xEF13, "Pause"),
+    (0xEF14, "Scroll_Lock"),
+    (0xEF1B, "Escape"),
+    (0xEF50, "Home"),
+    (0xEF51, "Left"),
+    (0xEF52, "Up"),
+    (0xEF53, "Right"),
+    (0xEF54, "Down"),
+    (0xEF55, "Prior"),
+    (0xEF56, "Next"),
+    (0xEF57, "End"),
+    (0xEF61, "Print"),
+    (0xEF63, "Insert"),
+    (0xEF67, "Menu"),
+    (0xEF7F, "Num_Lock"),
+    (0xEF8D, "KP_Enter"),
+    (0xEFE1, "Shift_L"),
+    (0xEFE2, "Shift_R"),
+    (0xEFE3, "Control_L"),
+    (0xEFE4, "Control_R"),
+    (0xEFE5, "Caps_Lock"),
+    (0xEFE7, "Meta_L"),
+    (0xEFE8, "Meta_R"),
+    (0xEFE9, "Alt_L"),
+    (0xEFEA, "Alt_R"),
+    (0xEFEB, "Super_L"),
+    (0xEFEC, "Super_R"),
+    (0xEFFF, "Delete"),
+    (0x0020, "space"),
+];
+
+/// First Barrier key id of F1 to F24.
+const KEY_F1: u16 = 0xEFBE;
+
+/// XKB key names on a US layout and the keysym name or character they
+/// produce, for events captured from X11.
+const XKB_KEYS: &[(&str, &str)] = &[
+    ("TLDE", "`"), ("AE01", "1"), ("AE02", "2"), ("AE03", "3"), ("AE04", "4"), ("AE05", "5"),
+    ("AE06", "6"), ("AE07", "7"), ("AE08", "8"), ("AE09", "9"), ("AE10", "0"), ("AE11", "-"),
+    ("AE12", "="), ("AD01", "q"), ("AD02", "w"), ("AD03", "e"), ("AD04", "r"), ("AD05", "t"),
+    ("AD06", "y"), ("AD07", "u"), ("AD08", "i"), ("AD09", "o"), ("AD10", "p"), ("AD11", "["),
+    ("AD12", "]"), ("BKSL", "\\"), ("AC01", "a"), ("AC02", "s"), ("AC03", "d"), ("AC04", "f"),
+    ("AC05", "g"), ("AC06", "h"), ("AC07", "j"), ("AC08", "k"), ("AC09", "l"), ("AC10", ";"),
+    ("AC11", "'"), ("AB01", "z"), ("AB02", "x"), ("AB03", "c"), ("AB04", "v"), ("AB05", "b"),
+    ("AB06", "n"), ("AB07", "m"), ("AB08", ","), ("AB09", "."), ("AB10", "/"),
+    ("SPCE", "space"), ("RTRN", "Return"), ("ESC", "Escape"), ("BKSP", "BackSpace"),
+    ("TAB", "Tab"), ("CAPS", "Caps_Lock"), ("LFSH", "Shift_L"), ("RTSH", "Shift_R"),
+    ("LCTL", "Control_L"), ("RCTL", "Control_R"), ("LALT", "Alt_L"), ("RALT", "Alt_R"),
+    ("LWIN", "Super_L"), ("RWIN", "Super_R"), ("COMP", "Menu"), ("UP", "Up"), ("DOWN", "Down"),
+    ("LEFT", "Left"), ("RGHT", "Right"), ("HOME", "Home"), ("END", "End"), ("PGUP", "Prior"),
+    ("PGDN", "Next"), ("INS", "Insert"), ("DELE", "Delete"), ("PRSC", "Print"),
+    ("SCLK", "Scroll_Lock"), ("PAUS", "Pause"), ("NMLK", "Num_Lock"), ("KPEN", "KP_Enter"),
+    ("FK01", "F1"), ("FK02", "F2"), ("FK03", "F3"), ("FK04", "F4"), ("FK05", "F5"), ("FK06", "F6"),
+    ("FK07", "F7"), ("FK08", "F8"), ("FK09", "F9"), ("FK10", "F10"), ("FK11", "F11"), ("FK12", "F12"),
+    ("FK13", "F13"), ("FK14", "F14"), ("FK15", "F15"), ("FK16", "F16"), ("FK17", "F17"), ("FK18", "F18"),
+    ("FK19", "F19"), ("FK20", "F20"), ("FK21", "F21"), ("FK22", "F22"), ("FK23", "F23"), ("FK24", "F24"),
+];
+
+/// Characters typed with shift on a US layout and the key's own character.
+const SHIFTED_KEYS: &[(&str, &str)] = &[
+    ("~", "`"), ("!", "1"), ("@", "2"), ("#", "3"), ("$", "4"), ("%", "5"), ("^", "6"), ("&", "7"),
+    ("*", "8"), ("(", "9"), (")", "0"), ("_", "-"), ("+", "="), ("{", "["), ("}", "]"), ("|", "\\"),
+    (":", ";"), ("\"", "'"), ("<", ","), (">", "."), ("?", "/"),
+];
+
+/// A name for a Barrier key id. Printable keys are their character and
+/// other keys their X keysym name.
+pub fn key_name(id: u16) -> String {
+    if let Some((_, name)) = SPECIAL_KEYS.iter().find(|(key, _)| *key == id) {
+        return name.to_string();
+    }
+    if (KEY_F1..KEY_F1 + 24).contains(&id) {
+        return format!("F{}", id - KEY_F1 + 1);
+    }
+    match key_char(id) {
+        Some(c) => c.to_string(),
+        None => format!("0x{:04X}", id),
+    }
+}
+
+/// The X keysym for a Barrier key id, or 0 if it has none.
+pub fn keysym(id: u16) -> u32 {
+    match id {
+        // Barrier's function keys are X's, moved down from 0xFFxx
+        0xEF00..=0xEFFF => 0xFF00 | u32::from(id & 0xFF),
+        0x20..=0x7E | 0xA0..=0xFF => id.into(),
+        _ if key_char(id).is_some() => 0x0100_0000 | u32::from(id),
+        _ => 0,
+    }
+}
+
+/// The Barrier key id for an X keysym, the other way round from `keysym`.
+/// `None` for keysyms that are neither a character nor a function key, and
+/// for legacy non-Latin keysyms.
+pub fn keysym_key_id(keysym: u32) -> Option<u16> {
+    match keysym {
+        0xFF00..=0xFFFF => Some(0xEF00 | (keysym & 0xFF) as u16),
+        0x20..=0x7E | 0xA0..=0xFF => Some(keysym as u16),
+        0x0100_0000..=0x0100_FFFF => Some((keysym & 0xFFFF) as u16).filter(|id| key_char(*id).is_some()),
+        _ => None,
+    }
+}
+
+/// The text a Barrier key id types: its character if it is a printable one.
+pub fn key_text(id: u16) -> String {
+    key_char(id).map(String::from).unwrap_or_default()
+}
+
+fn key_char(id: u16) -> Option<char> {
+    char::from_u32(id.into()).filter(|c| !c.is_control() && id < 0xE000)
+}
+
+/// The Barrier key id for a key name: anything `key_name` returns, or an
+/// XKB key name as captured from X11 (assuming a US layout).
+pub fn key_id(name: &str) -> Option<u16> {
+    let name = XKB_KEYS.iter().find(|(key, _)| *key == name).map_or(name, |(_, keysym)| keysym);
+    if let Some((id, _)) = SPECIAL_KEYS.iter().find(|(_, key)| *key == name) {
+        return Some(*id);
+    }
+    if let Some(number) = name.strip_prefix('F').and_then(|number| number.parse::<u16>().ok()) {
+        return (1..=24).contains(&number).then(|| KEY_F1 + number - 1);
+    }
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => u16::try_from(u32::from(c)).ok(),
+        _ => None,
+    }
+}
+
+/// The key that types a Barrier key id on a US layout, if we have it.
+pub fn key_code(id: u16) -> Option<KeyCode> {
+    let mut name = key_name(id);
+    match SHIFTED_KEYS.iter().find(|(shifted, _)| *shifted == name) {
+        Some((_, key)) => name = key.to_string(),
+        None if name.len() == 1 => name.make_ascii_lowercase(),
+        None => {}
+    }
+    XKB_KEYS.iter().find(|(_, keysym)| *keysym == name).and_then(|(key, _)| KeyCode::from_xkb_name(key))
+}
+
+/// Barrier's number for a button: left, middle and right are 1 to 3 and
+/// the side buttons follow, where X has the wheel.
+pub fn button_id(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+        MouseButton::Back => 4,
+        MouseButton::Forward => 5,
+        MouseButton::Extra(extra) => 5u8.saturating_add(extra.get()),
+    }
+}
+
+/// The button for a Barrier button number; `None` for 0.
+pub fn mouse_button(id: u8) -> Option<MouseButton> {
+    match id {
+        0 => None,
+        1 => Some(MouseButton::Left),
+        2 => Some(MouseButton::Middle),
+        3 => Some(MouseButton::Right),
+        4 => Some(MouseButton::Back),
+        5 => Some(MouseButton::Forward),
+        extra => NonZeroU8::new(extra - 5).map(MouseButton::Extra),
+    }
+}
+
+fn malformed(what: String) -> NetworkError {
+    NetworkError::Handshake(HandshakeError::Malformed(format!("Barrier message: {}", what)))
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.put_u8(value);
+        self
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.put_u16(value);
+        self
+    }
+
+    fn i16(&mut self, value: i16) -> &mut Self {
+        self.0.put_i16(value);
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.put_u32(value);
+        self
+    }
+
+    fn string(&mut self, value: &str) -> &mut Self {
+        self.u32(value.len() as u32).raw(value.as_bytes())
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.0.len() < len {
+            return Err(malformed(format!("{} bytes missing", len - self.0.len())));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| malformed("name is not UTF-8".to_string()))
+    }
+}
@@ -0,0 +1,341 @@
+//! Serving stock Barrier, Synergy 1 and Input Leap clients.
+//!
+//! Barrier and Input Leap clients only answer a `Barrier` hello and Synergy
+//! 1 clients a `Synergy` one, so a listener serves one `Dialect` or the other.
+//!
+//! `BarrierScreen` greets a client, asks for its screen info and then turns
+//! the `Event`s the hub routes to it into Barrier messages:
+//!
+//! - `Event::ScreenSwitch` to the client's name becomes `CINN` at the last
+//!   pointer position, and a switch anywhere else `COUT`. The hub tells the
+//!   previously active client where the pointer went, which is how a Barrier
+//!   client learns to hide its cursor.
+//! - Positions are clamped to the client's screen; deltas pass through.
+//! - Buttons map to Barrier's numbers (see `button_id`), and `Event::Scroll`
+//!   becomes `DMWM` with its fractions kept.
+//! - Keys carry their X keycode as the button and a Barrier key id for
+//!   the keysym they typed on our layout (see `keysym_key_id`), or for what
+//!   they type on a US layout if the keysym has no id (see `key_id`). The
+//!   modifier state is tracked from the keys we sent. Keys without an id
+//!   are dropped.
+//!
+//! Barrier clients always get positions, never deltas, and are dropped
+//! after `KEEPALIVES_UNTIL_DEATH` keepalive intervals of silence.
+
+use super::{
+    button_id, key_id, keysym_key_id, Dialect, modifiers, BarrierConnection, Message, KEEPALIVES_UNTIL_DEATH, KEEPALIVE_INTERVAL, PROTOCOL_MAJOR,
+    PROTOCOL_MINOR,
+};
+use crate::event::Event;
+use crate::keycode::KeyCode;
+use crate::network::handshake::{HandshakeError, ScreenInfo};
+use crate::network::hub::Hub;
+use crate::network::{NetworkError, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+
+/// One wheel notch in Barrier's units.
+const WHEEL_STEP: i16 = 120;
+
+/// The X keycode for a key, which is what Barrier clients on X11 expect as
+/// the button; 0 if we have none.
+fn x_keycode(code: KeyCode) -> u16 {
+    code.to_evdev().map_or(0, |evdev| evdev + 8)
+}
+
+/// The Barrier key id for a captured key: the keysym it produced, so other
+/// layouts type what they say, or else what the key types on a US layout.
+fn barrier_key_id(code: KeyCode, keysym: u32) -> Option<u16> {
+    keysym_key_id(keysym).or_else(|| code.xkb_name().and_then(key_id))
+}
+
+/// A Barrier client connected to us, as one of our screens.
+pub struct BarrierScreen {
+    conn: BarrierConnection,
+    name: String,
+    client_version: (u16, u16),
+    /// Position and size of the client's screen.
+    area: (i16, i16, i16, i16),
+    cursor: (i16, i16),
+    seq: u32,
+    modifiers: u16,
+    active: bool,
+    timeout: Duration,
+    last_heard: Instant,
+}
+
+impl BarrierScreen {
+    /// Greets the client on `stream` in `dialect` and waits for its name and
+    /// screen info.
+    pub async fn accept(stream: TcpStream, dialect: Dialect) -> Result<Self> {
+        Self::handshake(BarrierConnection::new(stream), dialect).await
+    }
+
+    /// Like `accept`, over an existing message stream.
+    pub async fn handshake(mut conn: BarrierConnection, dialect: Dialect) -> Result<Self> {
+        let timeout = KEEPALIVE_INTERVAL * KEEPALIVES_UNTIL_DEATH;
+        conn.send(&Message::Hello { dialect, major: PROTOCOL_MAJOR, minor: PROTOCOL_MINOR }).await?;
+        let (client_version, name) = match receive_within(&mut conn, timeout).await? {
+            Message::HelloBack { major, minor, name, .. } if major == PROTOCOL_MAJOR => ((major, minor), name),
+            Message::HelloBack { major, minor, .. } => {
+                conn.send(&Message::Incompatible { major: PROTOCOL_MAJOR, minor: PROTOCOL_MINOR }).await?;
+                return Err(HandshakeError::Rejected(format!(
+                    "client speaks Barrier protocol {}.{}, we speak {}.x",
+                    major, minor, PROTOCOL_MAJOR
+                ))
+                .into());
+            }
+            other => return Err(HandshakeError::Malformed(format!("expected a Barrier hello, got {:?}", other)).into()),
+        };
+        conn.send(&Message::QueryInfo).await?;
+        let (area, cursor) = match receive_within(&mut conn, timeout).await? {
+            Message::Info { x, y, width, height, cursor_x, cursor_y, .. } => ((x, y, width, height), (cursor_x, cursor_y)),
+            other => return Err(HandshakeError::Malformed(format!("expected screen info, got {:?}", other)).into()),
+        };
+        conn.send(&Message::InfoAck).await?;
+        conn.send(&Message::ResetOptions).await?;
+        Ok(Self {
+            conn,
+            name,
+            client_version,
+            area,
+            cursor,
+            seq: 0,
+            modifiers: 0,
+            active: false,
+            timeout,
+            last_heard: Instant::now(),
+        })
+    }
+
+    /// The screen name the client announced.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The protocol version the client announced.
+    pub fn client_version(&self) -> (u16, u16) {
+        self.client_version
+    }
+
+    /// The client's screen size, as last reported.
+    pub fn screen(&self) -> ScreenInfo {
+        ScreenInfo { width: self.area.2.max(0) as u16, height: self.area.3.max(0) as u16 }
+    }
+
+    /// Whether the pointer is on the client's screen.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The messages that carry `event` to the client, if any.
+    pub fn translate(&mut self, event: &Event) -> Vec<Message> {
+        let message = match event {
+            Event::ScreenSwitch { to_screen } if *to_screen == self.name => {
+                if self.active {
+                    return Vec::new();
+                }
+                self.active = true;
+                self.seq = self.seq.wrapping_add(1);
+                Message::Enter { x: self.cursor.0, y: self.cursor.1, seq: self.seq, modifiers: self.modifiers }
+            }
+            Event::ScreenSwitch { .. } => {
+                if !self.active {
+                    return Vec::new();
+                }
+                self.active = false;
+                Message::Leave
+            }
+            Event::MouseMove { x, y } => {
+                let (left, top, width, height) = self.area;
+                self.cursor = (clamp(*x, left, width), clamp(*y, top, height));
+                Message::MouseMove { x: self.cursor.0, y: self.cursor.1 }
+            }
+            Event::MouseMoveRelative { dx, dy } => {
+                let (dx, dy) = (saturate(*dx), saturate(*dy));
+                let (left, top, width, height) = self.area;
+                self.cursor = (
+                    clamp(self.cursor.0 as i32 + dx as i32, left, width),
+                    clamp(self.cursor.1 as i32 + dy as i32, top, height),
+                );
+                Message::MouseMoveRelative { dx, dy }
+            }
+            // Barrier scrolls up for positive `dy`
+            Event::Scroll { dx, dy } => match (notches(*dx), notches(-*dy)) {
+                (0, 0) => return Vec::new(),
+                (dx, dy) => Message::Wheel { dx, dy },
+            },
+            Event::MouseButton { button, pressed } => {
+                let button = button_id(*button);
+                if *pressed {
+                    Message::MouseDown { button }
+                } else {
+                    Message::MouseUp { button }
+                }
+            }
+            Event::KeyPress { code, keysym, .. } => {
+                let Some(id) = barrier_key_id(*code, *keysym) else {
+                    return Vec::new();
+                };
+                let message = Message::KeyDown { id, modifiers: self.modifiers, button: x_keycode(*code) };
+                self.modifiers = match modifier(id) {
+                    Modifier::Held(mask) => self.modifiers | mask,
+                    Modifier::Lock(mask) => self.modifiers ^ mask,
+                    Modifier::None => self.modifiers,
+                };
+                message
+            }
+            Event::KeyRelease { code, keysym } => {
+                let Some(id) = barrier_key_id(*code, *keysym) else {
+                    return Vec::new();
+                };
+                if let Modifier::Held(mask) = modifier(id) {
+                    self.modifiers &= !mask;
+                }
+                Message::KeyUp { id, modifiers: self.modifiers, button: x_keycode(*code) }
+            }
+            Event::Heartbeat | Event::Error(_) => return Vec::new(),
+        };
+        vec![message]
+    }
+
+    /// Sends `event` to the client, translated.
+    pub async fn send_event(&mut self, event: &Event) -> Result<()> {
+        for message in self.translate(event) {
+            self.conn.send(&message).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<()> {
+        self.conn.send(message).await
+    }
+
+    /// Reads the next message from the client, returning the reply it
+    /// needs, if any. Fails if the client refuses us or goes quiet.
+    ///
+    /// Cancel safe: nothing is written here.
+    pub async fn receive(&mut self) -> Result<Option<Message>> {
+        let message = tokio::time::timeout_at(self.last_heard + self.timeout, self.conn.receive())
+            .await
+            .map_err(|_| NetworkError::Timeout(self.timeout))??;
+        self.last_heard = Instant::now();
+        match message {
+            // The client resized its screen or warped its pointer
+            Message::Info { x, y, width, height, cursor_x, cursor_y, .. } => {
+                self.area = (x, y, width, height);
+                self.cursor = (cursor_x, cursor_y);
+                Ok(Some(Message::InfoAck))
+            }
+            Message::Bad => Err(HandshakeError::Rejected("client reported a protocol error".to_string()).into()),
+            Message::Close => Err(NetworkError::Connection("Barrier client said goodbye".to_string())),
+            // Keepalive echoes, and clipboard and screen saver messages,
+            // which are not supported
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Accepts Barrier clients, or Synergy 1 ones for `Dialect::Synergy`, on
+/// `listener` forever and serves them as screens of `hub`, alongside
+/// clients speaking our own protocol.
+pub async fn serve(hub: &Hub, listener: TcpListener, dialect: Dialect) -> Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            match BarrierScreen::accept(stream, dialect).await {
+                Ok(screen) => serve_client(&hub, screen, addr).await,
+                Err(e) => hub.reject(addr, e.to_string()),
+            }
+        });
+    }
+}
+
+/// Relays routed events to one Barrier client until it goes away.
+pub async fn serve_client(hub: &Hub, mut screen: BarrierScreen, addr: SocketAddr) {
+    // Barrier clients cannot prove who they are, so they never replace one
+    let mut registration = match hub.register(screen.name.clone(), addr, false) {
+        Ok(registration) => registration,
+        Err(e) => {
+            let _ = screen.send(&Message::Busy).await;
+            hub.reject(addr, e.to_string());
+            return;
+        }
+    };
+    let mut keepalive = tokio::time::interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    let reason = loop {
+        tokio::select! {
+            event = registration.recv() => match event {
+                Some(event) => {
+                    if let Err(e) = screen.send_event(&event).await {
+                        break e.to_string();
+                    }
+                }
+                None => {
+                    let _ = screen.send(&Message::Close).await;
+                    break registration.close_reason();
+                }
+            },
+            received = screen.receive() => match received {
+                Ok(Some(reply)) => {
+                    if let Err(e) = screen.send(&reply).await {
+                        break e.to_string();
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => break e.to_string(),
+            },
+            _ = keepalive.tick() => {
+                if let Err(e) = screen.send(&Message::KeepAlive).await {
+                    break e.to_string();
+                }
+            }
+        }
+    };
+    registration.leave(reason);
+}
+
+enum Modifier {
+    /// Down while the key is held.
+    Held(u16),
+    /// Toggled by each press.
+    Lock(u16),
+    None,
+}
+
+fn modifier(id: u16) -> Modifier {
+    match id {
+        0xEFE1 | 0xEFE2 => Modifier::Held(modifiers::SHIFT),
+        0xEFE3 | 0xEFE4 => Modifier::Held(modifiers::CONTROL),
+        0xEFE7 | 0xEFE8 => Modifier::Held(modifiers::META),
+        0xEFE9 | 0xEFEA => Modifier::Held(modifiers::ALT),
+        0xEFEB | 0xEFEC => Modifier::Held(modifiers::SUPER),
+        0xEFE5 => Modifier::Lock(modifiers::CAPS_LOCK),
+        0xEF7F => Modifier::Lock(modifiers::NUM_LOCK),
+        0xEF14 => Modifier::Lock(modifiers::SCROLL_LOCK),
+        _ => Modifier::None,
+    }
+}
+
+async fn receive_within(conn: &mut BarrierConnection, timeout: Duration) -> Result<Message> {
+    tokio::time::timeout(timeout, conn.receive()).await.map_err(|_| NetworkError::Timeout(timeout))?
+}
+
+/// `value` moved into the `size` pixels starting at `start`.
+fn clamp(value: i32, start: i16, size: i16) -> i16 {
+    let end = start as i32 + (size as i32 - 1).max(0);
+    value.clamp(start as i32, end) as i16
+}
+
+/// Wheel clicks in Barrier's units.
+fn notches(clicks: f64) -> i16 {
+    // Casting saturates, and NaN becomes 0
+    (clicks * WHEEL_STEP as f64).round() as i16
+}
+
+fn saturate(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
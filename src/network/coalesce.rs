@@ -0,0 +1,234 @@
+//! Sender-side motion coalescing.
+//!
+//! Only the newest pointer position matters, so when events pile up faster
+//! than the link drains them there is no point sending every intermediate
+//! one. `MotionCoalescer` queues outgoing events and replaces a pending
+//! motion event with a newer one instead of queueing both, or adds the
+//! deltas together for relative motion. It only merges
+//! with the motion event at the back of the queue: anything queued after a
+//! key or button event stays behind it, so motion is never reordered
+//! relative to keys and buttons.
+//!
+//! It also paces motion to the link. Given round-trip time samples, motion
+//! goes out at most once per `rtt_fraction` of the smoothed RTT (capped at
+//! `max_interval`); motion arriving in between merges into the pending
+//! event. Keys and buttons are never delayed, and flush any motion queued
+//! ahead of them.
+//!
+//! It queues `Event`s or `TimedEvent`s alike (see `Queued`). Merged motion
+//! takes on the timing of the newest event merged in.
+
+use crate::event::{Event, TimedEvent};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoalesceConfig {
+    /// Motion is sent at most once per this fraction of the smoothed RTT.
+    pub rtt_fraction: f64,
+    /// Upper bound on the pacing interval, however slow the link.
+    pub max_interval: Duration,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            rtt_fraction: 0.25,
+            max_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+/// What the coalescer can queue: an `Event`, or one wrapped with timing.
+pub trait Queued: Clone {
+    fn event(&self) -> &Event;
+    fn event_mut(&mut self) -> &mut Event;
+}
+
+impl Queued for Event {
+    fn event(&self) -> &Event {
+        self
+    }
+
+    fn event_mut(&mut self) -> &mut Event {
+        self
+    }
+}
+
+impl Queued for TimedEvent {
+    fn event(&self) -> &Event {
+        &self.event
+    }
+
+    fn event_mut(&mut self) -> &mut Event {
+        &mut self.event
+    }
+}
+
+#[derive(Debug)]
+pub struct MotionCoalescer<E = Event> {
+    config: CoalesceConfig,
+    queue: VecDeque<E>,
+    srtt: Option<Duration>,
+    last_motion: Option<Instant>,
+    merged: u64,
+}
+
+fn is_motion<E: Queued>(queued: &E) -> bool {
+    matches!(queued.event(), Event::MouseMove { .. } | Event::MouseMoveRelative { .. })
+}
+
+/// Folds `next` into `pending` if both are the same kind of motion.
+fn merge<E: Queued>(pending: &mut E, next: &E) -> bool {
+    let merged = match (pending.event(), next.event()) {
+        (Event::MouseMove { .. }, Event::MouseMove { .. }) => next.event().clone(),
+        (Event::MouseMoveRelative { dx, dy }, Event::MouseMoveRelative { dx: next_dx, dy: next_dy }) => {
+            Event::MouseMoveRelative { dx: dx.saturating_add(*next_dx), dy: dy.saturating_add(*next_dy) }
+        }
+        _ => return false,
+    };
+    *pending = next.clone();
+    *pending.event_mut() = merged;
+    true
+}
+
+impl<E: Queued> MotionCoalescer<E> {
+    pub fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            queue: VecDeque::new(),
+            srtt: None,
+            last_motion: None,
+            merged: 0,
+        }
+    }
+
+    /// Queues an event, merging it into pending motion where that is safe.
+    pub fn push(&mut self, event: E) {
+        if let Some(pending) = self.queue.back_mut() {
+            if merge(pending, &event) {
+                self.merged += 1;
+                return;
+            }
+        }
+        self.queue.push_back(event);
+    }
+
+    /// Takes the next event to send, whether or not it is due yet.
+    pub fn pop(&mut self) -> Option<E> {
+        let event = self.queue.pop_front()?;
+        if is_motion(&event) {
+            self.last_motion = Some(Instant::now());
+        }
+        Some(event)
+    }
+
+    /// When the front of the queue may be sent, or `None` if it is empty.
+    pub fn ready_at(&self) -> Option<Instant> {
+        let front = self.queue.front()?;
+        let now = Instant::now();
+        // Motion with something behind it has to go now to keep the order
+        if !is_motion(front) || self.queue.len() > 1 {
+            return Some(now);
+        }
+        match self.last_motion {
+            Some(last) => Some((last + self.motion_interval()).max(now)),
+            None => Some(now),
+        }
+    }
+
+    /// Discards the oldest queued motion event that can go without moving
+    /// the pointer under a key or button, to make room. It is folded into
+    /// a neighbouring motion event with nothing else in between: a later
+    /// absolute position overrides it, and a dropped delta is added to the
+    /// delta after it, or else to the motion before it. Returns whether
+    /// anything was dropped.
+    pub fn drop_oldest_motion(&mut self) -> bool {
+        (0..self.queue.len()).any(|index| is_motion(&self.queue[index]) && self.fold_away(index))
+    }
+
+    /// Folds the motion event at `index` into its neighbour, if it has one
+    /// it can be folded into.
+    fn fold_away(&mut self, index: usize) -> bool {
+        let next = self.queue.get(index + 1).map(|next| next.event());
+        match (self.queue[index].event(), next) {
+            (_, Some(Event::MouseMove { .. })) => {
+                self.queue.remove(index);
+                return true;
+            }
+            (Event::MouseMoveRelative { .. }, Some(Event::MouseMoveRelative { .. })) => {
+                let mut sum = self.queue.remove(index).expect("index is in range");
+                merge(&mut sum, &self.queue[index]);
+                self.queue[index] = sum;
+                return true;
+            }
+            _ => {}
+        }
+        if index == 0 || !is_motion(&self.queue[index - 1]) {
+            return false;
+        }
+        let dropped = self.queue.remove(index).expect("index is in range");
+        let earlier = &mut self.queue[index - 1];
+        match (earlier.event(), dropped.event()) {
+            (Event::MouseMove { x, y }, &Event::MouseMoveRelative { dx, dy }) => {
+                let moved = Event::MouseMove { x: x.saturating_add(dx), y: y.saturating_add(dy) };
+                *earlier = dropped;
+                *earlier.event_mut() = moved;
+            }
+            (Event::MouseMoveRelative { .. }, Event::MouseMoveRelative { .. }) => {
+                merge(earlier, &dropped);
+            }
+            // A position overrides whatever came before it
+            _ => *earlier = dropped,
+        }
+        true
+    }
+
+    /// Takes the next event if it is due.
+    pub fn pop_ready(&mut self) -> Option<E> {
+        match self.ready_at() {
+            Some(at) if at <= Instant::now() => self.pop(),
+            _ => None,
+        }
+    }
+
+    /// Feeds a round-trip time measurement into the pacing.
+    pub fn observe_rtt(&mut self, sample: Duration) {
+        // The same smoothing TCP uses (RFC 6298)
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => srtt.mul_f64(0.875) + sample.mul_f64(0.125),
+            None => sample,
+        });
+    }
+
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// Minimum spacing between motion events at the current RTT.
+    pub fn motion_interval(&self) -> Duration {
+        self.srtt
+            .map(|srtt| srtt.mul_f64(self.config.rtt_fraction).min(self.config.max_interval))
+            .unwrap_or(Duration::ZERO)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// How many motion events were merged away so far.
+    pub fn merged(&self) -> u64 {
+        self.merged
+    }
+}
+
+impl Default for MotionCoalescer {
+    fn default() -> Self {
+        Self::new(CoalesceConfig::default())
+    }
+}
@@ -0,0 +1,153 @@
+//! Payload encodings for events carried in `FrameType::Event` frames.
+//!
+//! JSON is always available and easy to read in a packet capture. The
+//! binary codecs are smaller and faster to encode and sit behind the
+//! `cbor`, `bincode` and `msgpack` cargo features.
+
+use super::{NetworkError, Result};
+use crate::event::Event;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub trait EventCodec: Send + Sync {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>>;
+    fn decode(&self, data: &[u8]) -> Result<Event>;
+}
+
+impl<C: EventCodec + ?Sized> EventCodec for Box<C> {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        (**self).encode(event)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Event> {
+        (**self).decode(data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl EventCodec for JsonCodec {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        serde_json::to_vec(event).map_err(Into::into)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Event> {
+        serde_json::from_slice(data).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl EventCodec for CborCodec {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ciborium::into_writer(event, &mut data)
+            .map_err(|e| NetworkError::Codec(format!("CBOR: {}", e)))?;
+        Ok(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Event> {
+        ciborium::from_reader(data).map_err(|e| NetworkError::Codec(format!("CBOR: {}", e)))
+    }
+}
+
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl EventCodec for BincodeCodec {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        bincode::serialize(event).map_err(|e| NetworkError::Codec(format!("bincode: {}", e)))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Event> {
+        bincode::deserialize(data).map_err(|e| NetworkError::Codec(format!("bincode: {}", e)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl EventCodec for MsgPackCodec {
+    fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        rmp_serde::to_vec(event).map_err(|e| NetworkError::Codec(format!("MessagePack: {}", e)))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Event> {
+        rmp_serde::from_slice(data).map_err(|e| NetworkError::Codec(format!("MessagePack: {}", e)))
+    }
+}
+
+/// Names a codec so it can be chosen at runtime, e.g. from the command line.
+///
+/// Every variant exists regardless of enabled features so that peers built
+/// with different features can still describe what they support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CodecKind {
+    Json,
+    Cbor,
+    Bincode,
+    MsgPack,
+}
+
+impl CodecKind {
+    pub const ALL: [CodecKind; 4] = [CodecKind::Json, CodecKind::Cbor, CodecKind::Bincode, CodecKind::MsgPack];
+
+    /// Whether this build was compiled with support for the codec.
+    pub fn is_available(self) -> bool {
+        match self {
+            CodecKind::Json => true,
+            CodecKind::Cbor => cfg!(feature = "cbor"),
+            CodecKind::Bincode => cfg!(feature = "bincode"),
+            CodecKind::MsgPack => cfg!(feature = "msgpack"),
+        }
+    }
+
+    pub fn available() -> Vec<CodecKind> {
+        Self::ALL.into_iter().filter(|kind| kind.is_available()).collect()
+    }
+
+    pub fn codec(self) -> Result<Box<dyn EventCodec>> {
+        match self {
+            CodecKind::Json => Ok(Box::new(JsonCodec)),
+            #[cfg(feature = "cbor")]
+            CodecKind::Cbor => Ok(Box::new(CborCodec)),
+            #[cfg(feature = "bincode")]
+            CodecKind::Bincode => Ok(Box::new(BincodeCodec)),
+            #[cfg(feature = "msgpack")]
+            CodecKind::MsgPack => Ok(Box::new(MsgPackCodec)),
+            #[allow(unreachable_patterns)]
+            other => Err(NetworkError::Codec(format!("{} support is not compiled in", other))),
+        }
+    }
+}
+
+impl fmt::Display for CodecKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CodecKind::Json => "json",
+            CodecKind::Cbor => "cbor",
+            CodecKind::Bincode => "bincode",
+            CodecKind::MsgPack => "msgpack",
+        })
+    }
+}
+
+impl FromStr for CodecKind {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| NetworkError::Codec(format!("unknown codec '{}'", s)))
+    }
+}
@@ -0,0 +1,204 @@
+//! Finding servers on the local network.
+//!
+//! A server with an `Announcer` sends a small UDP beacon to a multicast
+//! group every few seconds, carrying its screen name, the port it listens on
+//! and its TLS certificate fingerprint, if it has one. Clients listen on the
+//! group with a `BeaconListener` and connect to the address the beacon came
+//! from.
+//!
+//! Beacons are not authenticated. The fingerprint is there so users can
+//! check it against the one the server prints and pin it; a client never
+//! trusts it just because it was announced.
+//!
+//! Several listeners can share the group port on one machine. Setting
+//! `DiscoveryConfig::interface` to `127.0.0.1` keeps everything on loopback.
+
+use super::tls::Fingerprint;
+use super::{NetworkError, Result};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+/// Multicast group and port beacons go to by default, in the
+/// organization-local scope.
+pub const DEFAULT_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 24, 80), 24880);
+
+/// Tells beacons apart from other traffic on the group.
+const SERVICE: &str = "rust-barrier";
+/// Beacons are a few dozen bytes; anything much larger is not one.
+const MAX_BEACON_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryConfig {
+    pub group: SocketAddrV4,
+    /// Interface to send and listen on; unspecified lets the system pick.
+    pub interface: Ipv4Addr,
+    /// How often a server announces itself.
+    pub interval: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            group: DEFAULT_GROUP,
+            interface: Ipv4Addr::UNSPECIFIED,
+            interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What a server announces about itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Beacon {
+    pub name: String,
+    pub port: u16,
+    pub fingerprint: Option<Fingerprint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireBeacon {
+    service: String,
+    name: String,
+    port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+}
+
+impl Beacon {
+    pub fn encode(&self) -> Vec<u8> {
+        let wire = WireBeacon {
+            service: SERVICE.to_string(),
+            name: self.name.clone(),
+            port: self.port,
+            fingerprint: self.fingerprint.map(|fingerprint| fingerprint.to_string()),
+        };
+        serde_json::to_vec(&wire).expect("beacons always serialize")
+    }
+
+    /// `None` for datagrams that are not our beacons.
+    pub fn decode(datagram: &[u8]) -> Option<Self> {
+        let wire: WireBeacon = serde_json::from_slice(datagram).ok()?;
+        if wire.service != SERVICE {
+            return None;
+        }
+        let fingerprint = match wire.fingerprint {
+            Some(fingerprint) => Some(fingerprint.parse().ok()?),
+            None => None,
+        };
+        Some(Self { name: wire.name, port: wire.port, fingerprint })
+    }
+}
+
+/// A server that announced itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    pub name: String,
+    /// Where the beacon came from, with the announced port.
+    pub addr: SocketAddr,
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// Sends a server's beacon to the group.
+pub struct Announcer {
+    socket: UdpSocket,
+    datagram: Vec<u8>,
+    config: DiscoveryConfig,
+}
+
+impl Announcer {
+    pub fn new(beacon: &Beacon, config: DiscoveryConfig) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Beacons stay on the local network
+        socket.set_multicast_ttl_v4(1)?;
+        socket.set_multicast_loop_v4(true)?;
+        if !config.interface.is_unspecified() {
+            socket.set_multicast_if_v4(&config.interface)?;
+        }
+        socket.bind(&SocketAddr::from((config.interface, 0)).into())?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket: UdpSocket::from_std(socket.into())?, datagram: beacon.encode(), config })
+    }
+
+    /// Sends the beacon once.
+    pub async fn announce(&self) -> Result<()> {
+        self.socket.send_to(&self.datagram, self.config.group).await?;
+        Ok(())
+    }
+
+    /// Sends the beacon every `interval` until sending fails.
+    pub async fn run(&self) -> Result<()> {
+        let mut interval = tokio::time::interval(self.config.interval);
+        loop {
+            interval.tick().await;
+            self.announce().await?;
+        }
+    }
+}
+
+/// Receives beacons from the group.
+pub struct BeaconListener {
+    socket: UdpSocket,
+}
+
+impl BeaconListener {
+    pub fn bind(config: DiscoveryConfig) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.group.port())).into())?;
+        socket.join_multicast_v4(config.group.ip(), &config.interface)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket: UdpSocket::from_std(socket.into())? })
+    }
+
+    /// Waits for the next beacon, skipping anything else sent to the group.
+    ///
+    /// Cancel safe.
+    pub async fn next(&mut self) -> Result<DiscoveredServer> {
+        let mut datagram = [0; MAX_BEACON_SIZE];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut datagram).await?;
+            if let Some(beacon) = Beacon::decode(&datagram[..len]) {
+                return Ok(DiscoveredServer {
+                    name: beacon.name,
+                    addr: SocketAddr::new(from.ip(), beacon.port),
+                    fingerprint: beacon.fingerprint,
+                });
+            }
+        }
+    }
+}
+
+/// The servers heard from within `wait`, sorted by name, each listed once
+/// per address.
+pub async fn discover(config: DiscoveryConfig, wait: Duration) -> Result<Vec<DiscoveredServer>> {
+    let mut listener = BeaconListener::bind(config)?;
+    let deadline = Instant::now() + wait;
+    let mut found = BTreeMap::new();
+    while let Ok(server) = tokio::time::timeout_at(deadline, listener.next()).await {
+        let server = server?;
+        found.insert((server.name.clone(), server.addr), server);
+    }
+    Ok(found.into_values().collect())
+}
+
+/// The first server announcing `name`. Fails with `Timeout` if none does
+/// within `wait`.
+pub async fn find(name: &str, config: DiscoveryConfig, wait: Duration) -> Result<DiscoveredServer> {
+    let mut listener = BeaconListener::bind(config)?;
+    tokio::time::timeout(wait, async {
+        loop {
+            let server = listener.next().await?;
+            if server.name == name {
+                return Ok(server);
+            }
+        }
+    })
+    .await
+    .map_err(|_| NetworkError::Timeout(wait))?
+}
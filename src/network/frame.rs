@@ -0,0 +1,158 @@
+//! Length-prefixed framing used on every `NetworkConnection`.
+//!
+//! Each frame is a five byte header followed by the payload:
+//!
+//! ```text
+//! +------+---------------------+-------------------+
+//! | type | length (u32, BE)    | payload ...       |
+//! +------+---------------------+-------------------+
+//! ```
+//!
+//! The length only counts the payload. Frames larger than the configured
+//! maximum are rejected from the header alone, before anything is buffered.
+
+use super::{NetworkError, Result};
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const HEADER_LEN: usize = 5;
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameType {
+    Event = 0x01,
+    /// An event with its sequence number and capture time (see
+    /// `network::sequence`).
+    TimedEvent = 0x02,
+    Hello = 0x10,
+    Welcome = 0x11,
+    Reject = 0x12,
+    AuthChallenge = 0x13,
+    AuthProof = 0x14,
+    MotionChannel = 0x15,
+    Ping = 0x16,
+    Pong = 0x17,
+    /// A motion datagram resent on the stream ahead of a reliable event
+    /// (see `network::motion`).
+    Motion = 0x18,
+}
+
+impl TryFrom<u8> for FrameType {
+    type Error = NetworkError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0x01 => Ok(FrameType::Event),
+            0x02 => Ok(FrameType::TimedEvent),
+            0x10 => Ok(FrameType::Hello),
+            0x11 => Ok(FrameType::Welcome),
+            0x12 => Ok(FrameType::Reject),
+            0x13 => Ok(FrameType::AuthChallenge),
+            0x14 => Ok(FrameType::AuthProof),
+            0x15 => Ok(FrameType::MotionChannel),
+            0x16 => Ok(FrameType::Ping),
+            0x17 => Ok(FrameType::Pong),
+            0x18 => Ok(FrameType::Motion),
+            other => Err(NetworkError::UnknownFrameType(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub kind: FrameType,
+    pub payload: Vec<u8>,
+}
+
+/// Serializes a frame header and payload into one buffer.
+pub fn encode(kind: FrameType, payload: &[u8], max_frame_size: usize) -> Result<Vec<u8>> {
+    if payload.len() > max_frame_size {
+        return Err(NetworkError::FrameTooLarge { size: payload.len(), max: max_frame_size });
+    }
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+    data.push(kind as u8);
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(payload);
+    Ok(data)
+}
+
+pub(crate) async fn write_frame<W>(
+    writer: &mut W,
+    kind: FrameType,
+    payload: &[u8],
+    max_frame_size: usize,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let data = encode(kind, payload, max_frame_size)?;
+    writer.write_all(&data).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Buffers bytes from a stream until a whole frame is available.
+///
+/// `read_frame` is cancel safe: partially received frames stay in the
+/// internal buffer, so it can be used as a `tokio::select!` branch.
+pub(crate) struct FrameReader<R> {
+    inner: R,
+    buf: BytesMut,
+    max_frame_size: usize,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::with_capacity(8 * 1024),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    pub async fn read_frame(&mut self) -> Result<Frame> {
+        loop {
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(frame);
+            }
+            if self.inner.read_buf(&mut self.buf).await? == 0 {
+                return Err(self.eof_error());
+            }
+        }
+    }
+
+    fn parse_frame(&mut self) -> Result<Option<Frame>> {
+        if self.buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let kind = FrameType::try_from(self.buf[0])?;
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if len > self.max_frame_size {
+            return Err(NetworkError::FrameTooLarge { size: len, max: self.max_frame_size });
+        }
+        if self.buf.len() < HEADER_LEN + len {
+            self.buf.reserve(HEADER_LEN + len - self.buf.len());
+            return Ok(None);
+        }
+        self.buf.advance(HEADER_LEN);
+        let payload = self.buf.split_to(len).to_vec();
+        Ok(Some(Frame { kind, payload }))
+    }
+
+    fn eof_error(&self) -> NetworkError {
+        if self.buf.is_empty() {
+            return NetworkError::Connection("Connection closed".into());
+        }
+        let expected = if self.buf.len() < HEADER_LEN {
+            HEADER_LEN
+        } else {
+            HEADER_LEN + u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize
+        };
+        NetworkError::TruncatedFrame { expected, received: self.buf.len() }
+    }
+}
@@ -0,0 +1,263 @@
+//! Versioned hello exchange performed before any event is sent.
+//!
+//! The client opens with a `Hello`. The server checks the protocol version
+//! ranges overlap, picks the settings both sides support and answers with
+//! a `Welcome`, or with a `Reject` carrying a human readable reason. All
+//! three messages are JSON regardless of the event codec, and unknown
+//! fields are ignored so newer builds can add capabilities freely.
+
+use super::codec::{CodecKind, EventCodec};
+use super::frame::FrameType;
+use super::{NetworkConnection, NetworkError, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+/// Version spoken by this build.
+pub const PROTOCOL_VERSION: u16 = 4;
+/// Oldest version this build can still talk to.
+pub const MIN_PROTOCOL_VERSION: u16 = 4;
+
+#[derive(Error, Debug)]
+pub enum HandshakeError {
+    #[error("Protocol version mismatch: we support {local_min}..={local_max}, peer supports {peer_min}..={peer_max}")]
+    VersionMismatch { local_min: u16, local_max: u16, peer_min: u16, peer_max: u16 },
+    #[error("No codec in common: we offer {ours:?}, peer offers {theirs:?}")]
+    NoCommonCodec { ours: Vec<CodecKind>, theirs: Vec<CodecKind> },
+    #[error("Peer rejected the handshake: {0}")]
+    Rejected(String),
+    #[error("Expected a {expected:?} frame but got {got:?}; is the peer running an older build?")]
+    UnexpectedFrame { expected: FrameType, got: FrameType },
+    #[error("Malformed handshake message: {0}")]
+    Malformed(String),
+    #[error("One side requires a shared secret but the other has none configured")]
+    AuthenticationRequired,
+    #[error("No shared secret configured")]
+    MissingCredentials,
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+    #[error("Transport error during handshake: {0}")]
+    Transport(#[source] Box<NetworkError>),
+}
+
+impl From<NetworkError> for HandshakeError {
+    fn from(e: NetworkError) -> Self {
+        match e {
+            NetworkError::Handshake(e) => e,
+            other => HandshakeError::Transport(Box::new(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenInfo {
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Supported event codecs, most preferred first.
+    #[serde(deserialize_with = "known_codecs")]
+    pub codecs: Vec<CodecKind>,
+    pub clipboard: bool,
+    /// Pointer motion as `Event::MouseMoveRelative` deltas instead of
+    /// absolute `Event::MouseMove` positions.
+    pub relative_motion: bool,
+    /// Pointer motion may travel over UDP (see `network::motion`).
+    pub udp_motion: bool,
+    /// Whether this side insists on `NetworkConnection::authenticate`.
+    pub auth: bool,
+    /// Events carry sequence numbers and capture times (see
+    /// `network::sequence`).
+    pub timed_events: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            codecs: CodecKind::available(),
+            clipboard: false,
+            relative_motion: false,
+            udp_motion: false,
+            auth: false,
+            timed_events: false,
+        }
+    }
+}
+
+/// Drops codec names this build has never heard of instead of failing.
+fn known_codecs<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<CodecKind>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    Ok(names.iter().filter_map(|name| name.parse().ok()).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u16,
+    pub min_version: u16,
+    pub name: String,
+    #[serde(default)]
+    pub screen: ScreenInfo,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            name: name.into(),
+            screen: ScreenInfo::default(),
+            capabilities: Capabilities::default(),
+        }
+    }
+}
+
+/// Settings both peers agreed on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u16,
+    pub codec: CodecKind,
+    pub clipboard: bool,
+    pub relative_motion: bool,
+    /// Both sides must set up the motion channel next.
+    pub udp_motion: bool,
+    /// Both sides must call `NetworkConnection::authenticate` next.
+    pub auth: bool,
+    /// Set up by the handshake; `send_timed` sends timing from now on.
+    pub timed_events: bool,
+    /// The hello received from the other side.
+    pub peer: Hello,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Welcome {
+    version: u16,
+    codec: CodecKind,
+    clipboard: bool,
+    relative_motion: bool,
+    #[serde(default)]
+    udp_motion: bool,
+    #[serde(default)]
+    timed_events: bool,
+    hello: Hello,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reject {
+    reason: String,
+}
+
+/// Computes the session a server with `local` would grant to a client that sent `peer`.
+pub fn negotiate(local: &Hello, peer: &Hello) -> std::result::Result<Session, HandshakeError> {
+    let version = local.version.min(peer.version);
+    if version < local.min_version.max(peer.min_version) {
+        return Err(HandshakeError::VersionMismatch {
+            local_min: local.min_version,
+            local_max: local.version,
+            peer_min: peer.min_version,
+            peer_max: peer.version,
+        });
+    }
+    // Never silently fall back to an unauthenticated session
+    if local.capabilities.auth != peer.capabilities.auth {
+        return Err(HandshakeError::AuthenticationRequired);
+    }
+
+    // The client's preference order wins among codecs both sides support
+    let codec = peer.capabilities.codecs.iter()
+        .copied()
+        .find(|codec| local.capabilities.codecs.contains(codec))
+        .ok_or_else(|| HandshakeError::NoCommonCodec {
+            ours: local.capabilities.codecs.clone(),
+            theirs: peer.capabilities.codecs.clone(),
+        })?;
+
+    Ok(Session {
+        version,
+        codec,
+        clipboard: local.capabilities.clipboard && peer.capabilities.clipboard,
+        relative_motion: local.capabilities.relative_motion && peer.capabilities.relative_motion,
+        // Datagrams are not authenticated, so they would bypass the secret
+        udp_motion: local.capabilities.udp_motion && peer.capabilities.udp_motion && !local.capabilities.auth,
+        auth: local.capabilities.auth,
+        timed_events: local.capabilities.timed_events && peer.capabilities.timed_events,
+        peer: peer.clone(),
+    })
+}
+
+fn decode<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<T> {
+    serde_json::from_slice(payload).map_err(|e| HandshakeError::Malformed(e.to_string()).into())
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    /// Sends our hello and waits for the server's verdict.
+    pub async fn client_handshake(&mut self, hello: Hello) -> Result<Session> {
+        self.send_frame(FrameType::Hello, &serde_json::to_vec(&hello)?).await?;
+
+        let frame = self.read_frame().await?;
+        match frame.kind {
+            FrameType::Welcome => {
+                let welcome: Welcome = decode(&frame.payload)?;
+                self.limits.check_string("screen name", &welcome.hello.name)?;
+                if welcome.hello.capabilities.auth != hello.capabilities.auth {
+                    return Err(HandshakeError::AuthenticationRequired.into());
+                }
+                self.timed = welcome.timed_events;
+                Ok(Session {
+                    version: welcome.version,
+                    codec: welcome.codec,
+                    clipboard: welcome.clipboard,
+                    relative_motion: welcome.relative_motion,
+                    udp_motion: welcome.udp_motion && !hello.capabilities.auth,
+                    auth: hello.capabilities.auth,
+                    timed_events: welcome.timed_events,
+                    peer: welcome.hello,
+                })
+            }
+            FrameType::Reject => {
+                let reject: Reject = decode(&frame.payload)?;
+                Err(HandshakeError::Rejected(reject.reason).into())
+            }
+            got => Err(HandshakeError::UnexpectedFrame { expected: FrameType::Welcome, got }.into()),
+        }
+    }
+
+    /// Waits for a client hello and accepts or rejects it.
+    ///
+    /// A rejected client is told why before the error is returned here.
+    pub async fn server_handshake(&mut self, hello: Hello) -> Result<Session> {
+        let frame = self.read_frame().await?;
+        let result = match frame.kind {
+            FrameType::Hello => decode::<Hello>(&frame.payload)
+                .and_then(|peer| self.limits.check_string("screen name", &peer.name).map(|()| peer))
+                .and_then(|peer| negotiate(&hello, &peer).map_err(NetworkError::from)),
+            got => Err(HandshakeError::UnexpectedFrame { expected: FrameType::Hello, got }.into()),
+        };
+
+        match result {
+            Ok(session) => {
+                let welcome = Welcome {
+                    version: session.version,
+                    codec: session.codec,
+                    clipboard: session.clipboard,
+                    relative_motion: session.relative_motion,
+                    udp_motion: session.udp_motion,
+                    timed_events: session.timed_events,
+                    hello,
+                };
+                self.send_frame(FrameType::Welcome, &serde_json::to_vec(&welcome)?).await?;
+                self.timed = session.timed_events;
+                Ok(session)
+            }
+            Err(e) => {
+                let reject = Reject { reason: e.to_string() };
+                // Best effort: the peer may already be gone
+                let _ = self.send_frame(FrameType::Reject, &serde_json::to_vec(&reject)?).await;
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Keepalives and dead-peer detection.
+//!
+//! Once enabled with `NetworkConnection::set_heartbeat`, every read has a
+//! deadline of `timeout` after the last frame received; if the peer stays
+//! silent that long, reads fail with `NetworkError::Timeout`. Each side is
+//! expected to send `Event::Heartbeat` whenever it has been idle for
+//! `interval` (see `send_heartbeat_if_due`). Received heartbeats refresh
+//! the deadline and are not returned from `receive_event`.
+//!
+//! The same two methods also drive the latency pings (see
+//! `network::latency`), so one timer branch keeps both going.
+
+use super::codec::EventCodec;
+use super::{NetworkConnection, Result};
+use crate::event::Event;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    /// Send a heartbeat after this long without sending anything.
+    pub interval: Duration,
+    /// Give up on the peer after this long without receiving anything.
+    pub timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+pub(crate) struct HeartbeatState {
+    pub config: HeartbeatConfig,
+    pub last_sent: Instant,
+    pub last_received: Instant,
+}
+
+impl HeartbeatState {
+    pub fn deadline(&self) -> Instant {
+        self.last_received + self.config.timeout
+    }
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    pub fn set_heartbeat(&mut self, config: HeartbeatConfig) {
+        let now = Instant::now();
+        self.heartbeat = Some(HeartbeatState { config, last_sent: now, last_received: now });
+    }
+
+    /// When the next heartbeat or ping is due, if any is.
+    ///
+    /// Meant for a `tokio::time::sleep_until` branch next to `receive_event`.
+    pub fn next_heartbeat(&self) -> Option<Instant> {
+        let heartbeat = self.heartbeat.as_ref().map(|state| state.last_sent + state.config.interval);
+        match (heartbeat, self.latency.next_due()) {
+            (Some(heartbeat), Some(latency)) => Some(heartbeat.min(latency)),
+            (heartbeat, latency) => heartbeat.or(latency),
+        }
+    }
+
+    /// Sends a ping if one is due, then `Event::Heartbeat` if
+    /// nothing else went out for a whole interval. Returns whether anything
+    /// was sent.
+    pub async fn send_heartbeat_if_due(&mut self) -> Result<bool> {
+        let sent = self.send_ping_if_due().await?;
+        match self.heartbeat.as_ref().map(|state| state.last_sent + state.config.interval) {
+            Some(due) if due <= Instant::now() => {
+                self.send_event(Event::Heartbeat).await?;
+                Ok(true)
+            }
+            _ => Ok(sent),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//! The server side of a multi-machine desk.
+//!
+//! A `Hub` keeps one entry per connected client, keyed by the screen name
+//! the client announced in its hello, and remembers which screen is active.
+//! Captured events go through `dispatch`: `Event::ScreenSwitch` changes the
+//! active screen and everything else is queued for the active client only.
+//! A switch is passed on to both the client that lost the pointer and the
+//! one that got it.
+//! While no client screen is active, input stays on the local machine and
+//! `dispatch` returns `false`.
+//!
+//! Capture reports pointer motion both as positions and as deltas; each
+//! client only gets the kind it negotiated (`Session::relative_motion`).
+//!
+//! Clients only get input once switched to, by a captured `ScreenSwitch`
+//! or `switch_next`, and control returns to the local screen when the
+//! active client leaves. A screen name can only be registered once: a
+//! client that reconnects while its stale connection is still registered
+//! is turned away until that one times out, unless it authenticated under
+//! that same name as its identity, in which case it takes the name over
+//! (`replace`).
+//!
+//! Connections set up with `set_latency` report their round-trip times to
+//! the hub (`Hub::latency`), and the RTT paces the client's motion.
+//!
+//! `dispatch` never waits for the network: each client has a bounded send
+//! queue (see `network::outbound`) drained by its own task, and a client
+//! whose queue overflows under `OverflowPolicy::Disconnect` is dropped.
+//!
+//! Events are stamped with their capture time in `dispatch` and numbered
+//! per screen as they are sent, continuing across reconnects, so clients
+//! that negotiated `timed_events` can spot lost and replayed events (see
+//! `network::sequence`).
+//!
+//! Stock Barrier clients join the same hub through `barrier::server`.
+
+use super::codec::EventCodec;
+use super::handshake::Session;
+use super::latency::LatencyReport;
+use super::outbound::{self, OutboundConfig, OutboundReceiver, OutboundSender};
+use super::transport::Listen;
+use super::{NetworkConnection, NetworkError, Result};
+use crate::event::{DeviceId, Event, EventClock, TimedEvent};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+/// Something that happened to the hub, for logging and input grabbing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HubEvent {
+    Joined { screen: String, addr: SocketAddr },
+    Rejected { addr: SocketAddr, reason: String },
+    Left { screen: String, reason: String },
+    /// `None` means the local screen.
+    Switched { to: Option<String> },
+}
+
+#[derive(Clone)]
+pub struct Hub {
+    inner: Arc<Mutex<Inner>>,
+    events: broadcast::Sender<HubEvent>,
+}
+
+struct Inner {
+    local_screen: String,
+    outbound: OutboundConfig,
+    clients: HashMap<String, Client>,
+    active: Option<String>,
+    next_id: u64,
+    source: DeviceId,
+    /// Sequence numbers per screen, kept when a client leaves.
+    clocks: HashMap<String, EventClock>,
+}
+
+struct Client {
+    id: u64,
+    queue: OutboundSender<TimedEvent>,
+    relative_motion: bool,
+    latency: Option<LatencyReport>,
+}
+
+impl Client {
+    fn wants(&self, event: &Event) -> bool {
+        match event {
+            Event::MouseMove { .. } => !self.relative_motion,
+            Event::MouseMoveRelative { .. } => self.relative_motion,
+            _ => true,
+        }
+    }
+}
+
+/// A client's place in the hub. Dropping it removes the client.
+pub struct Registration {
+    hub: Hub,
+    screen: String,
+    id: u64,
+    queue: OutboundReceiver<TimedEvent>,
+    reason: String,
+}
+
+impl Hub {
+    /// `local_screen` is the name of the server's own screen; switching to
+    /// it returns control to the server.
+    pub fn new(local_screen: impl Into<String>) -> Self {
+        Self::with_outbound(local_screen, OutboundConfig::default())
+    }
+
+    /// Like `new`, with a different size and overflow policy for the
+    /// clients' send queues.
+    pub fn with_outbound(local_screen: impl Into<String>, outbound: OutboundConfig) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                local_screen: local_screen.into(),
+                outbound,
+                clients: HashMap::new(),
+                active: None,
+                next_id: 0,
+                source: DeviceId::random(),
+                clocks: HashMap::new(),
+            })),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<HubEvent> {
+        self.events.subscribe()
+    }
+
+    /// The active client screen, or `None` while the local screen is active.
+    pub fn active_screen(&self) -> Option<String> {
+        self.lock().active.clone()
+    }
+
+    /// Names of the connected client screens, sorted.
+    pub fn screens(&self) -> Vec<String> {
+        let mut screens: Vec<_> = self.lock().clients.keys().cloned().collect();
+        screens.sort();
+        screens
+    }
+
+    /// Round-trip statistics for a connected client, once it has answered
+    /// a ping.
+    pub fn latency(&self, screen: &str) -> Option<LatencyReport> {
+        self.lock().clients.get(screen)?.latency
+    }
+
+    /// Routes a captured event without waiting for the network. Returns
+    /// whether it was queued for a client; motion of the kind the active
+    /// client did not ask for is dropped.
+    pub fn dispatch(&self, event: Event) -> bool {
+        self.dispatch_timed(TimedEvent::untimed(event))
+    }
+
+    /// Like `dispatch`, for an event that was captured earlier. Its capture
+    /// time is passed on; the sequence number is the hub's own.
+    pub fn dispatch_timed(&self, event: TimedEvent) -> bool {
+        let mut inner = self.lock();
+        if let Event::ScreenSwitch { to_screen } = &event.event {
+            let target = inner.clients.contains_key(to_screen).then(|| to_screen.clone());
+            if target.is_none() && *to_screen != inner.local_screen {
+                // Unknown screen: stay where we are
+                return false;
+            }
+            self.switch(&mut inner, target);
+            return false;
+        }
+        let Some(active) = &inner.active else {
+            return false;
+        };
+        let client = &inner.clients[active];
+        if !client.wants(&event.event) {
+            return false;
+        }
+        match client.queue.push(event) {
+            Ok(()) => true,
+            Err(e) => {
+                // Let go of the client now rather than when its task notices
+                let active = active.clone();
+                self.remove(&mut inner, &active, e.to_string());
+                false
+            }
+        }
+    }
+
+    /// Moves control to the next client screen in name order, and back to
+    /// the local screen after the last one. Returns the new active screen.
+    pub fn switch_next(&self) -> Option<String> {
+        let mut inner = self.lock();
+        let mut screens: Vec<_> = inner.clients.keys().cloned().collect();
+        screens.sort();
+        let next = match &inner.active {
+            None => screens.first().cloned(),
+            Some(active) => screens.into_iter().find(|screen| screen > active),
+        };
+        self.switch(&mut inner, next.clone());
+        next
+    }
+
+    /// Adds a client under `screen`, unless another client or the local
+    /// screen already goes by that name. `relative_motion` picks which kind
+    /// of motion it gets.
+    pub fn register(&self, screen: impl Into<String>, addr: SocketAddr, relative_motion: bool) -> Result<Registration> {
+        let screen = screen.into();
+        let mut inner = self.lock();
+        if inner.clients.contains_key(&screen) {
+            return Err(NetworkError::ScreenTaken(screen));
+        }
+        self.insert(&mut inner, screen, addr, relative_motion)
+    }
+
+    /// Like `register`, but takes the name over from an older connection
+    /// still using it. Only for clients that authenticated under this name,
+    /// or anyone could hijack a screen by reusing its name. The secret is
+    /// shared by every client, so this keeps out strangers but not another
+    /// client that knows it.
+    pub fn replace(&self, screen: impl Into<String>, addr: SocketAddr, relative_motion: bool) -> Result<Registration> {
+        let screen = screen.into();
+        let mut inner = self.lock();
+        self.insert(&mut inner, screen, addr, relative_motion)
+    }
+
+    fn insert(&self, inner: &mut Inner, screen: String, addr: SocketAddr, relative_motion: bool) -> Result<Registration> {
+        if screen == inner.local_screen {
+            return Err(NetworkError::ScreenTaken(screen));
+        }
+        let (queue_tx, queue) = outbound::channel(inner.outbound);
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let client = Client { id, queue: queue_tx, relative_motion, latency: None };
+        if let Some(stale) = inner.clients.insert(screen.clone(), client) {
+            stale.queue.close("replaced by a newer connection");
+            // The new connection carries on where the old one was
+            if inner.active.as_ref() == Some(&screen) {
+                let switch = Event::ScreenSwitch { to_screen: screen.clone() };
+                let _ = inner.clients[&screen].queue.push(TimedEvent::untimed(switch));
+            }
+        }
+        let _ = self.events.send(HubEvent::Joined { screen: screen.clone(), addr });
+        Ok(Registration {
+            hub: self.clone(),
+            screen,
+            id,
+            queue,
+            reason: "disconnected".to_string(),
+        })
+    }
+
+    /// Accepts clients forever. `setup` runs TLS, the handshake and
+    /// authentication on each new connection; clients are registered under
+    /// the screen name from their hello.
+    pub async fn serve<L, C, F, Fut>(&self, listener: L, setup: F) -> Result<()>
+    where
+        L: Listen,
+        C: EventCodec + 'static,
+        F: Fn(L::Incoming) -> Fut,
+        Fut: Future<Output = Result<(NetworkConnection<C>, Session)>> + Send + 'static,
+    {
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let hub = self.clone();
+            let accepted = setup(stream);
+            tokio::spawn(async move {
+                match accepted.await {
+                    Ok((conn, session)) => hub.serve_client(conn, session, addr).await,
+                    Err(e) => hub.reject(addr, e.to_string()),
+                }
+            });
+        }
+    }
+
+    /// Relays routed events to one client until it goes away.
+    ///
+    /// Events come from the client's send queue, so a client on a slow link
+    /// gets the latest pointer position rather than a backlog. A client
+    /// whose screen name is taken is told so with an `Event::Error` and
+    /// dropped, unless it authenticated with the screen name as its
+    /// identity (`peer_identity`); then it replaces the connection holding
+    /// the name instead.
+    pub async fn serve_client<C: EventCodec>(
+        &self,
+        mut conn: NetworkConnection<C>,
+        session: Session,
+        addr: SocketAddr,
+    ) {
+        let registered = if conn.peer_identity() == Some(session.peer.name.as_str()) {
+            self.replace(session.peer.name, addr, session.relative_motion)
+        } else {
+            self.register(session.peer.name, addr, session.relative_motion)
+        };
+        let mut registration = match registered {
+            Ok(registration) => registration,
+            Err(e) => {
+                let _ = conn.send_event(Event::Error(e.to_string())).await;
+                self.reject(addr, e.to_string());
+                return;
+            }
+        };
+        let mut samples = 0;
+        let reason = loop {
+            let stats = conn.latency_stats();
+            if stats.count() != samples {
+                samples = stats.count();
+                if let Some(latest) = stats.latest() {
+                    registration.queue.observe_rtt(latest.rtt);
+                }
+                self.set_latency(&registration, stats.report());
+            }
+            let next_heartbeat = conn.next_heartbeat();
+            tokio::select! {
+                event = registration.recv_timed() => match event {
+                    Some(event) => {
+                        let event = self.sequence(&registration, event);
+                        if let Err(e) = conn.send_timed(event).await {
+                            break e.to_string();
+                        }
+                    }
+                    None => break registration.close_reason(),
+                },
+                // Clients only send heartbeats and pings, which
+                // `receive_event` consumes; this notices when they stop.
+                // Limit violations are dropped there too; only a client that
+                // keeps at it is disconnected.
+                received = conn.receive_event() => match received {
+                    Err(e) if !e.is_violation() => break e.to_string(),
+                    _ => {}
+                },
+                _ = sleep_until(next_heartbeat) => {
+                    if let Err(e) = conn.send_heartbeat_if_due().await {
+                        break e.to_string();
+                    }
+                }
+            }
+        };
+        registration.leave(reason);
+    }
+
+    fn switch(&self, inner: &mut Inner, to: Option<String>) {
+        if inner.active == to {
+            return;
+        }
+        if let Some(client) = inner.active.as_ref().and_then(|active| inner.clients.get(active)) {
+            let to_screen = to.clone().unwrap_or_else(|| inner.local_screen.clone());
+            let _ = client.queue.push(TimedEvent::untimed(Event::ScreenSwitch { to_screen }));
+        }
+        if let Some(screen) = &to {
+            let switch = Event::ScreenSwitch { to_screen: screen.clone() };
+            let _ = inner.clients[screen].queue.push(TimedEvent::untimed(switch));
+        }
+        inner.active = to.clone();
+        let _ = self.events.send(HubEvent::Switched { to });
+    }
+
+    /// Reports a connection that failed before it could be registered.
+    pub(crate) fn reject(&self, addr: SocketAddr, reason: String) {
+        let _ = self.events.send(HubEvent::Rejected { addr, reason });
+    }
+
+    /// Numbers an event about to be sent to the registration's screen.
+    fn sequence(&self, registration: &Registration, event: TimedEvent) -> TimedEvent {
+        let mut inner = self.lock();
+        let source = inner.source;
+        let clock = inner.clocks.entry(registration.screen.clone()).or_insert_with(|| EventClock::new(source));
+        clock.sequence(event)
+    }
+
+    fn set_latency(&self, registration: &Registration, report: Option<LatencyReport>) {
+        let mut inner = self.lock();
+        if let Some(client) = inner.clients.get_mut(&registration.screen).filter(|client| client.id == registration.id) {
+            client.latency = report;
+        }
+    }
+
+    fn unregister(&self, screen: &str, id: u64, reason: String) {
+        let mut inner = self.lock();
+        // A newer connection may have taken the name over already
+        if inner.clients.get(screen).map(|client| client.id) != Some(id) {
+            return;
+        }
+        self.remove(&mut inner, screen, reason);
+    }
+
+    fn remove(&self, inner: &mut Inner, screen: &str, reason: String) {
+        if let Some(client) = inner.clients.remove(screen) {
+            client.queue.close(reason.clone());
+        }
+        let _ = self.events.send(HubEvent::Left { screen: screen.to_string(), reason });
+        if inner.active.as_deref() == Some(screen) {
+            self.switch(inner, None);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Registration {
+    pub fn screen(&self) -> &str {
+        &self.screen
+    }
+
+    /// The next event routed to this client, once it is due to be sent, or
+    /// `None` after the client was replaced or its queue overflowed.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.recv_timed().await.map(|timed| timed.event)
+    }
+
+    /// Like `recv`, with the time the event was captured. It is not numbered
+    /// yet; `serve_client` does that as it sends.
+    pub async fn recv_timed(&mut self) -> Option<TimedEvent> {
+        self.queue.recv().await
+    }
+
+    /// Why `recv` stopped returning events.
+    pub(crate) fn close_reason(&self) -> String {
+        self.queue.close_reason().unwrap_or_default()
+    }
+
+    /// Removes the client, reporting why it left.
+    pub fn leave(mut self, reason: impl Into<String>) {
+        self.reason = reason.into();
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let reason = std::mem::take(&mut self.reason);
+        self.hub.unregister(&self.screen, self.id, reason);
+    }
+}
+
+async fn sleep_until(due: Option<Instant>) {
+    match due {
+        Some(due) => tokio::time::sleep_until(due).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,238 @@
+//! Round-trip latency and clock offset.
+//!
+//! With `NetworkConnection::set_latency`, a connection sends a `Ping` frame
+//! every `ping_interval` carrying its wall-clock send time. The peer answers
+//! with a `Pong` that echoes it and adds its own receive and send times, as
+//! in NTP, and answers pings whether or not it sends any itself. Each reply
+//! gives a round-trip time, measured on the monotonic clock, and an
+//! estimate of how far the peer's wall clock is ahead of ours.
+//!
+//! `LatencyStats` keeps the last `window` samples. Percentiles show how
+//! the link behaves now, and the clock offset comes from the sample with
+//! the shortest round trip, which is the least skewed by queueing.
+//!
+//! Pings go out from `send_heartbeat_if_due`, so loops that already keep
+//! the heartbeat going need nothing else. `receive_event` consumes pings
+//! and pongs and writes the pongs itself, a buffer at a time, so it stays
+//! cancel safe.
+
+use super::codec::EventCodec;
+use super::frame::FrameType;
+use super::{NetworkConnection, NetworkError, Result};
+use crate::event::wall_clock;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyConfig {
+    /// How often to ping the peer.
+    pub ping_interval: Duration,
+    /// How many recent samples the statistics cover.
+    pub window: usize,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(1),
+            window: 64,
+        }
+    }
+}
+
+/// One ping/pong exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub rtt: Duration,
+    /// Microseconds the peer's clock is ahead of ours.
+    pub clock_offset: i64,
+}
+
+impl LatencySample {
+    /// Computes a sample from the four NTP timestamps, in microseconds
+    /// since the Unix epoch: our send time `t0`, the peer's receive time
+    /// `t1` and send time `t2`, and our receive time `t3`. `rtt` excludes
+    /// the time the peer held on to the ping.
+    pub fn from_timestamps(t0: i64, t1: i64, t2: i64, t3: i64) -> Self {
+        Self::new(micros(t3 - t0), t0, t1, t2, t3)
+    }
+
+    /// Like `from_timestamps`, with the time between our send and receive
+    /// measured separately (on a clock that cannot jump).
+    fn new(elapsed: Duration, t0: i64, t1: i64, t2: i64, t3: i64) -> Self {
+        Self {
+            rtt: elapsed.saturating_sub(micros(t2 - t1)),
+            clock_offset: ((t1 - t0) + (t2 - t3)) / 2,
+        }
+    }
+}
+
+/// Percentiles and clock offset over the recent samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// Samples taken since the connection started.
+    pub samples: u64,
+    pub latest: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    /// Microseconds the peer's clock is ahead of ours.
+    pub clock_offset: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencyStats {
+    window: usize,
+    recent: VecDeque<LatencySample>,
+    count: u64,
+}
+
+impl LatencyStats {
+    pub fn new(window: usize) -> Self {
+        Self { window: window.max(1), recent: VecDeque::new(), count: 0 }
+    }
+
+    pub fn record(&mut self, sample: LatencySample) {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(sample);
+        self.count += 1;
+    }
+
+    /// Samples recorded so far, including those out of the window.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn latest(&self) -> Option<LatencySample> {
+        self.recent.back().copied()
+    }
+
+    /// The round-trip time below which `percentile` percent of the recent
+    /// samples fall (nearest rank).
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut rtts: Vec<_> = self.recent.iter().map(|sample| sample.rtt).collect();
+        rtts.sort();
+        let rank = (percentile / 100.0 * rtts.len() as f64).ceil() as usize;
+        rtts.get(rank.clamp(1, rtts.len().max(1)) - 1).copied()
+    }
+
+    /// The clock offset of the recent sample with the shortest round trip.
+    pub fn clock_offset(&self) -> Option<i64> {
+        self.recent.iter().min_by_key(|sample| sample.rtt).map(|sample| sample.clock_offset)
+    }
+
+    pub fn report(&self) -> Option<LatencyReport> {
+        Some(LatencyReport {
+            samples: self.count,
+            latest: self.latest()?.rtt,
+            p50: self.percentile(50.0)?,
+            p95: self.percentile(95.0)?,
+            p99: self.percentile(99.0)?,
+            clock_offset: self.clock_offset()?,
+        })
+    }
+}
+
+impl Default for LatencyStats {
+    fn default() -> Self {
+        Self::new(LatencyConfig::default().window)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyState {
+    config: Option<LatencyConfig>,
+    stats: LatencyStats,
+    next_id: u64,
+    last_ping: Option<Instant>,
+    /// The ping awaiting a pong: its id, wall-clock and monotonic send time.
+    outstanding: Option<(u64, i64, Instant)>,
+}
+
+impl LatencyState {
+    /// When the next ping should go out.
+    pub fn next_due(&self) -> Option<Instant> {
+        let config = self.config?;
+        Some(match self.last_ping {
+            Some(last) => last + config.ping_interval,
+            None => Instant::now(),
+        })
+    }
+
+    /// Handles a `Ping` or `Pong` frame payload, returning the payload of
+    /// the `Pong` to answer a ping with.
+    pub fn receive(&mut self, kind: FrameType, payload: &[u8]) -> Result<Option<Vec<u8>>> {
+        let now = wall_clock();
+        let fields = decode(payload)?;
+        match (kind, fields.as_slice()) {
+            (FrameType::Ping, &[id, t0]) => return Ok(Some(encode(&[id, t0, now, wall_clock()]))),
+            (FrameType::Pong, &[id, t0, t1, t2]) => {
+                // A late pong for a ping we gave up on is ignored
+                if let Some((outstanding, sent, sent_at)) = self.outstanding {
+                    if outstanding == id as u64 && sent == t0 {
+                        self.outstanding = None;
+                        self.stats.record(LatencySample::new(sent_at.elapsed(), t0, t1, t2, now));
+                    }
+                }
+            }
+            _ => return Err(NetworkError::Connection(format!("malformed {:?} frame", kind))),
+        }
+        Ok(None)
+    }
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    /// Starts pinging the peer to measure latency.
+    pub fn set_latency(&mut self, config: LatencyConfig) {
+        self.latency.stats = LatencyStats::new(config.window);
+        self.latency.config = Some(config);
+    }
+
+    pub fn latency_stats(&self) -> &LatencyStats {
+        &self.latency.stats
+    }
+
+    /// Percentiles and clock offset, once at least one pong came back.
+    pub fn latency(&self) -> Option<LatencyReport> {
+        self.latency.stats.report()
+    }
+
+    /// Sends a ping now, replacing any that is still unanswered.
+    pub async fn send_ping(&mut self) -> Result<()> {
+        let id = self.latency.next_id;
+        self.latency.next_id += 1;
+        let now = wall_clock();
+        self.latency.outstanding = Some((id, now, Instant::now()));
+        self.latency.last_ping = Some(Instant::now());
+        self.send_frame(FrameType::Ping, &encode(&[id as i64, now])).await
+    }
+
+    /// Sends a ping if one is due. Returns whether it did.
+    pub(crate) async fn send_ping_if_due(&mut self) -> Result<bool> {
+        match self.latency.next_due() {
+            Some(due) if due <= Instant::now() => {
+                self.send_ping().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+fn encode(fields: &[i64]) -> Vec<u8> {
+    fields.iter().flat_map(|field| field.to_be_bytes()).collect()
+}
+
+fn decode(payload: &[u8]) -> Result<Vec<i64>> {
+    if !payload.len().is_multiple_of(8) {
+        return Err(NetworkError::Connection("malformed latency frame".to_string()));
+    }
+    Ok(payload.chunks_exact(8).map(|chunk| i64::from_be_bytes(chunk.try_into().unwrap())).collect())
+}
+
+fn micros(value: i64) -> Duration {
+    Duration::from_micros(value.max(0) as u64)
+}
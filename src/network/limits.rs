@@ -0,0 +1,151 @@
+//! Limits on what a peer may send.
+//!
+//! Besides the frame size limit, which is checked from the frame header
+//! alone, every connection holds the peer to `ReceiveLimits`:
+//!
+//! - Frames and motion datagrams are rate limited with a token bucket that
+//!   refills at `max_events_per_second` and holds at most `burst`. Pings
+//!   count too, since each one makes us queue a pong.
+//! - String fields (key text, screen names, error messages) may not be
+//!   longer than `max_string_len` bytes.
+//!
+//! An event that breaks a limit is dropped and `receive_event` reports it
+//! as `NetworkError::RateLimited` or `NetworkError::FieldTooLong`; the
+//! connection stays usable, so callers can log these and carry on (see
+//! `NetworkError::is_violation`). A peer that racks up more than
+//! `max_violations` within `violation_window` is cut off, and every receive
+//! fails with `NetworkError::Misbehaving` from then on.
+
+use super::codec::EventCodec;
+use super::{NetworkConnection, NetworkError, Result};
+use crate::event::Event;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiveLimits {
+    /// Sustained rate of frames and motion datagrams.
+    pub max_events_per_second: u32,
+    /// How many may arrive at once after a quiet spell.
+    pub burst: u32,
+    pub max_string_len: usize,
+    /// Violations tolerated within `violation_window` before cutting the
+    /// peer off.
+    pub max_violations: u32,
+    pub violation_window: Duration,
+}
+
+impl Default for ReceiveLimits {
+    fn default() -> Self {
+        Self {
+            // Well above a 1000 Hz mouse plus typing
+            max_events_per_second: 5000,
+            burst: 5000,
+            max_string_len: 256,
+            max_violations: 20,
+            violation_window: Duration::from_secs(10),
+        }
+    }
+}
+
+pub(crate) struct LimitState {
+    config: ReceiveLimits,
+    tokens: f64,
+    refilled: Instant,
+    violations: VecDeque<Instant>,
+    /// Violations counted when the peer was cut off.
+    cut_off: Option<u32>,
+}
+
+impl Default for LimitState {
+    fn default() -> Self {
+        Self::new(ReceiveLimits::default())
+    }
+}
+
+impl LimitState {
+    fn new(config: ReceiveLimits) -> Self {
+        Self {
+            config,
+            tokens: config.burst as f64,
+            refilled: Instant::now(),
+            violations: VecDeque::new(),
+            cut_off: None,
+        }
+    }
+
+    /// Fails for good once the peer has been cut off.
+    pub fn check_open(&self) -> Result<()> {
+        match self.cut_off {
+            Some(violations) => Err(NetworkError::Misbehaving(violations)),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a token for one received frame or datagram.
+    pub fn admit(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled).as_secs_f64() * self.config.max_events_per_second as f64;
+        self.tokens = (self.tokens + refill).min(self.config.burst as f64);
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return Err(self.violation(NetworkError::RateLimited(self.config.max_events_per_second)));
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Checks the string fields of a received event.
+    pub fn check_event(&mut self, event: &Event) -> Result<()> {
+        let field = match event {
+            Event::KeyPress { text, .. } => Some(("key text", text)),
+            Event::ScreenSwitch { to_screen } => Some(("screen name", to_screen)),
+            Event::Error(message) => Some(("error message", message)),
+            _ => None,
+        };
+        match field {
+            Some((field, value)) => self.check_string(field, value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_string(&mut self, field: &'static str, value: &str) -> Result<()> {
+        if value.len() > self.config.max_string_len {
+            let error = NetworkError::FieldTooLong { field, len: value.len(), max: self.config.max_string_len };
+            return Err(self.violation(error));
+        }
+        Ok(())
+    }
+
+    /// Counts a violation, turning it into `Misbehaving` once there were
+    /// too many lately.
+    fn violation(&mut self, error: NetworkError) -> NetworkError {
+        let now = Instant::now();
+        while self.violations.front().is_some_and(|at| now.duration_since(*at) > self.config.violation_window) {
+            self.violations.pop_front();
+        }
+        self.violations.push_back(now);
+        let count = self.violations.len() as u32;
+        if count > self.config.max_violations {
+            self.cut_off = Some(count);
+            return NetworkError::Misbehaving(count);
+        }
+        error
+    }
+}
+
+impl NetworkError {
+    /// Whether this is a peer breaking a `ReceiveLimits` limit, after which
+    /// the connection can still be used.
+    pub fn is_violation(&self) -> bool {
+        matches!(self, NetworkError::RateLimited(_) | NetworkError::FieldTooLong { .. })
+    }
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    /// Replaces the default limits on what the peer may send.
+    pub fn set_receive_limits(&mut self, limits: ReceiveLimits) {
+        self.limits = LimitState::new(limits);
+    }
+}
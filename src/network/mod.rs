@@ -1,7 +1,37 @@
+pub mod auth;
+pub mod barrier;
+pub mod coalesce;
+pub mod codec;
+pub mod discovery;
+pub mod frame;
+pub mod handshake;
+pub mod heartbeat;
+pub mod hub;
+pub mod latency;
+pub mod limits;
+pub mod motion;
+pub mod outbound;
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod reconnect;
+pub mod sequence;
+pub mod tls;
+pub mod transport;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::io::{AsyncWriteExt, BufReader, AsyncBufReadExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use crate::event::Event;
+use tokio::time::Instant;
+use crate::event::{Event, TimedEvent};
+use auth::AuthState;
+use codec::{EventCodec, JsonCodec};
+use frame::{Frame, FrameReader, FrameType, DEFAULT_MAX_FRAME_SIZE};
+use handshake::HandshakeError;
+use heartbeat::HeartbeatState;
+use latency::LatencyState;
+use limits::LimitState;
+use motion::{MotionReceiver, MotionSender};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,36 +42,307 @@ pub enum NetworkError {
     Serialization(#[from] serde_json::Error),
     #[error("Connection error: {0}")]
     Connection(String),
+    #[error("Frame of {size} bytes exceeds the {max} byte limit")]
+    FrameTooLarge { size: usize, max: usize },
+    #[error("Connection closed mid-frame after {received} of {expected} bytes")]
+    TruncatedFrame { expected: usize, received: usize },
+    #[error("Unknown frame type {0:#04x}")]
+    UnknownFrameType(u8),
+    #[error("Codec error: {0}")]
+    Codec(String),
+    #[error("Unexpected {0:?} frame")]
+    UnexpectedFrame(FrameType),
+    #[error("Handshake failed: {0}")]
+    Handshake(#[from] HandshakeError),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("QUIC error: {0}")]
+    Quic(String),
+    #[error("No data from peer for {0:?}")]
+    Timeout(Duration),
+    #[error("Send queue overflowed its {0} event limit")]
+    QueueFull(usize),
+    #[error("Peer sent more than {0} events per second")]
+    RateLimited(u32),
+    #[error("{field} of {len} bytes exceeds the {max} byte limit")]
+    FieldTooLong { field: &'static str, len: usize, max: usize },
+    #[error("Peer cut off after {0} protocol violations")]
+    Misbehaving(u32),
+    #[error("A screen named {0} is already connected")]
+    ScreenTaken(String),
 }
 
 pub type Result<T> = std::result::Result<T, NetworkError>;
 
-pub struct NetworkConnection {
-    reader: BufReader<OwnedReadHalf>,
-    writer: OwnedWriteHalf,
+pub(crate) type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+pub(crate) type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+pub struct NetworkConnection<C: EventCodec = JsonCodec> {
+    reader: FrameReader<BoxedReader>,
+    writer: BoxedWriter,
+    codec: C,
+    max_frame_size: usize,
+    auth: AuthState,
+    heartbeat: Option<HeartbeatState>,
+    latency: LatencyState,
+    limits: LimitState,
+    /// Control frames written from the receive path, not yet fully sent.
+    control_out: BytesMut,
+    /// The newest pong waiting for `control_out` to drain. Older ones are
+    /// dropped, so a peer that pings but never reads cannot make us buffer
+    /// without bound.
+    next_pong: Option<Vec<u8>>,
+    motion_tx: Option<MotionSender>,
+    motion_rx: Option<MotionReceiver>,
+    /// Events carry sequence numbers and capture times (see
+    /// `network::sequence`).
+    timed: bool,
 }
 
-impl NetworkConnection {
+impl NetworkConnection<JsonCodec> {
     pub fn new(stream: TcpStream) -> Self {
+        Self::with_codec(stream, JsonCodec)
+    }
+
+    /// Over any byte stream, such as a `UnixStream` or one end of
+    /// `tokio::io::duplex`.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::from_halves(reader, writer, JsonCodec)
+    }
+
+    /// Over this process's stdin and stdout, for running behind `ssh` or
+    /// another tunnel. Nothing else may write to stdout meanwhile.
+    pub fn stdio() -> Self {
+        Self::from_halves(tokio::io::stdin(), tokio::io::stdout(), JsonCodec)
+    }
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    pub fn with_codec(stream: TcpStream, codec: C) -> Self {
         let (reader_half, writer_half) = stream.into_split();
-        let reader = BufReader::new(reader_half);
-        Self { reader, writer: writer_half }
+        Self::from_halves(reader_half, writer_half, codec)
+    }
+
+    /// Over separate read and write streams, such as a child process's
+    /// stdout and stdin.
+    pub fn from_halves<R, W>(reader: R, writer: W, codec: C) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        Self {
+            reader: FrameReader::new(Box::new(reader) as BoxedReader),
+            writer: Box::new(writer),
+            codec,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            auth: AuthState::default(),
+            heartbeat: None,
+            latency: LatencyState::default(),
+            limits: LimitState::default(),
+            control_out: BytesMut::new(),
+            next_pong: None,
+            motion_tx: None,
+            motion_rx: None,
+            timed: false,
+        }
+    }
+
+    /// Switches the codec used for subsequent events.
+    pub fn into_codec<D: EventCodec>(self, codec: D) -> NetworkConnection<D> {
+        NetworkConnection {
+            reader: self.reader,
+            writer: self.writer,
+            codec,
+            max_frame_size: self.max_frame_size,
+            auth: self.auth,
+            heartbeat: self.heartbeat,
+            latency: self.latency,
+            limits: self.limits,
+            control_out: self.control_out,
+            next_pong: self.next_pong,
+            motion_tx: self.motion_tx,
+            motion_rx: self.motion_rx,
+            timed: self.timed,
+        }
+    }
+
+    /// Limits the payload size of frames in both directions.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+        self.reader.set_max_frame_size(max_frame_size);
     }
 
     pub async fn send_event(&mut self, event: Event) -> Result<()> {
-        let mut data = serde_json::to_vec(&event)?;
-        data.push(b'\n');
-        self.writer.write_all(&data).await?;
-        self.writer.flush().await?;
-        Ok(())
+        let data = self.codec.encode(&event)?;
+        match (&event, &mut self.motion_tx) {
+            (Event::MouseMove { .. }, Some(motion)) => motion.send(&data).await,
+            _ => {
+                self.sync_motion().await?;
+                self.send_frame(FrameType::Event, &data).await
+            }
+        }
+    }
+
+    /// Sends `event` with its sequence number and capture time, or as a
+    /// plain event if the session did not negotiate `timed_events`.
+    pub async fn send_timed(&mut self, event: TimedEvent) -> Result<()> {
+        if !self.timed {
+            return self.send_event(event.event).await;
+        }
+        let data = sequence::encode(&self.codec, &event)?;
+        match (&event.event, &mut self.motion_tx) {
+            (Event::MouseMove { .. }, Some(motion)) => motion.send(&data).await,
+            _ => {
+                self.sync_motion().await?;
+                self.send_frame(FrameType::TimedEvent, &data).await
+            }
+        }
     }
 
+    /// Repeats the last motion datagram on the stream, so the event about
+    /// to follow it cannot overtake it (see `network::motion`).
+    async fn sync_motion(&mut self) -> Result<()> {
+        match self.motion_tx.as_mut().and_then(MotionSender::take_unsynced) {
+            Some(datagram) => self.send_frame(FrameType::Motion, &datagram).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for the next event. Cancel safe.
+    ///
+    /// Events that break the receive limits are dropped and reported as
+    /// errors after which the connection can still be used (see
+    /// `network::limits`).
     pub async fn receive_event(&mut self) -> Result<Event> {
-        let mut line = String::new();
-        let n = self.reader.read_line(&mut line).await?;
-        if n == 0 {
-            return Err(NetworkError::Connection("Connection closed".into()));
+        self.receive_timed().await.map(|timed| timed.event)
+    }
+
+    /// Like `receive_event`, keeping the sequence number and capture time.
+    /// Events sent without them come back unsequenced, as received now.
+    pub async fn receive_timed(&mut self) -> Result<TimedEvent> {
+        self.limits.check_open()?;
+        loop {
+            let pending_control = !self.control_out.is_empty();
+            let frame = tokio::select! {
+                frame = read_frame(&mut self.reader, &mut self.heartbeat) => frame?,
+                payload = recv_motion(&mut self.motion_rx) => {
+                    let payload = payload?;
+                    self.limits.admit()?;
+                    match decode_motion(&self.codec, self.timed, &payload) {
+                        Some(timed) => return Ok(timed),
+                        None => continue,
+                    }
+                }
+                // One write at a time, so cancelling never splits a frame
+                written = self.writer.write_buf(&mut self.control_out), if pending_control => {
+                    written?;
+                    if self.control_out.is_empty() {
+                        self.writer.flush().await?;
+                        if let Some(state) = &mut self.heartbeat {
+                            state.last_sent = Instant::now();
+                        }
+                        if let Some(pong) = self.next_pong.take() {
+                            self.control_out.extend_from_slice(&pong);
+                        }
+                    }
+                    continue;
+                }
+            };
+            self.limits.admit()?;
+            match frame.kind {
+                FrameType::Event | FrameType::TimedEvent => {
+                    let timed = match frame.kind {
+                        FrameType::TimedEvent => sequence::decode(&self.codec, &frame.payload)?,
+                        _ => TimedEvent::untimed(self.codec.decode(&frame.payload)?),
+                    };
+                    self.limits.check_event(&timed.event)?;
+                    // Keepalives are consumed here when the heartbeat subsystem is on
+                    if self.heartbeat.is_some() && timed.event == Event::Heartbeat {
+                        continue;
+                    }
+                    return Ok(timed);
+                }
+                FrameType::Ping | FrameType::Pong => {
+                    if let Some(pong) = self.latency.receive(frame.kind, &frame.payload)? {
+                        let pong = frame::encode(FrameType::Pong, &pong, self.max_frame_size)?;
+                        if self.control_out.is_empty() {
+                            self.control_out.extend_from_slice(&pong);
+                        } else {
+                            self.next_pong = Some(pong);
+                        }
+                    }
+                }
+                FrameType::Motion => {
+                    let Some(motion) = &mut self.motion_rx else {
+                        return Err(NetworkError::UnexpectedFrame(frame.kind));
+                    };
+                    let decoded = motion
+                        .accept_resent(&frame.payload)
+                        .and_then(|payload| decode_motion(&self.codec, self.timed, &payload));
+                    if let Some(timed) = decoded {
+                        return Ok(timed);
+                    }
+                }
+                other => return Err(NetworkError::UnexpectedFrame(other)),
+            }
+        }
+    }
+
+    pub(crate) async fn send_frame(&mut self, kind: FrameType, payload: &[u8]) -> Result<()> {
+        // Finish any control frame first so the two cannot interleave
+        if !self.control_out.is_empty() {
+            self.writer.write_all_buf(&mut self.control_out).await?;
+        }
+        if let Some(pong) = self.next_pong.take() {
+            self.writer.write_all(&pong).await?;
         }
-        Ok(serde_json::from_str(line.trim())?)
+        frame::write_frame(&mut self.writer, kind, payload, self.max_frame_size).await?;
+        if let Some(state) = &mut self.heartbeat {
+            state.last_sent = Instant::now();
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    pub(crate) async fn read_frame(&mut self) -> Result<Frame> {
+        read_frame(&mut self.reader, &mut self.heartbeat).await
+    }
+}
+
+/// Waits for a motion datagram, or forever without a motion channel.
+async fn recv_motion(motion: &mut Option<MotionReceiver>) -> Result<Vec<u8>> {
+    match motion {
+        Some(motion) => motion.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Decodes a motion datagram's payload, discarding anything but
+/// `Event::MouseMove`: only motion may take the unreliable path.
+fn decode_motion<C: EventCodec>(codec: &C, timed: bool, payload: &[u8]) -> Option<TimedEvent> {
+    let decoded = match timed {
+        true => sequence::decode(codec, payload),
+        false => codec.decode(payload).map(TimedEvent::untimed),
+    };
+    decoded.ok().filter(|timed| matches!(timed.event, Event::MouseMove { .. }))
+}
+
+/// Reads a frame, failing once the heartbeat deadline passes.
+async fn read_frame(reader: &mut FrameReader<BoxedReader>, heartbeat: &mut Option<HeartbeatState>) -> Result<Frame> {
+    let frame = match heartbeat {
+        Some(state) => {
+            let timeout = state.config.timeout;
+            tokio::time::timeout_at(state.deadline(), reader.read_frame())
+                .await
+                .map_err(|_| NetworkError::Timeout(timeout))??
+        }
+        None => reader.read_frame().await?,
+    };
+    if let Some(state) = heartbeat {
+        state.last_received = Instant::now();
+    }
+    Ok(frame)
+}
@@ -0,0 +1,202 @@
+//! Pointer motion over UDP.
+//!
+//! A lost TCP segment holds up everything queued behind it, so a cursor
+//! that is moving continuously stutters on a lossy link even though only
+//! the newest position matters. When both peers advertise `udp_motion`,
+//! `Event::MouseMove` can travel as datagrams instead while keys and
+//! buttons stay on the reliable stream.
+//!
+//! Each datagram is a 64-bit session token and a 64-bit sequence number,
+//! both big-endian, followed by the event encoded with the connection's
+//! codec. The receiver picks the token and tells the sender over the TCP
+//! connection (a `MotionChannel` frame), then drops datagrams carrying a
+//! different token and any whose sequence number is not newer than the last
+//! one accepted, so late or duplicated positions never move the cursor
+//! backwards. Datagrams are neither encrypted nor authenticated, so the token
+//! could be read off the wire and used to move the pointer: sessions that
+//! authenticate never negotiate `udp_motion`, and the command line refuses
+//! it together with TLS.
+//!
+//! A button press or key has to land where the pointer was when it was
+//! captured, so before any other event goes out on the stream the last
+//! datagram sent is repeated there in a `Motion` frame, unless it already
+//! was. The receiver runs it through the same token and sequence checks,
+//! which drop it if a newer datagram beat it and drop any older one that
+//! arrives later, so nothing on the stream overtakes the motion before it.
+//!
+//! Datagrams do not count as traffic for the heartbeat. Relative motion
+//! stays on TCP: a lost delta could not be made up by the next one.
+
+use super::codec::EventCodec;
+use super::frame::FrameType;
+use super::{NetworkConnection, NetworkError, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::UdpSocket;
+
+pub const HEADER_LEN: usize = 16;
+/// Stays under the IPv6 minimum MTU so datagrams are never fragmented.
+pub const MAX_DATAGRAM_SIZE: usize = 1200;
+
+/// Sent by the receiving side in a `MotionChannel` frame.
+#[derive(Serialize, Deserialize)]
+struct MotionOffer {
+    port: u16,
+    token: u64,
+}
+
+pub struct MotionSender {
+    socket: UdpSocket,
+    token: u64,
+    next_seq: u64,
+    /// The last datagram sent, until it is repeated on the stream.
+    unsynced: Option<Vec<u8>>,
+}
+
+impl MotionSender {
+    /// Sends datagrams to `peer`, tagged with the token the receiver chose.
+    pub async fn connect(peer: SocketAddr, token: u64) -> Result<Self> {
+        let bind: SocketAddr = match peer {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(peer).await?;
+        Ok(Self { socket, token, next_seq: 0, unsynced: None })
+    }
+
+    pub async fn send(&mut self, payload: &[u8]) -> Result<()> {
+        let size = HEADER_LEN + payload.len();
+        if size > MAX_DATAGRAM_SIZE {
+            return Err(NetworkError::FrameTooLarge { size, max: MAX_DATAGRAM_SIZE });
+        }
+        let mut datagram = Vec::with_capacity(size);
+        datagram.extend_from_slice(&self.token.to_be_bytes());
+        datagram.extend_from_slice(&self.next_seq.to_be_bytes());
+        datagram.extend_from_slice(payload);
+        self.next_seq += 1;
+        self.socket.send(&datagram).await?;
+        self.unsynced = Some(datagram);
+        Ok(())
+    }
+
+    /// The last datagram sent, if it has not been taken since.
+    pub(super) fn take_unsynced(&mut self) -> Option<Vec<u8>> {
+        self.unsynced.take()
+    }
+}
+
+pub struct MotionReceiver {
+    socket: UdpSocket,
+    token: u64,
+    last_seq: Option<u64>,
+    dropped: u64,
+    buf: Vec<u8>,
+}
+
+impl MotionReceiver {
+    /// Listens on `addr` with a fresh random token.
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            token: rand::thread_rng().gen(),
+            last_seq: None,
+            dropped: 0,
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// The token senders must put in every datagram.
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Datagrams discarded so far for being stale, foreign or malformed.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Waits for the next datagram newer than any accepted so far and
+    /// returns its payload. Cancel safe.
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        loop {
+            let len = self.socket.recv(&mut self.buf).await?;
+            if self.admit(header(&self.buf[..len])) {
+                return Ok(self.buf[HEADER_LEN..len].to_vec());
+            }
+        }
+    }
+
+    /// Runs a datagram repeated on the stream through the same checks,
+    /// returning its payload if it is still the newest.
+    pub(super) fn accept_resent(&mut self, datagram: &[u8]) -> Option<Vec<u8>> {
+        self.admit(header(datagram)).then(|| datagram[HEADER_LEN..].to_vec())
+    }
+
+    /// Whether a datagram with this token and sequence number is newer than
+    /// any accepted so far, counting it as dropped if not.
+    fn admit(&mut self, header: Option<(u64, u64)>) -> bool {
+        match header {
+            Some((token, seq)) if token == self.token && self.last_seq.is_none_or(|last| seq > last) => {
+                self.last_seq = Some(seq);
+                true
+            }
+            _ => {
+                self.dropped += 1;
+                false
+            }
+        }
+    }
+}
+
+/// The token and sequence number, if `datagram` is long enough to have them.
+fn header(datagram: &[u8]) -> Option<(u64, u64)> {
+    if datagram.len() < HEADER_LEN {
+        return None;
+    }
+    let token = u64::from_be_bytes(datagram[..8].try_into().unwrap());
+    let seq = u64::from_be_bytes(datagram[8..HEADER_LEN].try_into().unwrap());
+    Some((token, seq))
+}
+
+impl<C: EventCodec> NetworkConnection<C> {
+    /// Sends `Event::MouseMove` through `sender` from now on.
+    pub fn send_motion_via(&mut self, sender: MotionSender) {
+        self.motion_tx = Some(sender);
+    }
+
+    /// Also accepts `Event::MouseMove` from `receiver` in `receive_event`.
+    pub fn receive_motion_via(&mut self, receiver: MotionReceiver) {
+        self.motion_rx = Some(receiver);
+    }
+
+    /// Receiving side of the motion channel setup: binds a UDP socket on
+    /// `bind` and tells the peer where to send. Call after the handshake
+    /// when `Session::udp_motion` is set.
+    pub async fn listen_for_motion(&mut self, bind: IpAddr) -> Result<SocketAddr> {
+        let receiver = MotionReceiver::bind(SocketAddr::new(bind, 0)).await?;
+        let addr = receiver.local_addr()?;
+        let offer = MotionOffer { port: addr.port(), token: receiver.token() };
+        self.send_frame(FrameType::MotionChannel, &serde_json::to_vec(&offer)?).await?;
+        self.receive_motion_via(receiver);
+        Ok(addr)
+    }
+
+    /// Sending side of the motion channel setup: waits for the peer's
+    /// offer and sends motion to that port on `peer`.
+    pub async fn send_motion_to(&mut self, peer: IpAddr) -> Result<SocketAddr> {
+        let frame = self.read_frame().await?;
+        if frame.kind != FrameType::MotionChannel {
+            return Err(NetworkError::UnexpectedFrame(frame.kind));
+        }
+        let offer: MotionOffer = serde_json::from_slice(&frame.payload)?;
+        let addr = SocketAddr::new(peer, offer.port);
+        self.send_motion_via(MotionSender::connect(addr, offer.token).await?);
+        Ok(addr)
+    }
+}
@@ -0,0 +1,224 @@
+//! Bounded per-connection send queues.
+//!
+//! `send_event` writes inline, so whoever calls it waits for the socket.
+//! Capture must never wait for a slow client, so the hub queues events with
+//! `OutboundSender::push`, which never blocks, and each connection's own
+//! task drains its queue with `OutboundReceiver::recv`.
+//!
+//! The queue is a `MotionCoalescer`, so queued motion collapses and is
+//! paced to the link. Keys and buttons never wait for that pacing: any
+//! motion ahead of them goes out at once, and since consecutive motion is
+//! merged, at most one motion event sits between any two of them. They are
+//! not reordered past that motion, since a click has to land where the
+//! pointer was.
+//!
+//! A queue that is still full after merging overflows, and `OverflowPolicy`
+//! decides what happens: drop the oldest queued motion, or give up on the
+//! peer. Only motion that folds into a neighbouring motion event is dropped
+//! (see `MotionCoalescer::drop_oldest_motion`), so keys and buttons still
+//! happen where the pointer was. Keys and buttons are never dropped,
+//! because a lost release leaves a key stuck down; a queue with nothing
+//! else to drop always disconnects.
+//!
+//! Queues hold `Event`s or, as in the hub, `TimedEvent`s.
+
+use super::coalesce::{CoalesceConfig, MotionCoalescer, Queued};
+use super::{NetworkError, Result};
+use crate::event::Event;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued motion that can go without moving the
+    /// pointer under a key or button to make room.
+    DropMotion,
+    /// Close the queue; the connection using it should be dropped.
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutboundConfig {
+    /// Events that may be queued after merging.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    pub coalesce: CoalesceConfig,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::DropMotion,
+            coalesce: CoalesceConfig::default(),
+        }
+    }
+}
+
+/// The producing end of a send queue. Cloning it shares the queue.
+pub struct OutboundSender<E = Event> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> Clone for OutboundSender<E> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+/// The consuming end, owned by the task that writes to the connection.
+pub struct OutboundReceiver<E = Event> {
+    shared: Arc<Shared<E>>,
+}
+
+struct Shared<E> {
+    state: Mutex<State<E>>,
+    notify: Notify,
+}
+
+struct State<E> {
+    config: OutboundConfig,
+    queue: MotionCoalescer<E>,
+    dropped: u64,
+    closed: Option<String>,
+}
+
+/// Creates a send queue.
+pub fn channel<E: Queued>(config: OutboundConfig) -> (OutboundSender<E>, OutboundReceiver<E>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            config,
+            queue: MotionCoalescer::new(config.coalesce),
+            dropped: 0,
+            closed: None,
+        }),
+        notify: Notify::new(),
+    });
+    (OutboundSender { shared: shared.clone() }, OutboundReceiver { shared })
+}
+
+impl<E: Queued> OutboundSender<E> {
+    /// Queues `event` without waiting. Fails with `NetworkError::QueueFull`
+    /// when the overflow policy gives up on the peer, and with
+    /// `NetworkError::Connection` once the queue is closed.
+    pub fn push(&self, event: E) -> Result<()> {
+        let mut state = self.shared.lock();
+        if let Some(reason) = &state.closed {
+            return Err(NetworkError::Connection(reason.clone()));
+        }
+        state.queue.push(event);
+        if state.queue.len() > state.config.capacity {
+            if state.config.overflow == OverflowPolicy::DropMotion && state.queue.drop_oldest_motion() {
+                state.dropped += 1;
+            } else {
+                // No point sending the backlog to a peer we are giving up on
+                let error = NetworkError::QueueFull(state.config.capacity);
+                state.queue = MotionCoalescer::new(state.config.coalesce);
+                state.closed = Some(error.to_string());
+                drop(state);
+                self.shared.notify.notify_one();
+                return Err(error);
+            }
+        }
+        drop(state);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    /// Closes the queue. The receiver still gets the events already queued,
+    /// then `None`; `close_reason` returns `reason`.
+    pub fn close(&self, reason: impl Into<String>) {
+        let mut state = self.shared.lock();
+        if state.closed.is_none() {
+            state.closed = Some(reason.into());
+        }
+        drop(state);
+        self.shared.notify.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed.is_some()
+    }
+
+    /// Motion events discarded by `OverflowPolicy::DropMotion` so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// Events currently queued.
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<E: Queued> OutboundReceiver<E> {
+    /// Waits until the next event is due to be sent and takes it, or
+    /// returns `None` once the queue is closed and empty. Cancel safe.
+    pub async fn recv(&mut self) -> Option<E> {
+        loop {
+            let notified = self.shared.notify.notified();
+            let ready_at = {
+                let mut state = self.shared.lock();
+                if state.closed.is_some() {
+                    // Nothing more is coming, so pacing no longer matters
+                    return state.queue.pop();
+                }
+                match state.queue.ready_at() {
+                    Some(at) if at <= Instant::now() => return state.queue.pop(),
+                    ready_at => ready_at,
+                }
+            };
+            match ready_at {
+                Some(at) => tokio::select! {
+                    _ = notified => {}
+                    _ = tokio::time::sleep_until(at) => {}
+                },
+                None => notified.await,
+            }
+        }
+    }
+
+    /// Feeds a round-trip time measurement into motion pacing.
+    pub fn observe_rtt(&self, sample: Duration) {
+        self.shared.lock().queue.observe_rtt(sample);
+    }
+
+    /// Why the queue was closed, if it was.
+    pub fn close_reason(&self) -> Option<String> {
+        self.shared.lock().closed.clone()
+    }
+}
+
+impl<E> Shared<E> {
+    fn lock(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverflowPolicy::DropMotion => "drop-motion",
+            OverflowPolicy::Disconnect => "disconnect",
+        })
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        [OverflowPolicy::DropMotion, OverflowPolicy::Disconnect]
+            .into_iter()
+            .find(|policy| policy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown overflow policy '{}'", s))
+    }
+}
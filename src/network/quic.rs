@@ -0,0 +1,200 @@
+//! QUIC transport.
+//!
+//! An alternative to TCP, and to TLS over TCP, for links that lose packets
+//! or change address. Input events run on the first bidirectional stream,
+//! wrapped in an ordinary `NetworkConnection`, so the handshake, codecs and
+//! heartbeats work exactly as they do over TCP. Bulk data such as clipboard
+//! contents goes on short-lived unidirectional streams next to it; QUIC
+//! streams are independent, so a large transfer or a lost packet on one does
+//! not hold up input on the other.
+//!
+//! QUIC always encrypts. Peers authenticate with the same self-signed
+//! certificates and fingerprint pinning as `network::tls`.
+//!
+//! Connections survive a change of local address: after
+//! `QuicEndpoint::rebind` the peer sees packets from the new address and
+//! carries on with the same connection.
+
+use super::codec::JsonCodec;
+use super::tls::{self, Fingerprint, TlsConfig};
+use super::transport::{Dial, Listen};
+use super::{NetworkConnection, NetworkError, Result};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::pki_types::CertificateDer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+const ALPN: &[u8] = b"rust-barrier";
+/// Upper bound on a single bulk transfer.
+pub const MAX_BULK_SIZE: usize = 16 * 1024 * 1024;
+
+pub struct QuicEndpoint {
+    endpoint: quinn::Endpoint,
+    /// For connecting out; the server's address picks what to trust.
+    tls: TlsConfig,
+}
+
+/// The QUIC connection behind a `NetworkConnection`, for bulk streams.
+#[derive(Clone)]
+pub struct QuicConnection {
+    conn: quinn::Connection,
+}
+
+impl QuicEndpoint {
+    /// Listens on `addr` for clients; can also connect out.
+    pub fn server(addr: SocketAddr, config: &TlsConfig) -> Result<Self> {
+        let mut server_crypto = tls::server_config(config)?;
+        server_crypto.alpn_protocols = vec![ALPN.to_vec()];
+        let server_crypto = QuicServerConfig::try_from(server_crypto).map_err(quic_error)?;
+        let server_config = quinn::ServerConfig::with_crypto(Arc::new(server_crypto));
+        let endpoint = quinn::Endpoint::server(server_config, addr)?;
+        Ok(Self { endpoint, tls: config.clone() })
+    }
+
+    /// An endpoint bound to `addr` that only connects out.
+    pub fn client(addr: SocketAddr, config: &TlsConfig) -> Result<Self> {
+        let endpoint = quinn::Endpoint::client(addr)?;
+        Ok(Self { endpoint, tls: config.clone() })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Opens a connection to `server` and its event stream.
+    pub async fn connect(&self, server: SocketAddr) -> Result<(NetworkConnection, QuicConnection)> {
+        let (client_config, first_use) = client_config(&self.tls, &server.to_string())?;
+        let conn = self.endpoint
+            .connect_with(client_config, server, tls::SERVER_NAME)
+            .map_err(quic_error)?
+            .await
+            .map_err(quic_error)?;
+        let quic = QuicConnection { conn };
+        if first_use {
+            tls::remember_server(&self.tls, &server.to_string(), quic.peer_fingerprint()?)?;
+        }
+        let (send, recv) = quic.conn.open_bi().await.map_err(quic_error)?;
+        Ok((event_connection(send, recv), quic))
+    }
+
+    /// Waits for the next client and its event stream.
+    pub async fn accept(&self) -> Result<(NetworkConnection, QuicConnection)> {
+        self.incoming().await?.establish().await
+    }
+
+    /// Waits for the next client without finishing its handshake, so an
+    /// accept loop is not held up by one slow peer.
+    pub async fn incoming(&self) -> Result<QuicIncoming> {
+        self.endpoint
+            .accept()
+            .await
+            .map(QuicIncoming)
+            .ok_or_else(|| NetworkError::Connection("Endpoint closed".to_string()))
+    }
+
+    /// Moves the endpoint to a new local address, say after a laptop moved
+    /// to another network. Open connections migrate with it.
+    pub fn rebind(&self, addr: SocketAddr) -> Result<SocketAddr> {
+        let socket = std::net::UdpSocket::bind(addr)?;
+        let local = socket.local_addr()?;
+        self.endpoint.rebind(socket)?;
+        Ok(local)
+    }
+
+    /// Closes every connection on this endpoint.
+    pub fn close(&self) {
+        self.endpoint.close(0u32.into(), b"closing");
+    }
+}
+
+/// A client that has reached the endpoint but is not connected yet.
+pub struct QuicIncoming(quinn::Incoming);
+
+impl QuicIncoming {
+    pub fn remote_address(&self) -> SocketAddr {
+        self.0.remote_address()
+    }
+
+    /// Completes the QUIC handshake and waits for the event stream, which
+    /// only shows up once the client sends on it (`client_handshake` does
+    /// straight away).
+    pub async fn establish(self) -> Result<(NetworkConnection, QuicConnection)> {
+        let conn = self.0.await.map_err(quic_error)?;
+        let (send, recv) = conn.accept_bi().await.map_err(quic_error)?;
+        Ok((event_connection(send, recv), QuicConnection { conn }))
+    }
+}
+
+impl Dial for QuicEndpoint {
+    type Stream = (NetworkConnection, QuicConnection);
+
+    async fn dial(&self, address: &str) -> Result<Self::Stream> {
+        let server = tokio::net::lookup_host(address)
+            .await?
+            .next()
+            .ok_or_else(|| NetworkError::Connection(format!("{} did not resolve", address)))?;
+        self.connect(server).await
+    }
+}
+
+impl Listen for QuicEndpoint {
+    type Incoming = QuicIncoming;
+
+    async fn accept(&self) -> Result<(QuicIncoming, SocketAddr)> {
+        let incoming = self.incoming().await?;
+        let addr = incoming.remote_address();
+        Ok((incoming, addr))
+    }
+}
+
+impl QuicConnection {
+    /// Where the peer's packets currently come from.
+    pub fn remote_address(&self) -> SocketAddr {
+        self.conn.remote_address()
+    }
+
+    pub fn peer_fingerprint(&self) -> Result<Fingerprint> {
+        let certs = self.conn
+            .peer_identity()
+            .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok());
+        tls::peer_fingerprint(certs.as_deref().map(|certs| certs.as_slice()))
+    }
+
+    /// Sends `data` on a stream of its own.
+    pub async fn send_bulk(&self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_BULK_SIZE {
+            return Err(NetworkError::FrameTooLarge { size: data.len(), max: MAX_BULK_SIZE });
+        }
+        let mut send = self.conn.open_uni().await.map_err(quic_error)?;
+        send.write_all(data).await.map_err(quic_error)?;
+        send.finish().map_err(quic_error)?;
+        // Wait for the peer to take it all before the stream is dropped
+        send.stopped().await.map_err(quic_error)?;
+        Ok(())
+    }
+
+    /// Waits for the next `send_bulk` from the peer.
+    pub async fn receive_bulk(&self) -> Result<Vec<u8>> {
+        let mut recv = self.conn.accept_uni().await.map_err(quic_error)?;
+        recv.read_to_end(MAX_BULK_SIZE).await.map_err(quic_error)
+    }
+
+    pub fn close(&self) {
+        self.conn.close(0u32.into(), b"closing");
+    }
+}
+
+fn client_config(config: &TlsConfig, server: &str) -> Result<(quinn::ClientConfig, bool)> {
+    let (mut client_crypto, first_use) = tls::client_config(config, server)?;
+    client_crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let client_crypto = QuicClientConfig::try_from(client_crypto).map_err(quic_error)?;
+    Ok((quinn::ClientConfig::new(Arc::new(client_crypto)), first_use))
+}
+
+fn event_connection(send: quinn::SendStream, recv: quinn::RecvStream) -> NetworkConnection {
+    NetworkConnection::from_halves(recv, send, JsonCodec)
+}
+
+fn quic_error(e: impl std::fmt::Display) -> NetworkError {
+    NetworkError::Quic(e.to_string())
+}
@@ -0,0 +1,211 @@
+//! Reconnecting after the link drops.
+//!
+//! `Reconnector` dials a list of server addresses in order (say the wired
+//! address first, then Wi-Fi) and backs off exponentially, with jitter,
+//! between full passes over the list. Whatever runs on top of the socket
+//! (TLS, handshake, authentication) is passed in as a setup closure so it is
+//! redone on every attempt.
+//!
+//! `SessionState` remembers what the peer left us in, so a connection that
+//! died mid-keystroke does not leave keys stuck down. Getting the pointer
+//! back is up to the server: a hub hands it to a connection that replaces
+//! the one holding it (see `Hub::replace`).
+
+use super::handshake::HandshakeError;
+use super::transport::{Dial, Tcp};
+use super::{NetworkError, Result};
+use crate::event::{Event, MouseButton};
+use crate::keycode::KeyCode;
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackoffConfig {
+    /// Delay after the first failed pass over the address list.
+    pub initial: Duration,
+    /// Upper bound on the delay, before jitter.
+    pub max: Duration,
+    /// Growth factor applied after every failed pass.
+    pub multiplier: f64,
+    /// Each delay is randomised by up to this fraction in either direction.
+    pub jitter: f64,
+    /// How long a single connection attempt may take.
+    pub connect_timeout: Duration,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            connect_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Exponential backoff schedule.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    config: BackoffConfig,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Self {
+        Self { config, current: config.initial }
+    }
+
+    /// The delay before the next attempt; each call grows the following one.
+    pub fn next_delay(&mut self) -> Duration {
+        let base = self.current.min(self.config.max);
+        self.current = base.mul_f64(self.config.multiplier).min(self.config.max);
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return base;
+        }
+        base.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+
+    /// Starts over from the initial delay, after a successful connection.
+    pub fn reset(&mut self) {
+        self.current = self.config.initial;
+    }
+}
+
+pub struct Reconnector {
+    addresses: Vec<String>,
+    backoff: Backoff,
+    connect_timeout: Duration,
+    connected: Option<usize>,
+}
+
+impl Reconnector {
+    /// `addresses` are `host:port` strings, most preferred first.
+    pub fn new(addresses: Vec<String>, config: BackoffConfig) -> Self {
+        Self {
+            addresses,
+            backoff: Backoff::new(config),
+            connect_timeout: config.connect_timeout,
+            connected: None,
+        }
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    /// The address the last successful `connect` used.
+    pub fn connected_address(&self) -> Option<&str> {
+        self.connected.map(|index| self.addresses[index].as_str())
+    }
+
+    /// Tries each address in order until `setup` succeeds on one of them,
+    /// sleeping with backoff between passes. `on_failure` is told about every
+    /// failed attempt. Gives up when the address list is empty or the peer
+    /// turns us away in the handshake, since retrying would not change that.
+    pub async fn connect<T, F, Fut>(
+        &mut self,
+        setup: F,
+        on_failure: impl FnMut(&str, &NetworkError),
+    ) -> Result<T>
+    where
+        F: FnMut(TcpStream) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.connect_via(&Tcp, setup, on_failure).await
+    }
+
+    /// Like `connect`, over another transport.
+    pub async fn connect_via<D, T, F, Fut>(
+        &mut self,
+        dialer: &D,
+        mut setup: F,
+        mut on_failure: impl FnMut(&str, &NetworkError),
+    ) -> Result<T>
+    where
+        D: Dial,
+        F: FnMut(D::Stream) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if self.addresses.is_empty() {
+            return Err(NetworkError::Connection("No server addresses to connect to".to_string()));
+        }
+        loop {
+            for index in 0..self.addresses.len() {
+                let address = self.addresses[index].as_str();
+                let result = match tokio::time::timeout(self.connect_timeout, dialer.dial(address)).await {
+                    Ok(Ok(stream)) => setup(stream).await,
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(NetworkError::Timeout(self.connect_timeout)),
+                };
+                match result {
+                    Ok(connection) => {
+                        self.backoff.reset();
+                        self.connected = Some(index);
+                        return Ok(connection);
+                    }
+                    Err(e) if is_fatal(&e) => return Err(e),
+                    Err(e) => on_failure(address, &e),
+                }
+            }
+            tokio::time::sleep(self.backoff.next_delay()).await;
+        }
+    }
+}
+
+fn is_fatal(error: &NetworkError) -> bool {
+    matches!(error, NetworkError::Handshake(e) if !matches!(e, HandshakeError::Transport(_)))
+}
+
+/// What the peer has told us so far that must survive a reconnect.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionState {
+    current_screen: Option<String>,
+    held_keys: BTreeMap<KeyCode, u32>,
+    held_buttons: BTreeSet<MouseButton>,
+}
+
+impl SessionState {
+    /// Records an event that was delivered.
+    pub fn observe(&mut self, event: &Event) {
+        match event {
+            Event::KeyPress { code, keysym, .. } => {
+                self.held_keys.insert(*code, *keysym);
+            }
+            Event::KeyRelease { code, .. } => {
+                self.held_keys.remove(code);
+            }
+            Event::MouseButton { button, pressed: true } => {
+                self.held_buttons.insert(*button);
+            }
+            Event::MouseButton { button, pressed: false } => {
+                self.held_buttons.remove(button);
+            }
+            Event::ScreenSwitch { to_screen } => {
+                self.current_screen = Some(to_screen.clone());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn current_screen(&self) -> Option<&str> {
+        self.current_screen.as_deref()
+    }
+
+    /// Releases for every key and button still held down, which the peer can
+    /// no longer send now that the link is gone. Forgets them afterwards.
+    pub fn release_held(&mut self) -> Vec<Event> {
+        let keys = std::mem::take(&mut self.held_keys)
+            .into_iter()
+            .map(|(code, keysym)| Event::KeyRelease { code, keysym });
+        let buttons = std::mem::take(&mut self.held_buttons)
+            .into_iter()
+            .map(|button| Event::MouseButton { button, pressed: false });
+        keys.chain(buttons).collect()
+    }
+}
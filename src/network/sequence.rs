@@ -0,0 +1,127 @@
+//! Sequence numbers and capture times on the wire.
+//!
+//! When both peers advertise `timed_events`, events travel as `TimedEvent`
+//! frames: a 20 byte header with the sequence number (u64), the capture
+//! time in microseconds since the Unix epoch (i64) and the source's
+//! `DeviceId` (u32), all big-endian, followed by the event encoded with the
+//! connection's codec. Motion datagrams carry the same header inside their
+//! payload. Without the capability events go as plain `Event` frames and
+//! arrive unsequenced, stamped with the time they were received.
+//!
+//! The hub numbers each client's events as they leave its send queue, so
+//! coalesced or dropped motion leaves no holes, and keeps counting when the
+//! client reconnects. A `SequenceTracker` on the receiving end then tells
+//! lost datagrams and replayed events apart from the normal flow.
+
+use super::codec::EventCodec;
+use super::{NetworkError, Result};
+use crate::event::{DeviceId, TimedEvent};
+use std::collections::{BTreeSet, HashMap};
+
+pub const HEADER_LEN: usize = 20;
+
+/// Missing sequence numbers remembered per source, so that late arrivals
+/// can still be told apart from duplicates.
+const MAX_MISSING: u64 = 1024;
+
+pub(crate) fn encode<C: EventCodec>(codec: &C, event: &TimedEvent) -> Result<Vec<u8>> {
+    let data = codec.encode(&event.event)?;
+    let mut payload = Vec::with_capacity(HEADER_LEN + data.len());
+    payload.extend_from_slice(&event.seq.to_be_bytes());
+    payload.extend_from_slice(&event.captured_at.to_be_bytes());
+    payload.extend_from_slice(&event.source.0.to_be_bytes());
+    payload.extend_from_slice(&data);
+    Ok(payload)
+}
+
+pub(crate) fn decode<C: EventCodec>(codec: &C, payload: &[u8]) -> Result<TimedEvent> {
+    if payload.len() < HEADER_LEN {
+        return Err(NetworkError::Codec(format!("timed event of {} bytes is shorter than its header", payload.len())));
+    }
+    let (header, data) = payload.split_at(HEADER_LEN);
+    Ok(TimedEvent {
+        event: codec.decode(data)?,
+        seq: u64::from_be_bytes(header[0..8].try_into().unwrap()),
+        captured_at: i64::from_be_bytes(header[8..16].try_into().unwrap()),
+        source: DeviceId(u32::from_be_bytes(header[16..20].try_into().unwrap())),
+    })
+}
+
+/// How a received event fits in with the ones before it from its source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The next one expected, or the first one heard from its source.
+    InOrder,
+    /// Newer than expected: `missed` events in between have not arrived.
+    Gap { missed: u64 },
+    /// One that was missing, arriving after newer ones.
+    Late,
+    /// Delivered before, for instance replayed after a reconnect.
+    Duplicate,
+    /// Carries no sequence number.
+    Unsequenced,
+}
+
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    sources: HashMap<DeviceId, Source>,
+    missed: u64,
+    late: u64,
+    duplicates: u64,
+}
+
+#[derive(Debug, Default)]
+struct Source {
+    last: u64,
+    missing: BTreeSet<u64>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classifies a received event. Callers should drop duplicates.
+    pub fn observe(&mut self, event: &TimedEvent) -> Delivery {
+        if !event.is_sequenced() {
+            return Delivery::Unsequenced;
+        }
+        let source = self.sources.entry(event.source).or_default();
+        // `seq` comes from the peer, so nothing here may overflow on it
+        let seq = event.seq;
+        if source.last == 0 || source.last.checked_add(1) == Some(seq) {
+            source.last = seq;
+            return Delivery::InOrder;
+        }
+        if seq > source.last {
+            let missed = seq - source.last - 1;
+            source.missing.extend((source.last + 1).max(seq.saturating_sub(MAX_MISSING))..seq);
+            while source.missing.len() as u64 > MAX_MISSING {
+                source.missing.pop_first();
+            }
+            source.last = seq;
+            self.missed = self.missed.saturating_add(missed);
+            return Delivery::Gap { missed };
+        }
+        if source.missing.remove(&seq) {
+            self.late += 1;
+            Delivery::Late
+        } else {
+            self.duplicates += 1;
+            Delivery::Duplicate
+        }
+    }
+
+    /// Events skipped over by gaps that have not turned up since.
+    pub fn missing(&self) -> u64 {
+        self.missed.saturating_sub(self.late)
+    }
+
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
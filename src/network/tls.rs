@@ -0,0 +1,434 @@
+//! TLS transport with certificate fingerprint pinning.
+//!
+//! There is no certificate authority. Every installation generates its own
+//! self-signed certificate and peers are recognised by the SHA-256
+//! fingerprint of that certificate, much like SSH host keys: users compare
+//! fingerprints out of band once and pin them. Both sides present a
+//! certificate, so the server can pin its clients as well.
+//!
+//! A client needs to know which server certificate to expect: either one
+//! pinned up front, or one it trusted on first use and keeps in
+//! `KnownServers`, after which a server presenting any other certificate
+//! is refused. With neither, it refuses to connect. A server with no
+//! client pinned accepts any client certificate; `--secret` is what keeps
+//! strangers out there.
+
+use super::{NetworkConnection, NetworkError, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+
+const CERT_FILE: &str = "cert.der";
+const KEY_FILE: &str = "key.der";
+/// Certificates carry no meaningful host name, so clients always ask for this one.
+pub(crate) const SERVER_NAME: &str = "rust-barrier";
+
+/// SHA-256 digest of a DER encoded certificate.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(cert_der: &[u8]) -> Self {
+        Self(Sha256::digest(cert_der).into())
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({})", self)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = NetworkError;
+
+    /// Accepts 64 hex digits, optionally separated by colons.
+    fn from_str(s: &str) -> Result<Self> {
+        let hex: String = s.chars().filter(|c| *c != ':').collect();
+        let invalid = || NetworkError::Tls(format!("invalid fingerprint '{}'", s));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// A self-signed certificate and its private key.
+pub struct TlsIdentity {
+    cert: CertificateDer<'static>,
+    key: PrivatePkcs8KeyDer<'static>,
+}
+
+impl TlsIdentity {
+    pub fn generate(name: &str) -> Result<Self> {
+        let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).map_err(tls_error)?;
+        Ok(Self {
+            cert: certified.cert.der().clone(),
+            key: PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()),
+        })
+    }
+
+    /// Loads the identity stored in `dir`, creating and saving one on first use.
+    pub fn load_or_generate(dir: &Path, name: &str) -> Result<Self> {
+        let cert_path = dir.join(CERT_FILE);
+        let key_path = dir.join(KEY_FILE);
+        if cert_path.exists() && key_path.exists() {
+            return Ok(Self {
+                cert: CertificateDer::from(fs::read(cert_path)?),
+                key: PrivatePkcs8KeyDer::from(fs::read(key_path)?),
+            });
+        }
+
+        let identity = Self::generate(name)?;
+        fs::create_dir_all(dir)?;
+        write_private(&key_path, identity.key.secret_pkcs8_der())?;
+        fs::write(cert_path, &identity.cert)?;
+        Ok(identity)
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.cert)
+    }
+
+    fn key(&self) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::Pkcs8(self.key.clone_key())
+    }
+}
+
+impl Clone for TlsIdentity {
+    fn clone(&self) -> Self {
+        Self { cert: self.cert.clone(), key: self.key.clone_key() }
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?.write_all(data)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    fs::write(path, data)
+}
+
+/// Server fingerprints trusted on first use, one `address fingerprint` line
+/// per server in a file, like SSH's `known_hosts`.
+#[derive(Clone, Debug)]
+pub struct KnownServers {
+    path: PathBuf,
+}
+
+impl KnownServers {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The fingerprint remembered for `server`, if any.
+    pub fn get(&self, server: &str) -> Result<Option<Fingerprint>> {
+        let known = match fs::read_to_string(&self.path) {
+            Ok(known) => known,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        for line in known.lines() {
+            if let Some((address, fingerprint)) = line.split_once(' ') {
+                if address == server {
+                    return fingerprint.trim().parse().map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn remember(&self, server: &str, fingerprint: Fingerprint) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{} {}", server, fingerprint)?;
+        Ok(())
+    }
+}
+
+/// Our identity plus the peer fingerprints we accept.
+#[derive(Clone)]
+pub struct TlsConfig {
+    identity: TlsIdentity,
+    pinned: Vec<Fingerprint>,
+    known_servers: Option<KnownServers>,
+}
+
+impl TlsConfig {
+    /// Accepts any client certificate, but no server certificate until one
+    /// is pinned or `trust_on_first_use` is set.
+    pub fn new(identity: TlsIdentity) -> Self {
+        Self { identity, pinned: Vec::new(), known_servers: None }
+    }
+
+    pub fn pin(mut self, fingerprint: Fingerprint) -> Self {
+        self.pinned.push(fingerprint);
+        self
+    }
+
+    /// Without a pin, trusts the certificate a server presents the first
+    /// time and records it in `known`; from then on only that one.
+    pub fn trust_on_first_use(mut self, known: KnownServers) -> Self {
+        self.known_servers = Some(known);
+        self
+    }
+
+    pub fn identity(&self) -> &TlsIdentity {
+        &self.identity
+    }
+
+    pub fn known_servers(&self) -> Option<&KnownServers> {
+        self.known_servers.as_ref()
+    }
+
+    pub fn is_pinned(&self, fingerprint: &Fingerprint) -> bool {
+        self.pinned.contains(fingerprint)
+    }
+
+    /// What to accept from `server`, and whether this is the first time.
+    fn server_trust(&self, server: &str) -> Result<(Vec<Fingerprint>, bool)> {
+        if !self.pinned.is_empty() {
+            return Ok((self.pinned.clone(), false));
+        }
+        let Some(known) = &self.known_servers else {
+            return Err(NetworkError::Tls(format!("no certificate pinned for server {}", server)));
+        };
+        Ok(match known.get(server)? {
+            Some(fingerprint) => (vec![fingerprint], false),
+            None => (Vec::new(), true),
+        })
+    }
+
+    fn client_verifier(&self, provider: &Arc<CryptoProvider>) -> Arc<PinnedVerifier> {
+        Arc::new(PinnedVerifier {
+            accept_any: self.pinned.is_empty(),
+            pinned: self.pinned.clone(),
+            provider: provider.clone(),
+        })
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Accepts exactly the pinned certificates, or any for `accept_any`.
+#[derive(Debug)]
+struct PinnedVerifier {
+    pinned: Vec<Fingerprint>,
+    accept_any: bool,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedVerifier {
+    fn check(&self, cert: &CertificateDer<'_>) -> std::result::Result<(), rustls::Error> {
+        let fingerprint = Fingerprint::of(cert);
+        if self.accept_any || self.pinned.contains(&fingerprint) {
+            Ok(())
+        } else {
+            Err(rustls::Error::General(format!("certificate {} is not pinned", fingerprint)))
+        }
+    }
+
+    fn tls12(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn tls13(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        self.check(end_entity).map(|_| ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.tls12(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.tls13(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.schemes()
+    }
+}
+
+impl ClientCertVerifier for PinnedVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        self.check(end_entity).map(|_| ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.tls12(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.tls13(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.schemes()
+    }
+}
+
+pub(crate) fn tls_error(e: impl fmt::Display) -> NetworkError {
+    NetworkError::Tls(e.to_string())
+}
+
+pub(crate) fn peer_fingerprint(certs: Option<&[CertificateDer<'_>]>) -> Result<Fingerprint> {
+    certs
+        .and_then(|certs| certs.first())
+        .map(|cert| Fingerprint::of(cert))
+        .ok_or_else(|| NetworkError::Tls("peer sent no certificate".into()))
+}
+
+/// For connecting to `server`, an address as `KnownServers` records it.
+/// Also says whether the server is being trusted on first use.
+pub(crate) fn client_config(config: &TlsConfig, server: &str) -> Result<(rustls::ClientConfig, bool)> {
+    let (pinned, first_use) = config.server_trust(server)?;
+    let provider = provider();
+    let verifier = Arc::new(PinnedVerifier { pinned, accept_any: first_use, provider: provider.clone() });
+    let client_config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(tls_error)?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_client_auth_cert(vec![config.identity.cert.clone()], config.identity.key())
+        .map_err(tls_error)?;
+    Ok((client_config, first_use))
+}
+
+/// Records the certificate of a server trusted on first use.
+pub(crate) fn remember_server(config: &TlsConfig, server: &str, fingerprint: Fingerprint) -> Result<()> {
+    match &config.known_servers {
+        Some(known) => known.remember(server, fingerprint),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn server_config(config: &TlsConfig) -> Result<rustls::ServerConfig> {
+    let provider = provider();
+    rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(tls_error)?
+        .with_client_cert_verifier(config.client_verifier(&provider))
+        .with_single_cert(vec![config.identity.cert.clone()], config.identity.key())
+        .map_err(tls_error)
+}
+
+/// Runs the client side of the TLS handshake over `stream`.
+///
+/// Returns the connection and the fingerprint the server presented. A
+/// server trusted on first use is remembered by its address.
+pub async fn connect(stream: TcpStream, config: &TlsConfig) -> Result<(NetworkConnection, Fingerprint)> {
+    let server = stream.peer_addr()?.to_string();
+    let (client_config, first_use) = client_config(config, &server)?;
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+    let server_name = ServerName::try_from(SERVER_NAME).map_err(tls_error)?;
+    let stream = connector.connect(server_name, stream).await.map_err(tls_error)?;
+    let fingerprint = peer_fingerprint(stream.get_ref().1.peer_certificates())?;
+    if first_use {
+        remember_server(config, &server, fingerprint)?;
+    }
+    Ok((NetworkConnection::from_stream(stream), fingerprint))
+}
+
+/// Runs the server side of the TLS handshake over `stream`.
+///
+/// Clients must present a certificate; the returned fingerprint is theirs.
+pub async fn accept(stream: TcpStream, config: &TlsConfig) -> Result<(NetworkConnection, Fingerprint)> {
+    let server_config = server_config(config)?;
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+    let stream = acceptor.accept(stream).await.map_err(tls_error)?;
+    let fingerprint = peer_fingerprint(stream.get_ref().1.peer_certificates())?;
+    Ok((NetworkConnection::from_stream(stream), fingerprint))
+}
@@ -0,0 +1,77 @@
+//! How connections are made.
+//!
+//! `Reconnector` and `Hub` are written against these traits rather than
+//! TCP directly, so other transports (see `network::quic`) slot in. What a
+//! transport hands over is whatever its setup step needs next: a raw
+//! `TcpStream` for TCP, a ready event stream for QUIC.
+//!
+//! Byte streams that are not dialed, such as stdin and stdout or an
+//! in-memory pipe, go straight to `NetworkConnection::from_stream` or
+//! `from_halves`.
+
+use super::Result;
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// The client side: opens connections to `host:port` addresses.
+pub trait Dial {
+    type Stream: Send;
+
+    fn dial(&self, address: &str) -> impl Future<Output = Result<Self::Stream>> + Send;
+}
+
+/// The server side: waits for the next peer.
+pub trait Listen {
+    type Incoming: Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = Result<(Self::Incoming, SocketAddr)>> + Send;
+}
+
+/// Plain TCP.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tcp;
+
+impl Dial for Tcp {
+    type Stream = TcpStream;
+
+    async fn dial(&self, address: &str) -> Result<TcpStream> {
+        Ok(TcpStream::connect(address).await?)
+    }
+}
+
+impl Listen for TcpListener {
+    type Incoming = TcpStream;
+
+    async fn accept(&self) -> Result<(TcpStream, SocketAddr)> {
+        Ok(TcpListener::accept(self).await?)
+    }
+}
+
+/// Unix domain sockets; addresses are socket paths.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unix;
+
+#[cfg(unix)]
+impl Dial for Unix {
+    type Stream = UnixStream;
+
+    async fn dial(&self, address: &str) -> Result<UnixStream> {
+        Ok(UnixStream::connect(address).await?)
+    }
+}
+
+/// Peers on a Unix socket have no IP address; they are all reported as
+/// `0.0.0.0:0`.
+#[cfg(unix)]
+impl Listen for UnixListener {
+    type Incoming = UnixStream;
+
+    async fn accept(&self) -> Result<(UnixStream, SocketAddr)> {
+        let (stream, _) = UnixListener::accept(self).await?;
+        Ok((stream, SocketAddr::from(([0, 0, 0, 0], 0))))
+    }
+}
@@ -1,11 +1,20 @@
 use x11rb::connection::Connection;
-use x11rb::protocol::xproto::{ConnectionExt, GrabMode, EventMask};
-use x11rb::protocol::xproto::Window;
-use x11rb::rust_connection::RustConnection;
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
+use x11rb::protocol::xkb::{self as xkb_ext, ConnectionExt as _};
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, EventMask, ModMask};
+use x11rb::protocol::xproto::{self, Window};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
 use thiserror::Error;
 use crate::event::Event;
+use crate::keycode::KeyCode;
 use xkbcommon::xkb;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::sync::{Arc, Mutex};
+
+/// Most wheel clicks injected for one scroll event.
+const MAX_SCROLL_CLICKS: f64 = 100.0;
 
 #[derive(Error, Debug)]
 pub enum X11Error {
@@ -19,93 +28,182 @@ pub enum X11Error {
     KeymapError(String),
     #[error("XKB error: {0}")]
     XkbError(String),
+    #[error("XInput error: {0}")]
+    XInputError(String),
+    /// The event has no X11 equivalent here; others can still be injected.
+    #[error("Cannot inject {0}")]
+    Unsupported(String),
 }
 
-pub struct X11Platform {
-    conn: Arc<RustConnection>,
-    root: Window,
+/// A scroll valuator of some device.
+#[derive(Debug, Clone, Copy)]
+struct ScrollAxis {
+    horizontal: bool,
+    /// Valuator change that makes one wheel click.
+    increment: f64,
+}
+
+/// What the valuators in raw motion events mean, per device.
+#[derive(Default)]
+struct Valuators {
+    /// Scroll valuators by device and valuator number.
+    scroll: HashMap<(xinput::DeviceId, u16), ScrollAxis>,
+    /// Devices whose valuators 0 and 1 move the pointer by relative
+    /// amounts, such as mice and touchpads but not tablets.
+    relative: HashSet<xinput::DeviceId>,
+}
+
+/// The core keyboard's keymap, and its state for the keysyms and text of
+/// the keys we capture under the current modifiers and layout group.
+struct Keyboard {
     keymap: xkb::Keymap,
     state: xkb::State,
 }
 
+pub struct X11Platform {
+    conn: Arc<XCBConnection>,
+    root: Window,
+    width: u16,
+    height: u16,
+    xkb_context: xkb::Context,
+    keyboard_device: i32,
+    /// While `run_event_loop` captures input, reloaded when the server
+    /// reports a new keymap and kept in step with its modifiers and group
+    /// through XKB state events.
+    keyboard: Mutex<Keyboard>,
+    /// Captured pointer movement that did not add up to a whole pixel yet.
+    motion_remainder: Mutex<(f64, f64)>,
+    /// Injected scrolling that did not add up to a whole click yet.
+    scroll_remainder: Mutex<(f64, f64)>,
+}
+
 impl X11Platform {
     pub fn new() -> Result<Self, X11Error> {
-        // Connect to X server
-        let (conn, screen_num) = x11rb::connect(None)
+        Self::connect(None)
+    }
+
+    /// Opens X display `:{display}` instead of the one named by `$DISPLAY`.
+    pub fn open_display(display: u32) -> Result<Self, X11Error> {
+        Self::connect(Some(&format!(":{}", display)))
+    }
+
+    fn connect(display_name: Option<&str>) -> Result<Self, X11Error> {
+        // Connect to X server, through libxcb so libxkbcommon can share it
+        let display_name = display_name
+            .map(CString::new)
+            .transpose()
+            .map_err(|e| X11Error::ConnectionError(e.to_string()))?;
+        let (conn, screen_num) = XCBConnection::connect(display_name.as_deref())
             .map_err(|e| X11Error::ConnectionError(e.to_string()))?;
         let conn = Arc::new(conn);
         let setup = conn.setup();
         let screen = &setup.roots[screen_num];
         let root = screen.root;
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
 
-        // Initialize XKB
-        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
-
-        // Create keymap using Keymap::new_from_names
-        let keymap = xkb::Keymap::new_from_names(
-            &context,
-            "",
-            "",
-            "",
-            "",
-            None,
-            xkb::KEYMAP_COMPILE_NO_FLAGS,
-        )
-        .ok_or_else(|| X11Error::KeymapError("Failed to create keymap".to_string()))?;
-
-        let state = xkb::State::new(&keymap);
+        // Take the keymap the server uses for the core keyboard
+        let xkb_context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keyboard_device = setup_xkb(&conn)?;
+        let keyboard = load_keyboard(&conn, &xkb_context, keyboard_device)?;
 
         Ok(Self {
             conn,
             root,
-            keymap,
-            state,
+            width,
+            height,
+            xkb_context,
+            keyboard_device,
+            keyboard: Mutex::new(keyboard),
+            motion_remainder: Mutex::new((0.0, 0.0)),
+            scroll_remainder: Mutex::new((0.0, 0.0)),
         })
     }
 
+    /// Size of the root window in pixels.
+    pub fn screen_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
     pub fn grab_input(&self) -> Result<(), X11Error> {
-        // Grab keyboard
-        self.conn.grab_keyboard(
-            false,
-            self.root,
-            x11rb::CURRENT_TIME,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-        ).map_err(|e| X11Error::GrabError(e.to_string()))?
-        .reply()
-        .map_err(|e| X11Error::GrabError(e.to_string()))?;
+        self.input_grab().grab()
+    }
 
-        // Grab pointer (mouse)
-        self.conn.grab_pointer(
-            false,
-            self.root,
-            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | 
-            EventMask::POINTER_MOTION | EventMask::BUTTON_MOTION,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-            self.root,
-            self.root,
-            x11rb::CURRENT_TIME,
-        ).map_err(|e| X11Error::GrabError(e.to_string()))?
-        .reply()
-        .map_err(|e| X11Error::GrabError(e.to_string()))?;
+    pub fn ungrab_input(&self) -> Result<(), X11Error> {
+        self.input_grab().ungrab()
+    }
 
+    /// Reports `code` to `run_event_loop` even while input is not grabbed,
+    /// with any modifiers, so it can serve as a hotkey. Other applications
+    /// no longer see it.
+    pub fn grab_key(&self, code: KeyCode) -> Result<(), X11Error> {
+        let keycode = self.x_keycode(code)
+            .ok_or_else(|| X11Error::KeymapError(format!("no keycode for {}", code)))?;
+        self.conn.grab_key(false, self.root, ModMask::ANY, keycode, GrabMode::ASYNC, GrabMode::ASYNC)?
+            .check()?;
         Ok(())
     }
 
+    /// A handle that can grab or release input from another thread while
+    /// this platform is blocked in `run_event_loop`.
+    pub fn input_grab(&self) -> InputGrab {
+        InputGrab {
+            conn: self.conn.clone(),
+            root: self.root,
+        }
+    }
+
+    /// Reports captured input. Pointer motion is reported twice: as an
+    /// absolute `MouseMove` and as a `MouseMoveRelative` delta, so the
+    /// receiver can pick whichever its client negotiated.
+    ///
+    /// With XInput 2.1 the deltas come from the raw valuators of mice and
+    /// touchpads, so they keep coming while the grabbed pointer is pinned
+    /// against a screen edge. Without it they are the differences between
+    /// absolute positions, and stop there. Scrolling is reported as
+    /// `Scroll`, with the fractional amounts of smooth-scrolling devices
+    /// when XInput 2.1 is available.
+    ///
+    /// The raw motion and keyboard events behind this are only asked for
+    /// here, so a platform that just injects does not queue up every local
+    /// mouse move and keypress.
     pub fn run_event_loop<F>(&self, mut callback: F) -> Result<(), X11Error> 
     where
         F: FnMut(Event)
     {
+        select_keyboard_events(&self.conn, self.keyboard_device)?;
+        // The keymap or state may have changed since we connected
+        self.reload_keyboard()?;
+        // Without XInput 2.1 the wheel only shows up as buttons 4 to 7, and
+        // pointer deltas stop at the screen edges
+        let valuators = select_raw_events(&self.conn, self.root).ok().map(Mutex::new);
+        let valuators = valuators.as_ref();
+        let mut last_pointer = None;
         loop {
             let event = self.conn.wait_for_event()?;
-            if let Some(our_event) = self.convert_to_event(event)? {
+            if let (x11rb::protocol::Event::XinputRawMotion(motion), Some(valuators)) = (&event, valuators) {
+                let (delta, scroll) = self.raw_motion(valuators, motion);
+                delta.into_iter().chain(scroll).for_each(&mut callback);
+                continue;
+            }
+            if let Some(our_event) = self.convert_to_event(event, valuators)? {
+                // Without raw motion, deltas are worked out from positions
+                if let (&Event::MouseMove { x, y }, None) = (&our_event, valuators) {
+                    if let Some((last_x, last_y)) = last_pointer.replace((x, y)) {
+                        if (x, y) != (last_x, last_y) {
+                            callback(Event::MouseMoveRelative { dx: x - last_x, dy: y - last_y });
+                        }
+                    }
+                }
                 callback(our_event);
             }
         }
     }
 
+    /// Injects an input event. Events with no X11 equivalent here, such as
+    /// a key the keymap has no keycode for, fail with `Unsupported`;
+    /// control events like `ScreenSwitch` inject nothing.
     pub fn simulate_event(&self, event: &Event) -> Result<(), X11Error> {
+        let clamp = |value: i32| value.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
         match event {
             Event::MouseMove { x, y } => {
                 self.conn.warp_pointer(
@@ -113,23 +211,149 @@ impl X11Platform {
                     self.root,
                     0, 0,
                     0, 0,
-                    *x as i16, *y as i16,
+                    clamp(*x), clamp(*y),
                 ).map_err(|e| X11Error::GrabError(e.to_string()))?;
             }
-            Event::MouseButton { button: _, pressed: _ } => {
-                // TODO: Implement mouse button simulation
+            Event::MouseMoveRelative { dx, dy } => {
+                // Relative XTest motion goes through the pointer like a
+                // real mouse, so applications that confine or warp it
+                // still see the movement
+                self.conn.xtest_fake_input(xproto::MOTION_NOTIFY_EVENT, 1, x11rb::CURRENT_TIME, x11rb::NONE, clamp(*dx), clamp(*dy), 0)
+                    .map_err(|e| X11Error::XInputError(e.to_string()))?;
             }
-            Event::KeyPress { code: _, .. } | Event::KeyRelease { code: _, .. } => {
-                // TODO: Implement keyboard event simulation
+            Event::Scroll { dx, dy } => self.inject_scroll(*dx, *dy)?,
+            Event::MouseButton { button, pressed } => {
+                let number = button.to_x11()
+                    .ok_or_else(|| X11Error::Unsupported(format!("{:?}, which has no X11 button number", button)))?;
+                let kind = if *pressed { xproto::BUTTON_PRESS_EVENT } else { xproto::BUTTON_RELEASE_EVENT };
+                self.conn.xtest_fake_input(kind, number, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)
+                    .map_err(|e| X11Error::XInputError(e.to_string()))?;
             }
-            _ => {}
+            Event::KeyPress { code, .. } | Event::KeyRelease { code, .. } => {
+                let keycode = self.x_keycode(*code)
+                    .ok_or_else(|| X11Error::Unsupported(format!("key {}, which has no keycode in this keymap", code)))?;
+                let kind = if matches!(event, Event::KeyPress { .. }) { xproto::KEY_PRESS_EVENT } else { xproto::KEY_RELEASE_EVENT };
+                self.conn.xtest_fake_input(kind, keycode, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)
+                    .map_err(|e| X11Error::XInputError(e.to_string()))?;
+            }
+            Event::ScreenSwitch { .. } | Event::Heartbeat | Event::Error(_) => return Ok(()),
         }
         self.conn.flush().map_err(|e| X11Error::GrabError(e.to_string()))?;
         Ok(())
     }
 
-    fn convert_to_event(&self, x_event: x11rb::protocol::Event) -> Result<Option<Event>, X11Error> {
+    fn keyboard(&self) -> std::sync::MutexGuard<'_, Keyboard> {
+        self.keyboard.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The key for an X keycode, by the name the keymap gives it or else as
+    /// an evdev code.
+    fn key_code(&self, keycode: u8) -> Option<KeyCode> {
+        self.keyboard().keymap.key_get_name(keycode.into())
+            .and_then(KeyCode::from_xkb_name)
+            .or_else(|| u16::from(keycode).checked_sub(8).and_then(KeyCode::from_evdev))
+    }
+
+    /// The X keycode for a key, the other way round.
+    fn x_keycode(&self, code: KeyCode) -> Option<u8> {
+        code.xkb_name()
+            .and_then(|name| self.keyboard().keymap.key_by_name(name))
+            .or_else(|| code.to_evdev().map(|evdev| u32::from(evdev) + 8))
+            .and_then(|keycode| u8::try_from(keycode).ok())
+    }
+
+    /// Clicks the wheel buttons for whole clicks of scrolling, keeping the
+    /// fractions for next time since XTest cannot scroll smoothly.
+    fn inject_scroll(&self, dx: f64, dy: f64) -> Result<(), X11Error> {
+        let mut remainder = self.scroll_remainder.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (remainder_x, remainder_y) = &mut *remainder;
+        // X wheel buttons: 4 up, 5 down, 6 left, 7 right
+        for (delta, remainder, negative, positive) in [(dy, remainder_y, 4, 5), (dx, remainder_x, 6, 7)] {
+            if !delta.is_finite() {
+                continue;
+            }
+            *remainder += delta;
+            let clicks = remainder.trunc();
+            *remainder -= clicks;
+            let button = if clicks < 0.0 { negative } else { positive };
+            for _ in 0..clicks.abs().min(MAX_SCROLL_CLICKS) as u32 {
+                for kind in [xproto::BUTTON_PRESS_EVENT, xproto::BUTTON_RELEASE_EVENT] {
+                    self.conn.xtest_fake_input(kind, button, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)
+                        .map_err(|e| X11Error::XInputError(e.to_string()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds up the valuators in a raw motion event: the pointer delta from
+    /// valuators 0 and 1 of relative devices, in whole pixels with the
+    /// fractions kept for next time, and the scrolling.
+    fn raw_motion(&self, valuators: &Mutex<Valuators>, motion: &xinput::RawMotionEvent) -> (Option<Event>, Option<Event>) {
+        let valuators = valuators.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let relative = valuators.relative.contains(&motion.sourceid);
+        let (mut dx, mut dy) = (0.0, 0.0);
+        let (mut scroll_x, mut scroll_y) = (0.0, 0.0);
+        let numbers = motion.valuator_mask.iter().enumerate().flat_map(|(word, bits)| {
+            (0..32).filter(move |bit| bits & (1 << bit) != 0).map(move |bit| (word * 32 + bit) as u16)
+        });
+        // Values come in the order of the set bits
+        for (number, value) in numbers.zip(&motion.axisvalues) {
+            if let Some(axis) = valuators.scroll.get(&(motion.sourceid, number)) {
+                let clicks = fp3232(value) / axis.increment;
+                if axis.horizontal {
+                    scroll_x += clicks;
+                } else {
+                    scroll_y += clicks;
+                }
+            } else if relative && number == 0 {
+                dx += fp3232(value);
+            } else if relative && number == 1 {
+                dy += fp3232(value);
+            }
+        }
+
+        let mut remainder = self.motion_remainder.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        remainder.0 += dx;
+        remainder.1 += dy;
+        let (whole_x, whole_y) = (remainder.0.trunc(), remainder.1.trunc());
+        remainder.0 -= whole_x;
+        remainder.1 -= whole_y;
+        let delta = (whole_x != 0.0 || whole_y != 0.0)
+            .then_some(Event::MouseMoveRelative { dx: whole_x as i32, dy: whole_y as i32 });
+        (delta, Event::new_scroll(scroll_x, scroll_y))
+    }
+
+    fn convert_to_event(
+        &self,
+        x_event: x11rb::protocol::Event,
+        valuators: Option<&Mutex<Valuators>>,
+    ) -> Result<Option<Event>, X11Error> {
         match x_event {
+            x11rb::protocol::Event::XinputHierarchy(_) => {
+                self.refresh_valuators(valuators)?;
+                Ok(None)
+            }
+            x11rb::protocol::Event::XinputDeviceChanged(changed) if changed.reason == xinput::ChangeReason::DEVICE_CHANGE => {
+                self.refresh_valuators(valuators)?;
+                Ok(None)
+            }
+            // The server emulates these from the scroll valuators for core
+            // clients, and we have the valuators already
+            x11rb::protocol::Event::ButtonPress(button) | x11rb::protocol::Event::ButtonRelease(button)
+                if (4..=7).contains(&button.detail) && valuators.is_some() =>
+            {
+                Ok(None)
+            }
+            x11rb::protocol::Event::ButtonPress(button) if (4..=7).contains(&button.detail) => {
+                Ok(match button.detail {
+                    4 => Event::new_scroll(0.0, -1.0),
+                    5 => Event::new_scroll(0.0, 1.0),
+                    6 => Event::new_scroll(-1.0, 0.0),
+                    _ => Event::new_scroll(1.0, 0.0),
+                })
+            }
+            x11rb::protocol::Event::ButtonRelease(button) if (4..=7).contains(&button.detail) => Ok(None),
             x11rb::protocol::Event::MotionNotify(motion) => {
                 Ok(Some(Event::MouseMove {
                     x: motion.event_x.into(),
@@ -137,42 +361,214 @@ impl X11Platform {
                 }))
             }
             x11rb::protocol::Event::ButtonPress(button) => {
-                Ok(Some(Event::MouseButton {
-                    button: button.detail,
-                    pressed: true,
-                }))
+                Ok(Event::new_mouse_button(button.detail, true))
             }
             x11rb::protocol::Event::ButtonRelease(button) => {
-                Ok(Some(Event::MouseButton {
-                    button: button.detail,
-                    pressed: false,
-                }))
+                Ok(Event::new_mouse_button(button.detail, false))
             }
             x11rb::protocol::Event::KeyPress(key) => {
-                let keycode = key.detail as u32;
-                let key_name = self.keymap.key_get_name(keycode)
-                    .ok_or(X11Error::KeymapError("Invalid keycode".to_string()))?
-                    .to_string();
-                
-                Ok(Some(Event::KeyPress {
-                    code: key.detail as u16,
-                    name: key_name,
-                }))
+                let keycode = key.detail.into();
+                let (keysym, text) = {
+                    let keyboard = self.keyboard();
+                    (keyboard.state.key_get_one_sym(keycode), keyboard.state.key_get_utf8(keycode))
+                };
+                // Control characters, as for Return or Ctrl+C, are no text
+                let text = if text.chars().any(char::is_control) { String::new() } else { text };
+                Ok(self.key_code(key.detail).map(|code| Event::KeyPress { code, keysym, text }))
             }
             x11rb::protocol::Event::KeyRelease(key) => {
-                let keycode = key.detail as u32;
-                let key_name = self.keymap.key_get_name(keycode)
-                    .ok_or(X11Error::KeymapError("Invalid keycode".to_string()))?
-                    .to_string();
-                
-                Ok(Some(Event::KeyRelease {
-                    code: key.detail as u16,
-                    name: key_name,
-                }))
+                let keysym = self.keyboard().state.key_get_one_sym(key.detail.into());
+                Ok(self.key_code(key.detail).map(|code| Event::KeyRelease { code, keysym }))
+            }
+            x11rb::protocol::Event::XkbStateNotify(state) if i32::from(state.device_id) == self.keyboard_device => {
+                self.keyboard().state.update_mask(
+                    state.base_mods.into(),
+                    state.latched_mods.into(),
+                    state.locked_mods.into(),
+                    state.base_group as xkb::LayoutIndex,
+                    state.latched_group as xkb::LayoutIndex,
+                    state.locked_group.into(),
+                );
+                Ok(None)
+            }
+            x11rb::protocol::Event::XkbNewKeyboardNotify(new) if i32::from(new.device_id) == self.keyboard_device => {
+                self.reload_keyboard()?;
+                Ok(None)
+            }
+            x11rb::protocol::Event::XkbMapNotify(map) if i32::from(map.device_id) == self.keyboard_device => {
+                self.reload_keyboard()?;
+                Ok(None)
             }
             _ => Ok(None),
         }
     }
+
+    /// Fetches the keymap and state again after the keyboard or its layout
+    /// changed.
+    fn reload_keyboard(&self) -> Result<(), X11Error> {
+        let keyboard = load_keyboard(&self.conn, &self.xkb_context, self.keyboard_device)?;
+        *self.keyboard() = keyboard;
+        Ok(())
+    }
+
+    /// Re-reads the scroll valuators after devices were added, removed or
+    /// changed.
+    fn refresh_valuators(&self, valuators: Option<&Mutex<Valuators>>) -> Result<(), X11Error> {
+        if let Some(valuators) = valuators {
+            let fresh = query_valuators(&self.conn)?;
+            *valuators.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = fresh;
+        }
+        Ok(())
+    }
+}
+
+/// Enables XKB on the connection. Returns the core keyboard's device id.
+fn setup_xkb(conn: &XCBConnection) -> Result<i32, X11Error> {
+    let (mut major, mut minor, mut base_event, mut base_error) = (0, 0, 0, 0);
+    let supported = xkb::x11::setup_xkb_extension(
+        conn,
+        xkb::x11::MIN_MAJOR_XKB_VERSION,
+        xkb::x11::MIN_MINOR_XKB_VERSION,
+        xkb::x11::SetupXkbExtensionFlags::NoFlags,
+        &mut major,
+        &mut minor,
+        &mut base_event,
+        &mut base_error,
+    );
+    if !supported {
+        return Err(X11Error::XkbError("server has no usable XKB extension".to_string()));
+    }
+    let device = xkb::x11::get_core_keyboard_device_id(conn);
+    if device < 0 {
+        return Err(X11Error::XkbError("no core keyboard".to_string()));
+    }
+    Ok(device)
+}
+
+/// Asks for the events that change the core keyboard's keymap or state.
+fn select_keyboard_events(conn: &XCBConnection, device: i32) -> Result<(), X11Error> {
+    let device_spec = xkb_ext::DeviceSpec::try_from(device)
+        .map_err(|_| X11Error::XkbError("no core keyboard".to_string()))?;
+    let events = xkb_ext::EventType::NEW_KEYBOARD_NOTIFY | xkb_ext::EventType::MAP_NOTIFY | xkb_ext::EventType::STATE_NOTIFY;
+    conn.xkb_select_events(
+        device_spec,
+        xkb_ext::EventType::from(0u16),
+        events,
+        xkb_ext::MapPart::from(0u16),
+        xkb_ext::MapPart::from(0u16),
+        &xkb_ext::SelectEventsAux::new(),
+    )?
+    .check()?;
+    Ok(())
+}
+
+fn load_keyboard(conn: &XCBConnection, context: &xkb::Context, device: i32) -> Result<Keyboard, X11Error> {
+    let keymap = xkb::x11::keymap_new_from_device(context, conn, device, xkb::KEYMAP_COMPILE_NO_FLAGS);
+    if keymap.get_raw_ptr().is_null() {
+        return Err(X11Error::KeymapError("Failed to fetch the keymap from the server".to_string()));
+    }
+    let state = xkb::x11::state_new_from_device(&keymap, conn, device);
+    if state.get_raw_ptr().is_null() {
+        return Err(X11Error::KeymapError("Failed to fetch the keyboard state from the server".to_string()));
+    }
+    Ok(Keyboard { keymap, state })
+}
+
+/// Asks for raw motion and device changes from XInput 2.1, and returns what
+/// the valuators mean. Fails if the server does not have XInput 2.1.
+fn select_raw_events(conn: &XCBConnection, root: Window) -> Result<Valuators, X11Error> {
+    let version = conn.xinput_xi_query_version(2, 1)?.reply()?;
+    if (version.major_version, version.minor_version) < (2, 1) {
+        return Err(X11Error::XInputError(format!(
+            "server has XInput {}.{}, raw motion and smooth scrolling need 2.1",
+            version.major_version, version.minor_version
+        )));
+    }
+    // Raw events reach the root window even while the pointer is grabbed
+    conn.xinput_xi_select_events(root, &[
+        xinput::EventMask { deviceid: xinput::Device::ALL_MASTER.into(), mask: vec![xinput::XIEventMask::RAW_MOTION] },
+        xinput::EventMask {
+            deviceid: xinput::Device::ALL.into(),
+            mask: vec![xinput::XIEventMask::HIERARCHY | xinput::XIEventMask::DEVICE_CHANGED],
+        },
+    ])?;
+    query_valuators(conn)
+}
+
+fn query_valuators(conn: &XCBConnection) -> Result<Valuators, X11Error> {
+    let devices = conn.xinput_xi_query_device(xinput::Device::ALL)?.reply()?;
+    let mut valuators = Valuators::default();
+    for device in devices.infos {
+        for class in device.classes {
+            match class.data {
+                xinput::DeviceClassData::Scroll(scroll) => {
+                    let increment = fp3232(&scroll.increment);
+                    if increment != 0.0 {
+                        let horizontal = scroll.scroll_type == xinput::ScrollType::HORIZONTAL;
+                        valuators.scroll.insert((device.deviceid, scroll.number), ScrollAxis { horizontal, increment });
+                    }
+                }
+                xinput::DeviceClassData::Valuator(valuator)
+                    if valuator.number == 0 && valuator.mode == xinput::ValuatorMode::RELATIVE =>
+                {
+                    valuators.relative.insert(device.deviceid);
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(valuators)
+}
+
+fn fp3232(value: &xinput::Fp3232) -> f64 {
+    value.integral as f64 + value.frac as f64 / (1u64 << 32) as f64
+}
+
+#[derive(Clone)]
+pub struct InputGrab {
+    conn: Arc<XCBConnection>,
+    root: Window,
+}
+
+impl InputGrab {
+    pub fn grab(&self) -> Result<(), X11Error> {
+        // Grab keyboard
+        self.conn.grab_keyboard(
+            false,
+            self.root,
+            x11rb::CURRENT_TIME,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+        ).map_err(|e| X11Error::GrabError(e.to_string()))?
+        .reply()
+        .map_err(|e| X11Error::GrabError(e.to_string()))?;
+
+        // Grab pointer (mouse)
+        self.conn.grab_pointer(
+            false,
+            self.root,
+            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | 
+            EventMask::POINTER_MOTION | EventMask::BUTTON_MOTION,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+            self.root,
+            self.root,
+            x11rb::CURRENT_TIME,
+        ).map_err(|e| X11Error::GrabError(e.to_string()))?
+        .reply()
+        .map_err(|e| X11Error::GrabError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn ungrab(&self) -> Result<(), X11Error> {
+        self.conn.ungrab_keyboard(x11rb::CURRENT_TIME)
+            .map_err(|e| X11Error::GrabError(e.to_string()))?;
+        self.conn.ungrab_pointer(x11rb::CURRENT_TIME)
+            .map_err(|e| X11Error::GrabError(e.to_string()))?;
+        self.conn.flush()?;
+        Ok(())
+    }
 }
 
 impl Drop for X11Platform {
@@ -0,0 +1,224 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::network::barrier::client::BarrierClient;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::barrier::{key_code, key_name, key_text, keysym, BarrierConnection, Dialect, Message};
+use rust_barrier::network::handshake::{HandshakeError, ScreenInfo};
+use rust_barrier::network::NetworkError;
+use tokio::net::{TcpListener, TcpStream};
+
+const SCREEN: ScreenInfo = ScreenInfo { width: 1920, height: 1080 };
+
+fn all_messages() -> Vec<Message> {
+    vec![
+        Message::Hello { dialect: Dialect::Barrier, major: 1, minor: 6 },
+        Message::HelloBack { dialect: Dialect::Barrier, major: 1, minor: 6, name: "laptop".to_string() },
+        Message::Hello { dialect: Dialect::Synergy, major: 1, minor: 6 },
+        Message::HelloBack { dialect: Dialect::Synergy, major: 1, minor: 6, name: "laptop".to_string() },
+        Message::QueryInfo,
+        Message::Info { x: 0, y: 0, width: 1920, height: 1080, warp: 0, cursor_x: 5, cursor_y: -6 },
+        Message::InfoAck,
+        Message::KeepAlive,
+        Message::NoOp,
+        Message::Enter { x: 1, y: 2, seq: 3, modifiers: 4 },
+        Message::Leave,
+        Message::Close,
+        Message::KeyDown { id: 0x61, modifiers: 0, button: 38 },
+        Message::KeyRepeat { id: 0x61, modifiers: 0, count: 2, button: 38 },
+        Message::KeyUp { id: 0x61, modifiers: 0, button: 38 },
+        Message::MouseDown { button: 1 },
+        Message::MouseUp { button: 1 },
+        Message::MouseMove { x: 100, y: 200 },
+        Message::MouseMoveRelative { dx: -3, dy: 4 },
+        Message::Wheel { dx: 0, dy: -240 },
+        Message::ResetOptions,
+        Message::SetOptions(vec![0x4844_4243, 1]),
+        Message::Incompatible { major: 1, minor: 3 },
+        Message::Busy,
+        Message::UnknownClient,
+        Message::Bad,
+        Message::Other { code: *b"DCLP", body: vec![1, 2, 3] },
+    ]
+}
+
+/// A stand-in for a Barrier or Synergy server: sends the hello and returns
+/// the connection once the client has answered it in the same dialect.
+async fn accept(listener: &TcpListener, dialect: Dialect) -> BarrierConnection {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut conn = BarrierConnection::new(stream);
+    conn.send(&Message::Hello { dialect, major: 1, minor: 6 }).await.unwrap();
+    assert_eq!(
+        conn.receive().await.unwrap(),
+        Message::HelloBack { dialect, major: 1, minor: 6, name: "laptop".to_string() }
+    );
+    conn
+}
+
+#[test]
+fn test_messages_round_trip() {
+    for message in all_messages() {
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+}
+
+#[test]
+fn test_wire_format() {
+    assert_eq!(Message::Hello { dialect: Dialect::Barrier, major: 1, minor: 6 }.encode(), b"Barrier\x00\x01\x00\x06");
+    assert_eq!(Message::MouseMove { x: 100, y: -1 }.encode(), b"DMMV\x00\x64\xff\xff");
+    assert_eq!(
+        Message::HelloBack { dialect: Dialect::Barrier, major: 1, minor: 6, name: "pc".to_string() }.encode(),
+        b"Barrier\x00\x01\x00\x06\x00\x00\x00\x02pc"
+    );
+    // Synergy 1 opens the same way under another name
+    assert_eq!(
+        Message::decode(b"Synergy\x00\x01\x00\x06").unwrap(),
+        Message::Hello { dialect: Dialect::Synergy, major: 1, minor: 6 }
+    );
+    assert_eq!(
+        Message::HelloBack { dialect: Dialect::Synergy, major: 1, minor: 6, name: "pc".to_string() }.encode(),
+        b"Synergy\x00\x01\x00\x06\x00\x00\x00\x02pc"
+    );
+    assert!(Message::decode(b"DMMV\x00").is_err());
+}
+
+#[test]
+fn test_key_names() {
+    assert_eq!(key_name(0x61), "a");
+    assert_eq!(key_name(0x20), "space");
+    assert_eq!(key_name(0xEF0D), "Return");
+    assert_eq!(key_name(0xEFE1), "Shift_L");
+    assert_eq!(key_name(0xEFC9), "F12");
+    assert_eq!(key_name(0xE0F0), "0xE0F0");
+}
+
+#[test]
+fn test_key_codes() {
+    // Ids are what the key types on a US layout, shifted or not
+    assert_eq!(key_code(0x61), Some(KeyCode(0x04)));
+    assert_eq!(key_code(0x41), Some(KeyCode(0x04)));
+    assert_eq!(key_code(0x21), Some(KeyCode(0x1E)));
+    assert_eq!(key_code(0x22), Some(KeyCode(0x34)));
+    assert_eq!(key_code(0xEF0D), Some(KeyCode(0x28)));
+    assert_eq!(key_code(0xEFE1), Some(KeyCode(0xE1)));
+    assert_eq!(key_code(0xEFD5), Some(KeyCode(0x73)));
+    assert_eq!(key_code(0xE0F0), None);
+}
+
+#[test]
+fn test_keysyms() {
+    assert_eq!((keysym(0x61), key_text(0x61)), (0x61, "a".to_string()));
+    assert_eq!((keysym(0xE9), key_text(0xE9)), (0xE9, "é".to_string()));
+    assert_eq!((keysym(0x20AC), key_text(0x20AC)), (0x10020AC, "€".to_string()));
+    assert_eq!((keysym(0xEF0D), key_text(0xEF0D)), (0xFF0D, String::new()));
+    assert_eq!(keysym(0xEFBE), 0xFFBE);
+    assert_eq!((keysym(0xE0F0), key_text(0xE0F0)), (0, String::new()));
+}
+
+#[tokio::test]
+async fn test_session_with_scripted_server() {
+    let listener = TcpListener::bind("127.0.0.1:8220").await.unwrap();
+    let server = tokio::spawn(async move {
+        let mut conn = accept(&listener, Dialect::Barrier).await;
+        conn.send(&Message::QueryInfo).await.unwrap();
+        assert_eq!(
+            conn.receive().await.unwrap(),
+            Message::Info { x: 0, y: 0, width: 1920, height: 1080, warp: 0, cursor_x: 960, cursor_y: 540 }
+        );
+        conn.send(&Message::InfoAck).await.unwrap();
+        conn.send(&Message::ResetOptions).await.unwrap();
+        conn.send(&Message::SetOptions(vec![1, 2])).await.unwrap();
+        conn.send(&Message::KeepAlive).await.unwrap();
+        assert_eq!(conn.receive().await.unwrap(), Message::KeepAlive);
+
+        for message in [
+            Message::Enter { x: 0, y: 300, seq: 1, modifiers: 0 },
+            Message::MouseMove { x: 10, y: 300 },
+            Message::MouseMoveRelative { dx: 2, dy: -1 },
+            Message::MouseDown { button: 1 },
+            Message::MouseUp { button: 1 },
+            Message::Other { code: *b"DCLP", body: vec![0; 8] },
+            Message::KeyDown { id: 0x61, modifiers: 0, button: 38 },
+            Message::KeyUp { id: 0x61, modifiers: 0, button: 38 },
+            Message::Wheel { dx: 0, dy: 240 },
+            Message::Leave,
+            Message::Close,
+        ] {
+            conn.send(&message).await.unwrap();
+        }
+    });
+
+    let stream = TcpStream::connect("127.0.0.1:8220").await.unwrap();
+    let mut client = BarrierClient::connect(stream, "laptop", SCREEN).await.unwrap();
+    assert_eq!(client.server_version(), (1, 6));
+    assert_eq!(client.dialect(), Dialect::Barrier);
+
+    let mut events = Vec::new();
+    let error = loop {
+        match client.receive_event().await {
+            Ok(event) => events.push(event),
+            Err(e) => break e,
+        }
+    };
+    let button = |button, pressed| Event::MouseButton { button, pressed };
+    assert_eq!(
+        events,
+        [
+            Event::ScreenSwitch { to_screen: "laptop".to_string() },
+            Event::MouseMove { x: 0, y: 300 },
+            Event::MouseMove { x: 10, y: 300 },
+            Event::MouseMoveRelative { dx: 2, dy: -1 },
+            button(MouseButton::Left, true),
+            button(MouseButton::Left, false),
+            Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() },
+            Event::KeyRelease { code: KeyCode(0x04), keysym: 0x61 },
+            // Two notches up
+            Event::Scroll { dx: 0.0, dy: -2.0 },
+            Event::ScreenSwitch { to_screen: String::new() },
+        ]
+    );
+    assert!(matches!(error, NetworkError::Connection(_)), "{}", error);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_synergy_server_is_answered_as_synergy() {
+    let listener = TcpListener::bind("127.0.0.1:8255").await.unwrap();
+    let server = tokio::spawn(async move {
+        let mut conn = accept(&listener, Dialect::Synergy).await;
+        conn.send(&Message::Enter { x: 5, y: 6, seq: 1, modifiers: 0 }).await.unwrap();
+    });
+
+    let stream = TcpStream::connect("127.0.0.1:8255").await.unwrap();
+    let mut client = BarrierClient::connect(stream, "laptop", SCREEN).await.unwrap();
+    assert_eq!(client.dialect(), Dialect::Synergy);
+    assert_eq!(client.receive_event().await.unwrap(), Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unknown_screen_is_refused() {
+    let listener = TcpListener::bind("127.0.0.1:8221").await.unwrap();
+    tokio::spawn(async move {
+        let mut conn = accept(&listener, Dialect::Barrier).await;
+        conn.send(&Message::UnknownClient).await.unwrap();
+    });
+
+    let stream = TcpStream::connect("127.0.0.1:8221").await.unwrap();
+    let mut client = BarrierClient::connect(stream, "laptop", SCREEN).await.unwrap();
+    let error = client.receive_event().await.unwrap_err();
+    assert!(matches!(error, NetworkError::Handshake(HandshakeError::Rejected(_))), "{}", error);
+}
+
+#[tokio::test]
+async fn test_incompatible_server_version() {
+    let listener = TcpListener::bind("127.0.0.1:8222").await.unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut conn = BarrierConnection::new(stream);
+        conn.send(&Message::Hello { dialect: Dialect::Barrier, major: 2, minor: 0 }).await.unwrap();
+        let _ = conn.receive().await;
+    });
+
+    let stream = TcpStream::connect("127.0.0.1:8222").await.unwrap();
+    let result = BarrierClient::connect(stream, "laptop", SCREEN).await;
+    assert!(matches!(result, Err(NetworkError::Handshake(HandshakeError::Rejected(_)))));
+}
@@ -0,0 +1,276 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::barrier::client::BarrierClient;
+use rust_barrier::network::barrier::server::{self, BarrierScreen};
+use rust_barrier::network::barrier::{key_id, key_text, keysym, keysym_key_id, modifiers, BarrierConnection, Dialect, Message};
+use rust_barrier::network::handshake::{HandshakeError, ScreenInfo};
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::NetworkError;
+use std::num::NonZeroU8;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// What a stock Barrier 2.4 client sends for a 1920x1080 screen named
+// "laptop", length prefixes included
+const HELLO_BACK: &[u8] = b"\x00\x00\x00\x15Barrier\x00\x01\x00\x06\x00\x00\x00\x06laptop";
+const INFO: &[u8] = b"\x00\x00\x00\x12DINF\x00\x00\x00\x00\x07\x80\x04\x38\x00\x00\x03\xc0\x02\x1c";
+const KEEPALIVE: &[u8] = b"\x00\x00\x00\x04CALV";
+
+fn switch(to: &str) -> Event {
+    Event::ScreenSwitch { to_screen: to.to_string() }
+}
+
+fn key(name: &str, pressed: bool) -> Event {
+    let code = KeyCode::from_xkb_name(name).unwrap();
+    let id = key_id(name).unwrap();
+    if pressed {
+        Event::KeyPress { code, keysym: keysym(id), text: key_text(id) }
+    } else {
+        Event::KeyRelease { code, keysym: keysym(id) }
+    }
+}
+
+/// A screen named "laptop", and the client's end of its connection.
+async fn laptop() -> (BarrierScreen, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(HELLO_BACK).await.unwrap();
+        stream.write_all(INFO).await.unwrap();
+        stream
+    });
+    let (stream, _) = listener.accept().await.unwrap();
+    (BarrierScreen::accept(stream, Dialect::Barrier).await.unwrap(), client.await.unwrap())
+}
+
+/// Reads one length-prefixed message off a raw stream.
+async fn read_message(stream: &mut TcpStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await.unwrap();
+    let mut body = vec![0; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut body).await.unwrap();
+    [len.as_slice(), &body].concat()
+}
+
+#[test]
+fn test_key_ids() {
+    assert_eq!(key_id("a"), Some(0x61));
+    assert_eq!(key_id("Return"), Some(0xEF0D));
+    assert_eq!(key_id("F12"), Some(0xEFC9));
+    assert_eq!(key_id("0xE0F0"), Some(0xE0F0));
+    // XKB key names as captured from X11
+    assert_eq!(key_id("AC01"), Some(0x61));
+    assert_eq!(key_id("AE10"), Some(0x30));
+    assert_eq!(key_id("LFSH"), Some(0xEFE1));
+    assert_eq!(key_id("FK05"), Some(0xEFC2));
+    assert_eq!(key_id("SPCE"), Some(0x20));
+    assert_eq!(key_id("nonsense"), None);
+}
+
+#[test]
+fn test_keysym_key_ids() {
+    for id in [0x61, 0x41, 0x20, 0xE9, 0x20AC, 0xEF0D, 0xEFE1, 0xEFBE] {
+        assert_eq!(keysym_key_id(keysym(id)), Some(id));
+    }
+    assert_eq!(keysym_key_id(0), None);
+    // Legacy Cyrillic keysyms have no Unicode code point here
+    assert_eq!(keysym_key_id(0x6E3), None);
+}
+
+#[tokio::test]
+async fn test_translates_events() {
+    let (mut screen, _client) = laptop().await;
+    assert_eq!(screen.name(), "laptop");
+    assert_eq!(screen.client_version(), (1, 6));
+    assert_eq!(screen.screen(), ScreenInfo { width: 1920, height: 1080 });
+    assert!(!screen.is_active());
+
+    // Input before entering is passed on; the hub only routes it here once
+    // the screen is active
+    assert_eq!(screen.translate(&Event::MouseMove { x: 5000, y: -3 }), [Message::MouseMove { x: 1919, y: 0 }]);
+    assert_eq!(screen.translate(&switch("desk")), []);
+    assert_eq!(screen.translate(&switch("laptop")), [Message::Enter { x: 1919, y: 0, seq: 1, modifiers: 0 }]);
+    assert_eq!(screen.translate(&switch("laptop")), []);
+    assert!(screen.is_active());
+
+    assert_eq!(
+        screen.translate(&Event::MouseMoveRelative { dx: -100_000, dy: 4 }),
+        [Message::MouseMoveRelative { dx: i16::MIN, dy: 4 }]
+    );
+    let button = |button, pressed| Event::MouseButton { button, pressed };
+    assert_eq!(screen.translate(&button(MouseButton::Right, true)), [Message::MouseDown { button: 3 }]);
+    assert_eq!(screen.translate(&button(MouseButton::Back, false)), [Message::MouseUp { button: 4 }]);
+    assert_eq!(screen.translate(&button(MouseButton::Extra(NonZeroU8::new(2).unwrap()), true)), [Message::MouseDown { button: 7 }]);
+    assert_eq!(screen.translate(&Event::Scroll { dx: 0.0, dy: -1.0 }), [Message::Wheel { dx: 0, dy: 120 }]);
+    assert_eq!(screen.translate(&Event::Scroll { dx: -1.0, dy: 0.0 }), [Message::Wheel { dx: -120, dy: 0 }]);
+    // Smooth scrolling keeps its fractions
+    assert_eq!(screen.translate(&Event::Scroll { dx: 0.5, dy: 2.25 }), [Message::Wheel { dx: 60, dy: -270 }]);
+    assert_eq!(screen.translate(&Event::Scroll { dx: 0.001, dy: 0.0 }), []);
+
+    assert_eq!(screen.translate(&key("LFSH", true)), [Message::KeyDown { id: 0xEFE1, modifiers: 0, button: 50 }]);
+    assert_eq!(
+        screen.translate(&key("AC01", true)),
+        [Message::KeyDown { id: 0x61, modifiers: modifiers::SHIFT, button: 38 }]
+    );
+    assert_eq!(screen.translate(&key("LFSH", false)), [Message::KeyUp { id: 0xEFE1, modifiers: 0, button: 50 }]);
+    assert_eq!(screen.translate(&key("CAPS", true)), [Message::KeyDown { id: 0xEFE5, modifiers: 0, button: 66 }]);
+    assert_eq!(
+        screen.translate(&key("CAPS", false)),
+        [Message::KeyUp { id: 0xEFE5, modifiers: modifiers::CAPS_LOCK, button: 66 }]
+    );
+    // Keys type what the sender's layout made of them: on AZERTY the key
+    // US layouts have Q at types a, and AltGr+E the euro sign
+    assert_eq!(
+        screen.translate(&Event::KeyPress { code: KeyCode(0x14), keysym: 0x61, text: "a".to_string() }),
+        [Message::KeyDown { id: 0x61, modifiers: modifiers::CAPS_LOCK, button: 24 }]
+    );
+    assert_eq!(
+        screen.translate(&Event::KeyRelease { code: KeyCode(0x08), keysym: 0x10020AC }),
+        [Message::KeyUp { id: 0x20AC, modifiers: modifiers::CAPS_LOCK, button: 26 }]
+    );
+    // Keysyms without an id fall back to the US layout
+    assert_eq!(
+        screen.translate(&Event::KeyPress { code: KeyCode(0x06), keysym: 0x6E3, text: "ц".to_string() }),
+        [Message::KeyDown { id: 0x63, modifiers: modifiers::CAPS_LOCK, button: 54 }]
+    );
+    // Keys outside our table have no id
+    assert_eq!(screen.translate(&Event::KeyPress { code: KeyCode(0x03), keysym: 0, text: String::new() }), []);
+    assert_eq!(screen.translate(&Event::Heartbeat), []);
+
+    assert_eq!(screen.translate(&switch("desk")), [Message::Leave]);
+    assert_eq!(screen.translate(&switch("laptop")), [Message::Enter { x: 0, y: 4, seq: 2, modifiers: modifiers::CAPS_LOCK }]);
+}
+
+#[tokio::test]
+async fn test_recorded_session() {
+    let listener = TcpListener::bind("127.0.0.1:8230").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move { server::serve(&serving, listener, Dialect::Barrier).await });
+
+    let mut client = TcpStream::connect("127.0.0.1:8230").await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x0bBarrier\x00\x01\x00\x06");
+    client.write_all(HELLO_BACK).await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04QINF");
+    client.write_all(INFO).await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04CIAK");
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04CROP");
+
+    assert!(matches!(hub_events.recv().await.unwrap(), HubEvent::Joined { screen, .. } if screen == "laptop"));
+    // The pointer enters where the client said it was
+    hub.dispatch(switch("laptop"));
+    assert_eq!(hub.active_screen().as_deref(), Some("laptop"));
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x0eCINN\x03\xc0\x02\x1c\x00\x00\x00\x01\x00\x00");
+
+    hub.dispatch(Event::MouseMove { x: 10, y: 20 });
+    hub.dispatch(key("AC01", true));
+    hub.dispatch(Event::MouseButton { button: MouseButton::Left, pressed: true });
+    hub.dispatch(Event::Scroll { dx: 0.0, dy: 1.0 });
+    hub.dispatch(switch("desk"));
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x08DMMV\x00\x0a\x00\x14");
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x0aDKDN\x00\x61\x00\x00\x00\x26");
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x05DMDN\x01");
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x08DMWM\x00\x00\xff\x88");
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04COUT");
+    assert_eq!(hub.active_screen(), None);
+
+    // Keepalives are echoed back, and are all the server sends while idle
+    assert_eq!(read_message(&mut client).await, KEEPALIVE);
+    client.write_all(KEEPALIVE).await.unwrap();
+
+    drop(client);
+    let left = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let HubEvent::Left { screen, .. } = hub_events.recv().await.unwrap() {
+                return screen;
+            }
+        }
+    });
+    assert_eq!(left.await.unwrap(), "laptop");
+    assert!(hub.screens().is_empty());
+}
+
+#[tokio::test]
+async fn test_synergy_client_is_greeted_as_synergy() {
+    let listener = TcpListener::bind("127.0.0.1:8256").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move { server::serve(&serving, listener, Dialect::Synergy).await });
+
+    let mut client = TcpStream::connect("127.0.0.1:8256").await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x0bSynergy\x00\x01\x00\x06");
+    client.write_all(b"\x00\x00\x00\x15Synergy\x00\x01\x00\x06\x00\x00\x00\x06laptop").await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04QINF");
+    client.write_all(INFO).await.unwrap();
+    assert_eq!(read_message(&mut client).await, b"\x00\x00\x00\x04CIAK");
+    assert!(matches!(hub_events.recv().await.unwrap(), HubEvent::Joined { screen, .. } if screen == "laptop"));
+}
+
+#[tokio::test]
+async fn test_incompatible_client_is_refused() {
+    let listener = TcpListener::bind("127.0.0.1:8231").await.unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        BarrierScreen::accept(stream, Dialect::Barrier).await
+    });
+
+    let mut client = BarrierConnection::new(TcpStream::connect("127.0.0.1:8231").await.unwrap());
+    assert_eq!(client.receive().await.unwrap(), Message::Hello { dialect: Dialect::Barrier, major: 1, minor: 6 });
+    client.send(&Message::HelloBack { dialect: Dialect::Barrier, major: 2, minor: 0, name: "laptop".to_string() }).await.unwrap();
+    assert_eq!(client.receive().await.unwrap(), Message::Incompatible { major: 1, minor: 6 });
+    let error = server.await.unwrap().err().unwrap();
+    assert!(matches!(error, NetworkError::Handshake(HandshakeError::Rejected(_))), "{}", error);
+}
+
+#[tokio::test]
+async fn test_our_barrier_client_against_our_server() {
+    let listener = TcpListener::bind("127.0.0.1:8232").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move { server::serve(&serving, listener, Dialect::Barrier).await });
+
+    let stream = TcpStream::connect("127.0.0.1:8232").await.unwrap();
+    let screen = ScreenInfo { width: 1280, height: 800 };
+    let mut client = BarrierClient::connect(stream, "laptop", screen).await.unwrap();
+    // The client is registered once it answered the screen info query,
+    // which it does while waiting for events
+    let switching = hub.clone();
+    tokio::spawn(async move {
+        while !matches!(hub_events.recv().await.unwrap(), HubEvent::Joined { .. }) {}
+        switching.dispatch(switch("laptop"));
+    });
+    let entered = client.receive_event().await.unwrap();
+    assert_eq!(entered, switch("laptop"));
+    assert_eq!(client.receive_event().await.unwrap(), Event::MouseMove { x: 640, y: 400 });
+
+    let sent = [
+        Event::MouseMove { x: 100, y: 200 },
+        Event::MouseButton { button: MouseButton::Forward, pressed: true },
+        key("RTRN", true),
+        key("RTRN", false),
+    ];
+    for event in &sent {
+        assert!(hub.dispatch(event.clone()));
+    }
+    hub.dispatch(switch("desk"));
+
+    let mut received = Vec::new();
+    for _ in 0..5 {
+        received.push(client.receive_event().await.unwrap());
+    }
+    assert_eq!(
+        received,
+        [
+            Event::MouseMove { x: 100, y: 200 },
+            Event::MouseButton { button: MouseButton::Forward, pressed: true },
+            key("RTRN", true),
+            key("RTRN", false),
+            switch(""),
+        ]
+    );
+}
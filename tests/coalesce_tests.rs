@@ -0,0 +1,154 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::coalesce::{CoalesceConfig, MotionCoalescer};
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::Hub;
+use rust_barrier::network::NetworkConnection;
+use std::time::Duration;
+use tokio::net::TcpSocket;
+
+fn motion(x: i32) -> Event {
+    Event::MouseMove { x, y: 0 }
+}
+
+fn key(code: u16) -> Event {
+    Event::KeyPress { code: KeyCode(code), keysym: 0, text: String::new() }
+}
+
+#[test]
+fn test_merges_consecutive_motion() {
+    let mut coalescer = MotionCoalescer::default();
+    for x in 0..10 {
+        coalescer.push(motion(x));
+    }
+    assert_eq!(coalescer.len(), 1);
+    assert_eq!(coalescer.merged(), 9);
+    assert_eq!(coalescer.pop(), Some(motion(9)));
+    assert!(coalescer.is_empty());
+}
+
+#[test]
+fn test_never_merges_across_keys_or_buttons() {
+    let mut coalescer = MotionCoalescer::default();
+    coalescer.push(motion(1));
+    coalescer.push(motion(2));
+    coalescer.push(key(10));
+    coalescer.push(motion(3));
+    coalescer.push(Event::MouseButton { button: MouseButton::Left, pressed: true });
+    coalescer.push(motion(4));
+    coalescer.push(motion(5));
+
+    let sent: Vec<_> = std::iter::from_fn(|| coalescer.pop()).collect();
+    assert_eq!(
+        sent,
+        [motion(2), key(10), motion(3), Event::MouseButton { button: MouseButton::Left, pressed: true }, motion(5)]
+    );
+}
+
+#[tokio::test]
+async fn test_paces_motion_to_rtt() {
+    let mut coalescer = MotionCoalescer::new(CoalesceConfig {
+        rtt_fraction: 0.5,
+        max_interval: Duration::from_millis(500),
+    });
+    assert_eq!(coalescer.motion_interval(), Duration::ZERO);
+    coalescer.observe_rtt(Duration::from_millis(200));
+    assert_eq!(coalescer.motion_interval(), Duration::from_millis(100));
+
+    coalescer.push(motion(1));
+    assert_eq!(coalescer.pop_ready(), Some(motion(1)));
+
+    // Too soon after the last motion: it waits and absorbs newer positions
+    coalescer.push(motion(2));
+    coalescer.push(motion(3));
+    assert_eq!(coalescer.pop_ready(), None);
+
+    // A key flushes the pending motion ahead of it without waiting
+    coalescer.push(key(1));
+    assert_eq!(coalescer.pop_ready(), Some(motion(3)));
+    assert_eq!(coalescer.pop_ready(), Some(key(1)));
+}
+
+#[test]
+fn test_pacing_is_capped() {
+    let mut coalescer = MotionCoalescer::default();
+    coalescer.observe_rtt(Duration::from_secs(5));
+    assert_eq!(coalescer.motion_interval(), CoalesceConfig::default().max_interval);
+
+    // Smoothing moves an eighth of the way towards each new sample
+    let mut coalescer = MotionCoalescer::default();
+    coalescer.observe_rtt(Duration::from_millis(80));
+    coalescer.observe_rtt(Duration::from_millis(160));
+    assert_eq!(coalescer.smoothed_rtt(), Some(Duration::from_millis(90)));
+}
+
+#[tokio::test]
+async fn test_backed_up_client_gets_latest_position() {
+    // Small socket buffers so the link backs up quickly
+    let listen_socket = TcpSocket::new_v4().unwrap();
+    listen_socket.set_send_buffer_size(4096).unwrap();
+    listen_socket.bind("127.0.0.1:8180".parse().unwrap()).unwrap();
+    let listener = listen_socket.listen(8).unwrap();
+
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let client_socket = TcpSocket::new_v4().unwrap();
+    client_socket.set_recv_buffer_size(4096).unwrap();
+    let stream = client_socket.connect("127.0.0.1:8180".parse().unwrap()).await.unwrap();
+    let mut client = NetworkConnection::new(stream);
+    client.client_handshake(Hello::new("laptop")).await.unwrap();
+    while hub.screens().is_empty() {
+        hub_events.recv().await.unwrap();
+    }
+    hub.dispatch(Event::ScreenSwitch { to_screen: "laptop".to_string() });
+
+    // The client is not reading, so almost all of this piles up
+    const SAMPLES: i32 = 20_000;
+    for x in 0..SAMPLES {
+        hub.dispatch(motion(x));
+        if x % 5000 == 4999 {
+            hub.dispatch(key(x as u16));
+        }
+        if x % 1000 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(client.receive_event().await.unwrap(), Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    let mut received = Vec::new();
+    loop {
+        let event = client.receive_event().await.unwrap();
+        let last = event == key(SAMPLES as u16 - 1);
+        received.push(event);
+        if last {
+            break;
+        }
+    }
+
+    assert!(received.len() < SAMPLES as usize / 2, "{} events were not coalesced", received.len());
+    let keys: Vec<_> = received.iter().filter_map(|event| match event {
+        Event::KeyPress { code, .. } => Some(code.0),
+        _ => None,
+    }).collect();
+    assert_eq!(keys, [4999, 9999, 14999, 19999]);
+    assert!(received.contains(&motion(SAMPLES - 1)));
+    // Each key comes after the motion that preceded it and before what followed
+    let order: Vec<i32> = received.iter().map(|event| match event {
+        Event::MouseMove { x, .. } => *x,
+        Event::KeyPress { code, .. } => code.0 as i32,
+        other => panic!("unexpected {:?}", other),
+    }).collect();
+    assert!(order.windows(2).all(|pair| pair[0] <= pair[1]));
+}
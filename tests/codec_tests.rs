@@ -0,0 +1,95 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::codec::{CodecKind, EventCodec, JsonCodec};
+use rust_barrier::network::NetworkConnection;
+#[cfg(feature = "cbor")]
+use rust_barrier::network::NetworkError;
+use tokio::net::{TcpListener, TcpStream};
+
+fn sample_events() -> Vec<Event> {
+    vec![
+        Event::MouseMove { x: -100, y: 200 },
+        Event::MouseMoveRelative { dx: -3, dy: 7 },
+        Event::MouseButton { button: MouseButton::Right, pressed: false },
+        Event::Scroll { dx: -0.25, dy: 1.5 },
+        Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() },
+        Event::KeyRelease { code: KeyCode(0x04), keysym: 0x61 },
+        Event::ScreenSwitch { to_screen: "laptop".to_string() },
+        Event::Heartbeat,
+        Event::Error("lost".to_string()),
+    ]
+}
+
+fn assert_round_trip<C: EventCodec>(codec: C) {
+    for event in sample_events() {
+        let data = codec.encode(&event).unwrap();
+        assert_eq!(codec.decode(&data).unwrap(), event);
+    }
+}
+
+#[test]
+fn test_json_round_trip() {
+    assert_round_trip(JsonCodec);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn test_cbor_round_trip() {
+    assert_round_trip(rust_barrier::network::codec::CborCodec);
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn test_bincode_round_trip() {
+    assert_round_trip(rust_barrier::network::codec::BincodeCodec);
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn test_msgpack_round_trip() {
+    assert_round_trip(rust_barrier::network::codec::MsgPackCodec);
+}
+
+#[test]
+fn test_codec_kind_parsing() {
+    assert_eq!("json".parse::<CodecKind>().unwrap(), CodecKind::Json);
+    assert_eq!("MsgPack".parse::<CodecKind>().unwrap(), CodecKind::MsgPack);
+    assert!("xml".parse::<CodecKind>().is_err());
+    for kind in CodecKind::available() {
+        assert!(kind.codec().is_ok());
+        assert_eq!(kind.to_string().parse::<CodecKind>().unwrap(), kind);
+    }
+}
+
+#[tokio::test]
+async fn test_codec_per_connection() {
+    let listener = TcpListener::bind("127.0.0.1:8100").await.unwrap();
+
+    for kind in CodecKind::available() {
+        let client_stream = TcpStream::connect("127.0.0.1:8100").await.unwrap();
+        let (server_stream, _) = listener.accept().await.unwrap();
+
+        let mut client_conn = NetworkConnection::with_codec(client_stream, kind.codec().unwrap());
+        let mut server_conn = NetworkConnection::with_codec(server_stream, kind.codec().unwrap());
+
+        for event in sample_events() {
+            client_conn.send_event(event.clone()).await.unwrap();
+            assert_eq!(server_conn.receive_event().await.unwrap(), event);
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[tokio::test]
+async fn test_codec_mismatch_is_reported() {
+    let listener = TcpListener::bind("127.0.0.1:8101").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8101").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+
+    let mut client_conn = NetworkConnection::with_codec(client_stream, rust_barrier::network::codec::CborCodec);
+    let mut server_conn = NetworkConnection::new(server_stream);
+
+    client_conn.send_event(Event::Heartbeat).await.unwrap();
+    let result = server_conn.receive_event().await;
+    assert!(matches!(result, Err(NetworkError::Serialization(_))));
+}
@@ -0,0 +1,77 @@
+use rust_barrier::network::discovery::{self, Announcer, Beacon, BeaconListener, DiscoveryConfig};
+use rust_barrier::network::tls::Fingerprint;
+use rust_barrier::network::NetworkError;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+/// Beacons every 20ms on a group of the test's own, kept on loopback.
+fn loopback(port: u16) -> DiscoveryConfig {
+    DiscoveryConfig {
+        group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 24, 80), port),
+        interface: Ipv4Addr::LOCALHOST,
+        interval: Duration::from_millis(20),
+    }
+}
+
+fn fingerprint() -> Fingerprint {
+    Fingerprint::of(b"certificate")
+}
+
+fn beacon(name: &str, port: u16, fingerprint: Option<Fingerprint>) -> Beacon {
+    Beacon { name: name.to_string(), port, fingerprint }
+}
+
+/// Announces `beacon` until the test ends.
+fn announce(beacon: Beacon, config: DiscoveryConfig) {
+    let announcer = Announcer::new(&beacon, config).unwrap();
+    tokio::spawn(async move { announcer.run().await });
+}
+
+#[test]
+fn test_beacon_round_trip() {
+    for beacon in [beacon("desk", 8080, Some(fingerprint())), beacon("lab", 24800, None)] {
+        assert_eq!(Beacon::decode(&beacon.encode()), Some(beacon));
+    }
+    // Other traffic on the group is ignored
+    assert_eq!(Beacon::decode(br#"{"service":"other","name":"x","port":1}"#), None);
+    assert_eq!(Beacon::decode(b"\x00\x01garbage"), None);
+}
+
+#[tokio::test]
+async fn test_discovers_servers_on_loopback() {
+    let config = loopback(8240);
+    announce(beacon("desk", 8080, Some(fingerprint())), config);
+    announce(beacon("lab", 9000, None), config);
+
+    let servers = discovery::discover(config, Duration::from_millis(500)).await.unwrap();
+    let found: Vec<_> = servers.iter().map(|server| (server.name.as_str(), server.addr.to_string())).collect();
+    assert_eq!(found, [("desk", "127.0.0.1:8080".to_string()), ("lab", "127.0.0.1:9000".to_string())]);
+    assert_eq!(servers[0].fingerprint, Some(fingerprint()));
+    assert_eq!(servers[1].fingerprint, None);
+}
+
+#[tokio::test]
+async fn test_listeners_share_the_group() {
+    let config = loopback(8241);
+    let mut first = BeaconListener::bind(config).unwrap();
+    let mut second = BeaconListener::bind(config).unwrap();
+    Announcer::new(&beacon("desk", 8080, None), config).unwrap().announce().await.unwrap();
+
+    for listener in [&mut first, &mut second] {
+        let server = tokio::time::timeout(Duration::from_secs(5), listener.next()).await.unwrap().unwrap();
+        assert_eq!(server.name, "desk");
+    }
+}
+
+#[tokio::test]
+async fn test_finds_server_by_name() {
+    let config = loopback(8242);
+    announce(beacon("desk", 8080, None), config);
+    announce(beacon("lab", 9000, None), config);
+
+    let server = discovery::find("lab", config, Duration::from_secs(5)).await.unwrap();
+    assert_eq!(server.addr.to_string(), "127.0.0.1:9000");
+
+    let missing = discovery::find("attic", config, Duration::from_millis(200)).await;
+    assert!(matches!(missing, Err(NetworkError::Timeout(_))));
+}
@@ -1,4 +1,6 @@
-use rust_barrier::event::Event;
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use std::num::NonZeroU8;
 
 #[test]
 fn test_mouse_events() {
@@ -15,10 +17,10 @@ fn test_mouse_events() {
 
 #[test]
 fn test_keyboard_events() {
-    let valid_key = Event::new_key_press(65, "A".to_string());
-    assert!(valid_key.is_some());
+    let valid_key = Event::new_key_press(KeyCode(0x04), 0x41, "A".to_string());
+    assert_eq!(valid_key, Some(Event::KeyPress { code: KeyCode(0x04), keysym: 0x41, text: "A".to_string() }));
     
-    let invalid_key = Event::new_key_press(65, "".to_string());
+    let invalid_key = Event::new_key_press(KeyCode(0x03), 0x41, "A".to_string());
     assert!(invalid_key.is_none());
 }
 
@@ -45,7 +47,43 @@ fn test_event_constructors() {
     assert!(Event::new_mouse_button(1, true).is_some());
     assert!(Event::new_mouse_button(6, true).is_none());
     
+    // Test scroll validation
+    assert_eq!(Event::new_scroll(-1.0, 0.5), Some(Event::Scroll { dx: -1.0, dy: 0.5 }));
+    assert!(Event::new_scroll(0.0, 0.0).is_none());
+    assert!(Event::new_scroll(f64::NAN, 1.0).is_none());
+    
     // Test key press validation
-    assert!(Event::new_key_press(65, "A".to_string()).is_some());
-    assert!(Event::new_key_press(65, "".to_string()).is_none());
-}
\ No newline at end of file
+    assert!(Event::new_key_press(KeyCode(0xE1), 0xFFE1, String::new()).is_some());
+    assert!(Event::new_key_press(KeyCode(0xFFFF), 0, String::new()).is_none());
+}
+#[test]
+fn test_mouse_buttons() {
+    // X11 numbers, with the wheel at 4 to 7
+    for (x11, button) in [
+        (1, MouseButton::Left),
+        (2, MouseButton::Middle),
+        (3, MouseButton::Right),
+        (8, MouseButton::Back),
+        (9, MouseButton::Forward),
+        (10, MouseButton::Extra(NonZeroU8::MIN)),
+        (255, MouseButton::Extra(NonZeroU8::new(246).unwrap())),
+    ] {
+        assert_eq!(MouseButton::from_x11(x11), Some(button));
+        assert_eq!(button.to_x11(), Some(x11));
+    }
+    for x11 in [0, 4, 5, 6, 7] {
+        assert_eq!(MouseButton::from_x11(x11), None);
+    }
+
+    // On the wire a button is its X11 number, as older builds sent it
+    let event = Event::MouseButton { button: MouseButton::Back, pressed: true };
+    let json = serde_json::to_string(&event).unwrap();
+    assert_eq!(json, r#"{"MouseButton":{"button":8,"pressed":true}}"#);
+    assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+    assert!(serde_json::from_str::<Event>(r#"{"MouseButton":{"button":4,"pressed":true}}"#).is_err());
+
+    // Past button 255 there is no number to send, rather than a wrong one
+    let beyond = MouseButton::Extra(NonZeroU8::new(247).unwrap());
+    assert_eq!(beyond.to_x11(), None);
+    assert!(serde_json::to_string(&Event::MouseButton { button: beyond, pressed: true }).is_err());
+}
@@ -0,0 +1,85 @@
+use rust_barrier::event::Event;
+use rust_barrier::network::heartbeat::HeartbeatConfig;
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+const FAST: HeartbeatConfig = HeartbeatConfig {
+    interval: Duration::from_millis(50),
+    timeout: Duration::from_millis(200),
+};
+
+async fn connect_pair(port: u16) -> (NetworkConnection, NetworkConnection) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (NetworkConnection::new(client), NetworkConnection::new(server))
+}
+
+#[tokio::test]
+async fn test_silent_peer_times_out() {
+    let (mut client, _server) = connect_pair(8130).await;
+    client.set_heartbeat(FAST);
+
+    let result = client.receive_event().await;
+    assert!(matches!(result, Err(NetworkError::Timeout(timeout)) if timeout == FAST.timeout));
+}
+
+#[tokio::test]
+async fn test_heartbeats_keep_connection_alive() {
+    let (mut client, mut server) = connect_pair(8131).await;
+    client.set_heartbeat(FAST);
+    server.set_heartbeat(FAST);
+
+    // The server only sends heartbeats for well past the timeout, then a real event
+    let sender = tokio::spawn(async move {
+        let stop = tokio::time::Instant::now() + Duration::from_millis(600);
+        while tokio::time::Instant::now() < stop {
+            tokio::time::sleep_until(server.next_heartbeat().unwrap()).await;
+            server.send_heartbeat_if_due().await.unwrap();
+        }
+        server.send_event(Event::MouseMove { x: 1, y: 2 }).await.unwrap();
+        server
+    });
+
+    // Heartbeats are consumed, so the first thing returned is the real event
+    let event = client.receive_event().await.unwrap();
+    assert_eq!(event, Event::MouseMove { x: 1, y: 2 });
+    sender.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_deadline_survives_cancelled_receives() {
+    let (mut client, _server) = connect_pair(8132).await;
+    client.set_heartbeat(FAST);
+
+    // Repeatedly abandoning the read must not push the deadline back
+    let started = tokio::time::Instant::now();
+    let error = loop {
+        tokio::select! {
+            result = client.receive_event() => break result.unwrap_err(),
+            _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+        }
+    };
+    assert!(matches!(error, NetworkError::Timeout(_)));
+    assert!(started.elapsed() < Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn test_heartbeat_only_sent_when_idle() {
+    let (mut client, mut server) = connect_pair(8133).await;
+    client.set_heartbeat(FAST);
+
+    assert!(!client.send_heartbeat_if_due().await.unwrap());
+    tokio::time::sleep(FAST.interval).await;
+    // Any outgoing traffic counts, so a fresh event postpones the heartbeat
+    client.send_event(Event::MouseMove { x: 3, y: 4 }).await.unwrap();
+    assert!(!client.send_heartbeat_if_due().await.unwrap());
+
+    tokio::time::sleep(FAST.interval).await;
+    assert!(client.send_heartbeat_if_due().await.unwrap());
+
+    // Without heartbeats enabled the server sees the keepalive as a plain event
+    assert_eq!(server.receive_event().await.unwrap(), Event::MouseMove { x: 3, y: 4 });
+    assert_eq!(server.receive_event().await.unwrap(), Event::Heartbeat);
+}
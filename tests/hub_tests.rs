@@ -0,0 +1,224 @@
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::auth::Role;
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+const SECRET: &[u8] = b"correct horse battery staple";
+
+fn addr() -> SocketAddr {
+    "127.0.0.1:1".parse().unwrap()
+}
+
+fn switch(to: &str) -> Event {
+    Event::ScreenSwitch { to_screen: to.to_string() }
+}
+
+#[tokio::test]
+async fn test_routes_only_to_active_screen() {
+    let hub = Hub::new("desk");
+    let mut left = hub.register("left", addr(), false).unwrap();
+    let mut right = hub.register("right", addr(), false).unwrap();
+
+    // Joining does not take the pointer away from the local screen
+    assert_eq!(hub.active_screen(), None);
+    assert!(!hub.dispatch(Event::MouseMove { x: 0, y: 0 }));
+    assert!(!hub.dispatch(switch("left")));
+    assert_eq!(left.recv().await, Some(switch("left")));
+
+    assert!(hub.dispatch(Event::MouseMove { x: 1, y: 1 }));
+    assert!(!hub.dispatch(switch("right")));
+    assert!(hub.dispatch(Event::MouseMove { x: 2, y: 2 }));
+
+    assert_eq!(left.recv().await, Some(Event::MouseMove { x: 1, y: 1 }));
+    // The screen that lost the pointer hears where it went
+    assert_eq!(left.recv().await, Some(switch("right")));
+    assert_eq!(right.recv().await, Some(switch("right")));
+    assert_eq!(right.recv().await, Some(Event::MouseMove { x: 2, y: 2 }));
+    assert_eq!(hub.screens(), ["left", "right"]);
+}
+
+#[tokio::test]
+async fn test_switching_to_local_and_unknown_screens() {
+    let hub = Hub::new("desk");
+    let _left = hub.register("left", addr(), false).unwrap();
+    hub.dispatch(switch("left"));
+
+    hub.dispatch(switch("nowhere"));
+    assert_eq!(hub.active_screen().as_deref(), Some("left"));
+
+    hub.dispatch(switch("desk"));
+    assert_eq!(hub.active_screen(), None);
+    assert!(!hub.dispatch(Event::MouseMove { x: 1, y: 1 }));
+}
+
+#[tokio::test]
+async fn test_leaving_returns_control_locally() {
+    let hub = Hub::new("desk");
+    let mut events = hub.subscribe();
+    let left = hub.register("left", addr(), false).unwrap();
+    hub.dispatch(switch("left"));
+    left.leave("unplugged");
+
+    assert_eq!(events.recv().await.unwrap(), HubEvent::Joined { screen: "left".to_string(), addr: addr() });
+    assert_eq!(events.recv().await.unwrap(), HubEvent::Switched { to: Some("left".to_string()) });
+    assert_eq!(
+        events.recv().await.unwrap(),
+        HubEvent::Left { screen: "left".to_string(), reason: "unplugged".to_string() }
+    );
+    assert_eq!(events.recv().await.unwrap(), HubEvent::Switched { to: None });
+    assert!(hub.screens().is_empty());
+}
+
+#[tokio::test]
+async fn test_screen_names_are_not_shared() {
+    let hub = Hub::new("desk");
+    let _laptop = hub.register("laptop", addr(), false).unwrap();
+
+    assert!(matches!(hub.register("laptop", addr(), false), Err(NetworkError::ScreenTaken(name)) if name == "laptop"));
+    assert!(matches!(hub.register("desk", addr(), false), Err(NetworkError::ScreenTaken(_))));
+    assert!(matches!(hub.replace("desk", addr(), false), Err(NetworkError::ScreenTaken(_))));
+    assert_eq!(hub.screens(), ["laptop"]);
+}
+
+#[tokio::test]
+async fn test_replace_takes_over_stale_client() {
+    let hub = Hub::new("desk");
+    let mut stale = hub.register("laptop", addr(), false).unwrap();
+    hub.dispatch(switch("laptop"));
+    let mut fresh = hub.replace("laptop", addr(), false).unwrap();
+
+    assert_eq!(stale.recv().await, Some(switch("laptop")));
+    assert_eq!(stale.recv().await, None);
+    // The replacement is told it has the pointer
+    assert_eq!(fresh.recv().await, Some(switch("laptop")));
+
+    // The stale connection going away must not remove its replacement
+    drop(stale);
+    assert_eq!(hub.active_screen().as_deref(), Some("laptop"));
+    hub.dispatch(Event::MouseMove { x: 5, y: 5 });
+    assert_eq!(fresh.recv().await, Some(Event::MouseMove { x: 5, y: 5 }));
+}
+
+#[tokio::test]
+async fn test_switch_next_cycles_through_screens() {
+    let hub = Hub::new("desk");
+    let _right = hub.register("right", addr(), false).unwrap();
+    let _left = hub.register("left", addr(), false).unwrap();
+
+    assert_eq!(hub.switch_next().as_deref(), Some("left"));
+    assert_eq!(hub.switch_next().as_deref(), Some("right"));
+    // Back to the local screen after the last one
+    assert_eq!(hub.switch_next(), None);
+    assert_eq!(hub.active_screen(), None);
+    assert_eq!(hub.switch_next().as_deref(), Some("left"));
+}
+
+async fn join(name: &str) -> NetworkConnection {
+    let stream = TcpStream::connect("127.0.0.1:8150").await.unwrap();
+    let mut conn = NetworkConnection::new(stream);
+    conn.client_handshake(Hello::new(name)).await.unwrap();
+    conn
+}
+
+#[tokio::test]
+async fn test_serve_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:8150").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let mut left = join("left").await;
+    let mut right = join("right").await;
+    // A second client under a taken name is told why it is turned away
+    let mut impostor = join("left").await;
+    assert_eq!(impostor.receive_event().await.unwrap(), Event::Error("A screen named left is already connected".to_string()));
+    while hub.screens().len() < 2 {
+        events.recv().await.unwrap();
+    }
+
+    hub.dispatch(switch("left"));
+    hub.dispatch(switch("right"));
+    hub.dispatch(Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
+    assert_eq!(left.receive_event().await.unwrap(), switch("left"));
+    assert_eq!(left.receive_event().await.unwrap(), switch("right"));
+    assert_eq!(right.receive_event().await.unwrap(), switch("right"));
+    assert_eq!(right.receive_event().await.unwrap(), Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
+
+    // Dropping the active client hands control back to the server
+    drop(right);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while events.recv().await.unwrap() != (HubEvent::Switched { to: None }) {}
+    })
+    .await
+    .unwrap();
+    assert_eq!(hub.screens(), ["left"]);
+}
+
+/// Joins `127.0.0.1:8257` as `name`, authenticating with the shared secret
+/// under `identity`.
+async fn join_authenticated(name: &str, identity: &str) -> NetworkConnection {
+    let stream = TcpStream::connect("127.0.0.1:8257").await.unwrap();
+    let mut conn = NetworkConnection::new(stream);
+    let mut hello = Hello::new(name);
+    hello.capabilities.auth = true;
+    conn.client_handshake(hello).await.unwrap();
+    conn.set_identity(identity);
+    conn.set_credentials(SECRET);
+    conn.authenticate(Role::Client).await.unwrap();
+    conn
+}
+
+#[tokio::test]
+async fn test_only_the_screen_itself_takes_its_name_over() {
+    let listener = TcpListener::bind("127.0.0.1:8257").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let mut hello = Hello::new("desk");
+                hello.capabilities.auth = true;
+                let session = conn.server_handshake(hello).await?;
+                conn.set_identity("desk");
+                conn.set_credentials(SECRET);
+                conn.authenticate(Role::Server).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let _laptop = join_authenticated("laptop", "laptop").await;
+    assert!(matches!(events.recv().await.unwrap(), HubEvent::Joined { screen, .. } if screen == "laptop"));
+
+    // Knowing the secret does not make another client the laptop
+    let mut intruder = join_authenticated("laptop", "tablet").await;
+    let refused = tokio::time::timeout(Duration::from_secs(5), intruder.receive_event()).await.unwrap();
+    assert_eq!(refused.unwrap(), Event::Error("A screen named laptop is already connected".to_string()));
+
+    // The laptop itself, reconnecting, does take its name back
+    let _reconnected = join_authenticated("laptop", "laptop").await;
+    loop {
+        match events.recv().await.unwrap() {
+            HubEvent::Joined { screen, .. } if screen == "laptop" => break,
+            HubEvent::Joined { screen, .. } => panic!("{} joined", screen),
+            _ => {}
+        }
+    }
+    assert_eq!(hub.screens(), ["laptop"]);
+}
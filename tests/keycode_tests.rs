@@ -0,0 +1,58 @@
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use std::collections::HashSet;
+
+#[test]
+fn test_table_round_trips() {
+    let keys: Vec<_> = (0..=0xFFFF).map(KeyCode).filter(|code| code.name().is_some()).collect();
+    assert!(keys.len() > 100);
+    let mut evdev = HashSet::new();
+    let mut xkb = HashSet::new();
+    for code in keys {
+        // Every translation leads back to the same key, and no two keys share one
+        let name = code.name().unwrap();
+        assert_eq!(KeyCode::from_name(name), Some(code));
+        assert_eq!(KeyCode::from_xkb_name(code.xkb_name().unwrap()), Some(code), "{}", name);
+        assert!(xkb.insert(code.xkb_name()), "{}", name);
+        // Except Non-US #, which evdev does not tell apart from Backslash
+        if code == KeyCode(0x32) {
+            assert_eq!(KeyCode::from_evdev(code.to_evdev().unwrap()), Some(KeyCode(0x31)));
+            continue;
+        }
+        assert_eq!(KeyCode::from_evdev(code.to_evdev().unwrap()), Some(code), "{}", name);
+        assert!(evdev.insert(code.to_evdev()), "{}", name);
+    }
+}
+
+#[test]
+fn test_editing_and_media_keys() {
+    assert_eq!(KeyCode::from_xkb_name("I129").unwrap().to_string(), "KeypadComma");
+    assert_eq!(KeyCode::from_name("Copy").unwrap().to_evdev(), Some(133));
+    // Consumer page usages sit above the keyboard page
+    let play = KeyCode::from_name("PlayPause").unwrap();
+    assert_eq!(play, KeyCode(0xC0CD));
+    assert_eq!(play.to_evdev(), Some(164));
+    assert_eq!(KeyCode::from_xkb_name("I172"), Some(play));
+}
+
+#[test]
+fn test_physical_keys() {
+    let a = KeyCode::from_xkb_name("AC01").unwrap();
+    assert_eq!(a, KeyCode(0x04));
+    assert_eq!(a.to_evdev(), Some(30));
+    assert_eq!(KeyCode::from_evdev(42), Some(KeyCode(0xE1)));
+    assert_eq!(KeyCode::from_xkb_name("COMP").unwrap().to_string(), "Menu");
+    assert_eq!(KeyCode(0x03).xkb_name(), None);
+    assert_eq!(KeyCode(0x03).to_string(), "0x0003");
+}
+
+#[test]
+fn test_wire_format() {
+    // Keys travel as the bare usage
+    let event = Event::KeyPress { code: KeyCode(0x28), keysym: 0xFF0D, text: String::new() };
+    assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"KeyPress":{"code":40,"keysym":65293,"text":""}}"#);
+    assert_eq!(
+        serde_json::from_str::<Event>(r#"{"KeyRelease":{"code":40,"keysym":65293}}"#).unwrap(),
+        Event::KeyRelease { code: KeyCode(0x28), keysym: 0xFF0D }
+    );
+}
@@ -0,0 +1,165 @@
+use rust_barrier::event::Event;
+use rust_barrier::network::codec::{EventCodec, JsonCodec};
+use rust_barrier::network::frame::{self, FrameType, DEFAULT_MAX_FRAME_SIZE};
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::Hub;
+use rust_barrier::network::latency::{LatencyConfig, LatencySample, LatencyStats};
+use rust_barrier::network::NetworkConnection;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn fast_pings() -> LatencyConfig {
+    LatencyConfig { ping_interval: Duration::from_millis(10), ..LatencyConfig::default() }
+}
+
+fn sample(rtt_ms: u64, clock_offset: i64) -> LatencySample {
+    LatencySample { rtt: Duration::from_millis(rtt_ms), clock_offset }
+}
+
+/// Answers pings (and sends its own, if enabled) until the peer goes away.
+async fn keep_ticking(mut conn: NetworkConnection) {
+    loop {
+        let next = conn.next_heartbeat();
+        tokio::select! {
+            received = conn.receive_event() => if received.is_err() { return },
+            _ = tokio::time::sleep_until(next.unwrap_or_else(|| tokio::time::Instant::now() + Duration::from_secs(60))) => {
+                if conn.send_heartbeat_if_due().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ntp_timestamps() {
+    // The peer's clock is 500us ahead, each way takes 100us, and the peer
+    // holds the ping for 100us
+    let sample = LatencySample::from_timestamps(1_000, 1_600, 1_700, 1_300);
+    assert_eq!(sample.rtt, Duration::from_micros(200));
+    assert_eq!(sample.clock_offset, 500);
+}
+
+#[test]
+fn test_percentiles_over_window() {
+    let mut stats = LatencyStats::new(100);
+    assert!(stats.report().is_none());
+    for rtt in (1..=100).rev() {
+        stats.record(sample(rtt, rtt as i64 * 10));
+    }
+    let report = stats.report().unwrap();
+    assert_eq!(report.samples, 100);
+    assert_eq!(report.latest, Duration::from_millis(1));
+    assert_eq!(report.p50, Duration::from_millis(50));
+    assert_eq!(report.p95, Duration::from_millis(95));
+    assert_eq!(report.p99, Duration::from_millis(99));
+    // The offset is taken from the quickest round trip
+    assert_eq!(report.clock_offset, 10);
+
+    // A degraded link shows up once old samples leave the window
+    for _ in 0..100 {
+        stats.record(sample(300, 0));
+    }
+    assert_eq!(stats.percentile(50.0), Some(Duration::from_millis(300)));
+    assert_eq!(stats.count(), 200);
+}
+
+#[tokio::test]
+async fn test_ping_pong_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:8210").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8210").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    // Only one side pings; the other answers from its heartbeat branch
+    tokio::spawn(keep_ticking(NetworkConnection::new(server_stream)));
+
+    let mut client = NetworkConnection::new(client_stream);
+    assert!(client.latency().is_none());
+    client.set_latency(fast_pings());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while client.latency_stats().count() < 5 {
+            let next = client.next_heartbeat().unwrap();
+            tokio::select! {
+                received = client.receive_event() => { received.unwrap(); }
+                _ = tokio::time::sleep_until(next) => { client.send_heartbeat_if_due().await.unwrap(); }
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    let report = client.latency().unwrap();
+    assert!(report.p99 < Duration::from_secs(1));
+    // Both ends share a clock
+    assert!(report.clock_offset.abs() < 50_000, "offset {}us", report.clock_offset);
+}
+
+#[tokio::test]
+async fn test_hub_reports_client_latency() {
+    let listener = TcpListener::bind("127.0.0.1:8211").await.unwrap();
+    let hub = Hub::new("desk");
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                conn.set_latency(fast_pings());
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let mut client = NetworkConnection::new(TcpStream::connect("127.0.0.1:8211").await.unwrap());
+    client.client_handshake(Hello::new("laptop")).await.unwrap();
+    tokio::spawn(keep_ticking(client));
+
+    let report = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(report) = hub.latency("laptop") {
+                return report;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(report.samples >= 1);
+    assert!(hub.latency("nobody").is_none());
+}
+
+#[tokio::test]
+async fn test_unread_pongs_are_not_piled_up() {
+    // Room for about one pong, so the rest back up in the connection
+    let (ours, peer) = tokio::io::duplex(64);
+    let (mut peer_reader, mut peer_writer) = tokio::io::split(peer);
+    let mut conn = NetworkConnection::from_stream(ours);
+    tokio::spawn(async move {
+        for id in 0..200i64 {
+            let payload: Vec<u8> = [id, 0].iter().flat_map(|field| field.to_be_bytes()).collect();
+            let ping = frame::encode(FrameType::Ping, &payload, DEFAULT_MAX_FRAME_SIZE).unwrap();
+            peer_writer.write_all(&ping).await.unwrap();
+        }
+        let event = JsonCodec.encode(&Event::MouseMove { x: 1, y: 2 }).unwrap();
+        let event = frame::encode(FrameType::Event, &event, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        peer_writer.write_all(&event).await.unwrap();
+    });
+    assert_eq!(conn.receive_event().await.unwrap(), Event::MouseMove { x: 1, y: 2 });
+
+    // Only now does the peer read: a few pongs, then the event behind them
+    let sending = tokio::spawn(async move { conn.send_event(Event::MouseMove { x: 3, y: 4 }).await });
+    let mut pongs = 0;
+    loop {
+        let mut header = [0; 5];
+        peer_reader.read_exact(&mut header).await.unwrap();
+        let mut payload = vec![0; u32::from_be_bytes(header[1..].try_into().unwrap()) as usize];
+        peer_reader.read_exact(&mut payload).await.unwrap();
+        match FrameType::try_from(header[0]).unwrap() {
+            FrameType::Pong => pongs += 1,
+            FrameType::Event => break,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    sending.await.unwrap().unwrap();
+    assert!((1..=4).contains(&pongs), "{} pongs", pongs);
+}
@@ -0,0 +1,92 @@
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::frame::{self, FrameType, DEFAULT_MAX_FRAME_SIZE};
+use rust_barrier::network::handshake::{HandshakeError, Hello};
+use rust_barrier::network::limits::ReceiveLimits;
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+/// Our end of an in-memory link, and the raw peer end to write frames on.
+fn link(limits: ReceiveLimits) -> (NetworkConnection, DuplexStream) {
+    let (ours, theirs) = tokio::io::duplex(1 << 20);
+    let mut conn = NetworkConnection::from_stream(ours);
+    conn.set_receive_limits(limits);
+    (conn, theirs)
+}
+
+async fn send(peer: &mut DuplexStream, event: &Event) {
+    let payload = serde_json::to_vec(event).unwrap();
+    peer.write_all(&frame::encode(FrameType::Event, &payload, DEFAULT_MAX_FRAME_SIZE).unwrap()).await.unwrap();
+}
+
+fn message(text: String) -> Event {
+    Event::Error(text)
+}
+
+#[tokio::test]
+async fn test_long_strings_are_dropped() {
+    let (mut conn, mut peer) = link(ReceiveLimits { max_string_len: 16, ..ReceiveLimits::default() });
+    send(&mut peer, &message("x".repeat(17))).await;
+    send(&mut peer, &Event::ScreenSwitch { to_screen: "y".repeat(1000) }).await;
+    send(&mut peer, &message("AC01".to_string())).await;
+    send(&mut peer, &Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".repeat(17) }).await;
+
+    let error = conn.receive_event().await.unwrap_err();
+    assert!(error.is_violation());
+    assert_eq!(error.to_string(), "error message of 17 bytes exceeds the 16 byte limit");
+    let error = conn.receive_event().await.unwrap_err();
+    assert!(matches!(error, NetworkError::FieldTooLong { field: "screen name", len: 1000, max: 16 }));
+    // The connection carries on
+    assert_eq!(conn.receive_event().await.unwrap(), message("AC01".to_string()));
+    assert!(matches!(conn.receive_event().await, Err(NetworkError::FieldTooLong { field: "key text", len: 17, max: 16 })));
+}
+
+#[tokio::test]
+async fn test_event_rate_is_limited() {
+    let limits = ReceiveLimits { max_events_per_second: 10, burst: 5, ..ReceiveLimits::default() };
+    let (mut conn, mut peer) = link(limits);
+    for x in 0..7 {
+        send(&mut peer, &Event::MouseMove { x, y: 0 }).await;
+    }
+
+    // The burst goes through, the rest is dropped
+    for x in 0..5 {
+        assert_eq!(conn.receive_event().await.unwrap(), Event::MouseMove { x, y: 0 });
+    }
+    assert!(matches!(conn.receive_event().await, Err(NetworkError::RateLimited(10))));
+
+    // Tokens come back over time
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(matches!(conn.receive_event().await, Ok(Event::MouseMove { x: 6, y: 0 })));
+}
+
+#[tokio::test]
+async fn test_repeat_offender_is_cut_off() {
+    let (mut conn, mut peer) = link(ReceiveLimits { max_string_len: 4, max_violations: 2, ..ReceiveLimits::default() });
+    for _ in 0..3 {
+        send(&mut peer, &message("too long".to_string())).await;
+    }
+    send(&mut peer, &message("ok".to_string())).await;
+
+    assert!(conn.receive_event().await.unwrap_err().is_violation());
+    assert!(conn.receive_event().await.unwrap_err().is_violation());
+    let error = conn.receive_event().await.unwrap_err();
+    assert!(!error.is_violation());
+    assert_eq!(error.to_string(), "Peer cut off after 3 protocol violations");
+    // Even well-formed events are refused from now on
+    assert_eq!(conn.receive_event().await.unwrap_err().to_string(), error.to_string());
+}
+
+#[tokio::test]
+async fn test_long_screen_name_is_rejected() {
+    let (client_end, server_end) = tokio::io::duplex(4096);
+    let mut server = NetworkConnection::from_stream(server_end);
+    let mut client = NetworkConnection::from_stream(client_end);
+
+    let accepting = tokio::spawn(async move { server.server_handshake(Hello::new("desk")).await });
+    let result = client.client_handshake(Hello::new("z".repeat(300))).await;
+    assert!(matches!(result, Err(NetworkError::Handshake(HandshakeError::Rejected(_)))));
+    let error = accepting.await.unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "screen name of 300 bytes exceeds the 256 byte limit");
+}
@@ -0,0 +1,182 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::motion::{MotionReceiver, MotionSender, HEADER_LEN};
+use rust_barrier::network::NetworkConnection;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+async fn connect_pair(port: u16) -> (NetworkConnection, NetworkConnection) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (NetworkConnection::new(client), NetworkConnection::new(server))
+}
+
+fn datagram(token: u64, seq: u64, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(HEADER_LEN + payload.len());
+    datagram.extend_from_slice(&token.to_be_bytes());
+    datagram.extend_from_slice(&seq.to_be_bytes());
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+/// Forwards datagrams to `target`, dropping every fourth, swapping every
+/// fifth with the one after it and sending every seventh twice.
+async fn lossy_relay(target: SocketAddr) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        let mut held = None;
+        for n in 1u32.. {
+            let len = socket.recv(&mut buf).await.unwrap();
+            let packet = buf[..len].to_vec();
+            if n % 4 == 0 {
+                continue;
+            }
+            if n % 5 == 0 {
+                held = Some(packet);
+                continue;
+            }
+            socket.send_to(&packet, target).await.unwrap();
+            if n % 7 == 0 {
+                socket.send_to(&packet, target).await.unwrap();
+            }
+            if let Some(late) = held.take() {
+                socket.send_to(&late, target).await.unwrap();
+            }
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_receiver_drops_stale_and_foreign_datagrams() {
+    let mut receiver = MotionReceiver::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let token = receiver.token();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(receiver.local_addr().unwrap()).await.unwrap();
+
+    socket.send(&datagram(token, 5, b"five")).await.unwrap();
+    socket.send(&datagram(token, 3, b"stale")).await.unwrap();
+    socket.send(&datagram(token, 5, b"duplicate")).await.unwrap();
+    socket.send(&datagram(token.wrapping_add(1), 9, b"foreign")).await.unwrap();
+    socket.send(b"short").await.unwrap();
+    socket.send(&datagram(token, 6, b"six")).await.unwrap();
+
+    assert_eq!(receiver.recv().await.unwrap(), b"five");
+    assert_eq!(receiver.recv().await.unwrap(), b"six");
+    assert_eq!(receiver.dropped(), 4);
+}
+
+#[tokio::test]
+async fn test_motion_survives_packet_loss() {
+    let (mut client, mut server) = connect_pair(8160).await;
+    let receiver = MotionReceiver::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let relay = lossy_relay(receiver.local_addr().unwrap()).await;
+    server.send_motion_via(MotionSender::connect(relay, receiver.token()).await.unwrap());
+    client.receive_motion_via(receiver);
+
+    let sender = tokio::spawn(async move {
+        for i in 0..100 {
+            server.send_event(Event::MouseMove { x: i, y: i }).await.unwrap();
+            if i % 10 == 9 {
+                server.send_event(Event::KeyPress { code: KeyCode(i as u16), keysym: 0, text: String::new() }).await.unwrap();
+            }
+            // Give the relay a chance to interleave
+            tokio::task::yield_now().await;
+        }
+        server
+    });
+
+    let mut keys = Vec::new();
+    let mut positions = Vec::new();
+    while let Ok(event) = tokio::time::timeout(Duration::from_millis(300), client.receive_event()).await {
+        match event.unwrap() {
+            Event::KeyPress { code, .. } => keys.push(code.0),
+            Event::MouseMove { x, .. } => positions.push(x),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    let _server = sender.await.unwrap();
+
+    // Keys are reliable and ordered; motion is lossy but never goes backwards
+    assert_eq!(keys, (0..10).map(|i| i * 10 + 9).collect::<Vec<_>>());
+    assert!(!positions.is_empty() && positions.len() < 100);
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn test_lost_motion_is_repeated_before_a_click() {
+    let (mut client, mut server) = connect_pair(8250).await;
+    let receiver = MotionReceiver::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    // Nothing listens here, so every datagram is lost
+    let black_hole = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    server.send_motion_via(MotionSender::connect(black_hole.local_addr().unwrap(), receiver.token()).await.unwrap());
+    client.receive_motion_via(receiver);
+
+    server.send_event(Event::MouseMove { x: 1, y: 1 }).await.unwrap();
+    server.send_event(Event::MouseMove { x: 40, y: 30 }).await.unwrap();
+    let press = Event::MouseButton { button: MouseButton::Left, pressed: true };
+    let release = Event::MouseButton { button: MouseButton::Left, pressed: false };
+    server.send_event(press.clone()).await.unwrap();
+    server.send_event(release.clone()).await.unwrap();
+
+    assert_eq!(client.receive_event().await.unwrap(), Event::MouseMove { x: 40, y: 30 });
+    assert_eq!(client.receive_event().await.unwrap(), press);
+    // Repeated once, not before every event
+    assert_eq!(client.receive_event().await.unwrap(), release);
+}
+
+#[tokio::test]
+async fn test_negotiated_motion_channel() {
+    let (mut client, mut server) = connect_pair(8161).await;
+    let mut hello = Hello::new("laptop");
+    hello.capabilities.udp_motion = true;
+    let mut server_hello = Hello::new("desk");
+    server_hello.capabilities.udp_motion = true;
+
+    let (client_session, server_session) =
+        tokio::join!(client.client_handshake(hello), server.server_handshake(server_hello));
+    assert!(client_session.unwrap().udp_motion);
+    assert!(server_session.unwrap().udp_motion);
+
+    let localhost = "127.0.0.1".parse().unwrap();
+    let (listening, sending) = tokio::join!(client.listen_for_motion(localhost), server.send_motion_to(localhost));
+    assert_eq!(listening.unwrap(), sending.unwrap());
+
+    server.send_event(Event::MouseMove { x: 7, y: 8 }).await.unwrap();
+    assert_eq!(client.receive_event().await.unwrap(), Event::MouseMove { x: 7, y: 8 });
+    server.send_event(Event::MouseButton { button: MouseButton::Left, pressed: true }).await.unwrap();
+    assert_eq!(client.receive_event().await.unwrap(), Event::MouseButton { button: MouseButton::Left, pressed: true });
+}
+
+#[tokio::test]
+async fn test_udp_motion_needs_both_sides() {
+    let (mut client, mut server) = connect_pair(8162).await;
+    let mut hello = Hello::new("laptop");
+    hello.capabilities.udp_motion = true;
+
+    let (client_session, server_session) =
+        tokio::join!(client.client_handshake(hello), server.server_handshake(Hello::new("desk")));
+    assert!(!client_session.unwrap().udp_motion);
+    assert!(!server_session.unwrap().udp_motion);
+}
+
+#[tokio::test]
+async fn test_udp_motion_not_negotiated_with_auth() {
+    let (mut client, mut server) = connect_pair(8251).await;
+    let mut hello = Hello::new("laptop");
+    hello.capabilities.udp_motion = true;
+    hello.capabilities.auth = true;
+    let mut server_hello = Hello::new("desk");
+    server_hello.capabilities.udp_motion = true;
+    server_hello.capabilities.auth = true;
+
+    let (client_session, server_session) =
+        tokio::join!(client.client_handshake(hello), server.server_handshake(server_hello));
+    assert!(!client_session.unwrap().udp_motion);
+    assert!(!server_session.unwrap().udp_motion);
+}
@@ -1,15 +1,41 @@
+use rust_barrier::event::Event;
+use rust_barrier::network::auth::Role;
+use rust_barrier::network::handshake::{HandshakeError, Hello};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use tokio::net::{TcpListener, TcpStream};
+
+const SECRET: &[u8] = b"correct horse battery staple";
+
+async fn establish_test_connection() -> (NetworkConnection, NetworkConnection) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client_stream = TcpStream::connect(addr).await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+
+    let mut client = NetworkConnection::new(client_stream);
+    let mut server = NetworkConnection::new(server_stream);
+    client.set_identity("laptop");
+    server.set_identity("desk");
+    client.set_credentials(SECRET);
+    server.set_credentials(SECRET);
+    (client, server)
+}
+
 #[tokio::test]
 async fn test_secure_handshake() {
     let (mut client, mut server) = establish_test_connection().await;
     
     // Perform mutual authentication
-    let client_result = client.authenticate().await;
-    let server_result = server.authenticate().await;
+    let (client_result, server_result) = tokio::join!(client.authenticate(Role::Client), server.authenticate(Role::Server));
     
     assert!(client_result.is_ok());
     assert!(server_result.is_ok());
     assert_eq!(client.peer_identity(), Some(server.id()));
     assert_eq!(server.peer_identity(), Some(client.id()));
+
+    // The connection stays usable for events afterwards
+    client.send_event(Event::Heartbeat).await.unwrap();
+    assert_eq!(server.receive_event().await.unwrap(), Event::Heartbeat);
 }
 
 #[tokio::test]
@@ -18,9 +44,108 @@ async fn test_handshake_rejects_invalid_credentials() {
     
     client.set_credentials(b"wrong_password");
     
-    let client_result = client.authenticate().await;
-    let server_result = server.authenticate().await;
+    let (client_result, server_result) = tokio::join!(client.authenticate(Role::Client), server.authenticate(Role::Server));
     
     assert!(matches!(client_result, Err(HandshakeError::AuthenticationFailed)));
     assert!(matches!(server_result, Err(HandshakeError::AuthenticationFailed)));
-} 
\ No newline at end of file
+    assert_eq!(client.peer_identity(), None);
+    assert_eq!(server.peer_identity(), None);
+}
+
+#[tokio::test]
+async fn test_handshake_requires_credentials() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client_stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let mut client = NetworkConnection::new(client_stream);
+
+    assert!(matches!(client.authenticate(Role::Client).await, Err(HandshakeError::MissingCredentials)));
+}
+
+#[tokio::test]
+async fn test_secret_never_sent() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client_stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (mut raw_server, _) = listener.accept().await.unwrap();
+
+    let mut client = NetworkConnection::new(client_stream);
+    client.set_identity("laptop");
+    client.set_credentials(SECRET);
+
+    // Answer the challenge with garbage and capture everything the client sends
+    let peer = async {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut challenge = vec![0x13, 0, 0, 0, 32];
+        challenge.extend_from_slice(&[7u8; 32]);
+        raw_server.write_all(&challenge).await.unwrap();
+        raw_server.write_all(&[0x14, 0, 0, 0, 32]).await.unwrap();
+        raw_server.write_all(&[0u8; 32]).await.unwrap();
+        // Challenge frame (nonce + "laptop") followed by the proof frame
+        let mut seen = vec![0u8; 5 + 32 + 6 + 5 + 32];
+        raw_server.read_exact(&mut seen).await.unwrap();
+        seen
+    };
+    let (result, seen) = tokio::join!(client.authenticate(Role::Client), peer);
+
+    assert!(matches!(result, Err(HandshakeError::AuthenticationFailed)));
+    assert!(!seen.windows(SECRET.len()).any(|window| window == SECRET));
+}
+
+#[tokio::test]
+async fn test_proofs_are_bound_to_a_role() {
+    let (mut client, mut server) = establish_test_connection().await;
+
+    // Two servers each prove as a server, so neither proof passes as a client's
+    let (client_result, server_result) = tokio::join!(client.authenticate(Role::Server), server.authenticate(Role::Server));
+    assert!(matches!(client_result, Err(HandshakeError::AuthenticationFailed)));
+    assert!(matches!(server_result, Err(HandshakeError::AuthenticationFailed)));
+}
+
+#[tokio::test]
+async fn test_peer_claiming_our_identity_is_refused() {
+    let (mut client, mut server) = establish_test_connection().await;
+    client.set_identity("desk");
+
+    let (client_result, server_result) = tokio::join!(client.authenticate(Role::Client), server.authenticate(Role::Server));
+    assert!(matches!(client_result, Err(HandshakeError::AuthenticationFailed)));
+    assert!(matches!(server_result, Err(HandshakeError::AuthenticationFailed)));
+    assert_eq!(server.peer_identity(), None);
+}
+
+#[tokio::test]
+async fn test_auth_required_by_server() {
+    let (mut client, mut server) = establish_test_connection().await;
+
+    let mut server_hello = Hello::new("desk");
+    server_hello.capabilities.auth = true;
+    let (client_result, server_result) = tokio::join!(
+        client.client_handshake(Hello::new("laptop")),
+        server.server_handshake(server_hello),
+    );
+
+    assert!(matches!(
+        server_result,
+        Err(NetworkError::Handshake(HandshakeError::AuthenticationRequired))
+    ));
+    assert!(matches!(client_result, Err(NetworkError::Handshake(HandshakeError::Rejected(_)))));
+}
+
+#[tokio::test]
+async fn test_handshake_then_authenticate() {
+    let (mut client, mut server) = establish_test_connection().await;
+
+    let mut client_hello = Hello::new("laptop");
+    client_hello.capabilities.auth = true;
+    let mut server_hello = Hello::new("desk");
+    server_hello.capabilities.auth = true;
+    let (client_session, server_session) = tokio::join!(
+        client.client_handshake(client_hello),
+        server.server_handshake(server_hello),
+    );
+    assert!(client_session.unwrap().auth);
+    assert!(server_session.unwrap().auth);
+
+    let (client_result, server_result) = tokio::join!(client.authenticate(Role::Client), server.authenticate(Role::Server));
+    assert!(client_result.is_ok());
+    assert!(server_result.is_ok());
+    assert_eq!(server.peer_identity(), Some("laptop"));
+}
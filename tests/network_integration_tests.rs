@@ -1,5 +1,6 @@
-use rust_barrier::event::Event;
-use rust_barrier::network::{NetworkConnection, NetworkError};
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::{frame, NetworkConnection, NetworkError};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::AsyncWriteExt;
 
@@ -29,8 +30,8 @@ async fn test_event_transmission() {
     
     let events = vec![
         Event::MouseMove { x: 100, y: 200 },
-        Event::MouseButton { button: 1, pressed: true },
-        Event::KeyPress { code: 65, name: "A".to_string() },
+        Event::MouseButton { button: MouseButton::Left, pressed: true },
+        Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() },
         Event::Heartbeat,
     ];
 
@@ -64,12 +65,72 @@ async fn test_invalid_data() {
     
     let mut server_conn = NetworkConnection::new(server_stream);
     
-    // Send invalid JSON with newline delimiter
-    client.write_all(b"invalid json\n").await.unwrap();
+    // Send a well-formed frame carrying invalid JSON
+    let data = frame::encode(frame::FrameType::Event, b"invalid json", frame::DEFAULT_MAX_FRAME_SIZE).unwrap();
+    client.write_all(&data).await.unwrap();
     let result = server_conn.receive_event().await;
     assert!(matches!(result, Err(NetworkError::Serialization(_))));
 }
 
+#[tokio::test]
+async fn test_oversized_frame_rejected() {
+    let listener = TcpListener::bind("127.0.0.1:8089").await.unwrap();
+    let mut client = TcpStream::connect("127.0.0.1:8089").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    
+    let mut server_conn = NetworkConnection::new(server_stream);
+    server_conn.set_max_frame_size(1024);
+    
+    // Header announces 1 MiB; the reader must bail out before buffering it
+    let mut header = vec![frame::FrameType::Event as u8];
+    header.extend_from_slice(&(1024u32 * 1024).to_be_bytes());
+    client.write_all(&header).await.unwrap();
+    let result = server_conn.receive_event().await;
+    assert!(matches!(result, Err(NetworkError::FrameTooLarge { size: 1048576, max: 1024 })));
+}
+
+#[tokio::test]
+async fn test_truncated_frame() {
+    let listener = TcpListener::bind("127.0.0.1:8090").await.unwrap();
+    let mut client = TcpStream::connect("127.0.0.1:8090").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    
+    let mut server_conn = NetworkConnection::new(server_stream);
+    
+    let data = frame::encode(frame::FrameType::Event, br#"{"MouseMove":{"x":1,"y":2}}"#, 1024).unwrap();
+    client.write_all(&data[..10]).await.unwrap();
+    drop(client);
+    
+    let result = server_conn.receive_event().await;
+    assert!(matches!(result, Err(NetworkError::TruncatedFrame { received: 10, .. })));
+}
+
+#[tokio::test]
+async fn test_unknown_frame_type() {
+    let listener = TcpListener::bind("127.0.0.1:8091").await.unwrap();
+    let mut client = TcpStream::connect("127.0.0.1:8091").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    
+    let mut server_conn = NetworkConnection::new(server_stream);
+    
+    client.write_all(&[0xee, 0, 0, 0, 0]).await.unwrap();
+    let result = server_conn.receive_event().await;
+    assert!(matches!(result, Err(NetworkError::UnknownFrameType(0xee))));
+}
+
+#[tokio::test]
+async fn test_oversized_event_not_sent() {
+    let listener = TcpListener::bind("127.0.0.1:8092").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8092").await.unwrap();
+    let (_server_stream, _) = listener.accept().await.unwrap();
+    
+    let mut client_conn = NetworkConnection::new(client_stream);
+    client_conn.set_max_frame_size(16);
+    
+    let result = client_conn.send_event(Event::Error("x".repeat(64))).await;
+    assert!(matches!(result, Err(NetworkError::FrameTooLarge { max: 16, .. })));
+}
+
 #[tokio::test]
 async fn test_screen_switch() {
     let listener = TcpListener::bind("127.0.0.1:8082").await.unwrap();
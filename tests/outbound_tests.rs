@@ -0,0 +1,149 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::outbound::{self, OutboundConfig, OverflowPolicy};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpSocket;
+
+fn motion(x: i32) -> Event {
+    Event::MouseMove { x, y: 0 }
+}
+
+fn key(code: u16) -> Event {
+    Event::KeyPress { code: KeyCode(code), keysym: 0, text: String::new() }
+}
+
+fn config(capacity: usize, overflow: OverflowPolicy) -> OutboundConfig {
+    OutboundConfig { capacity, overflow, ..OutboundConfig::default() }
+}
+
+#[tokio::test]
+async fn test_overflow_drops_motion_next_to_motion() {
+    let (sender, mut receiver) = outbound::channel(config(3, OverflowPolicy::DropMotion));
+    for event in [motion(1), key(1), Event::MouseMoveRelative { dx: 2, dy: 0 }, motion(3)] {
+        sender.push(event).unwrap();
+    }
+    // The delta was overridden by the position after it anyway
+    assert_eq!(sender.len(), 3);
+    assert_eq!(sender.dropped(), 1);
+
+    // Motion between keys stays where it is: give up on the peer
+    assert!(matches!(sender.push(key(2)), Err(NetworkError::QueueFull(3))));
+    assert!(sender.is_closed());
+    assert_eq!(receiver.recv().await, None);
+    assert_eq!(receiver.close_reason(), Some(NetworkError::QueueFull(3).to_string()));
+}
+
+#[tokio::test]
+async fn test_dropped_deltas_are_not_lost() {
+    let (sender, mut receiver) = outbound::channel(config(2, OverflowPolicy::DropMotion));
+    sender.push(motion(100)).unwrap();
+    sender.push(Event::MouseMoveRelative { dx: 10, dy: 20 }).unwrap();
+    sender.push(key(1)).unwrap();
+
+    assert_eq!(receiver.recv().await, Some(Event::MouseMove { x: 110, y: 20 }));
+    assert_eq!(receiver.recv().await, Some(key(1)));
+}
+
+#[tokio::test]
+async fn test_dropped_deltas_never_move_a_click() {
+    let press = Event::MouseButton { button: MouseButton::Left, pressed: true };
+    let (sender, mut receiver) = outbound::channel(config(3, OverflowPolicy::DropMotion));
+    for event in [Event::MouseMoveRelative { dx: 1, dy: 2 }, press.clone(), Event::MouseMoveRelative { dx: 10, dy: 20 }] {
+        sender.push(event).unwrap();
+    }
+    // Folding either delta into the other would click somewhere else
+    assert!(matches!(sender.push(key(1)), Err(NetworkError::QueueFull(3))));
+    assert_eq!(sender.dropped(), 0);
+    assert_eq!(receiver.recv().await, None);
+}
+
+#[tokio::test]
+async fn test_disconnect_policy() {
+    let (sender, mut receiver) = outbound::channel(config(2, OverflowPolicy::Disconnect));
+    sender.push(motion(1)).unwrap();
+    sender.push(key(1)).unwrap();
+    assert!(sender.push(motion(2)).is_err());
+    assert_eq!(receiver.recv().await, None);
+    assert!(matches!(sender.push(key(2)), Err(NetworkError::Connection(_))));
+}
+
+#[tokio::test]
+async fn test_close_delivers_queued_events() {
+    let (sender, mut receiver) = outbound::channel(OutboundConfig::default());
+    sender.push(key(1)).unwrap();
+    sender.push(motion(1)).unwrap();
+    sender.close("bye");
+
+    assert_eq!(receiver.recv().await, Some(key(1)));
+    assert_eq!(receiver.recv().await, Some(motion(1)));
+    assert_eq!(receiver.recv().await, None);
+    assert_eq!(receiver.close_reason().as_deref(), Some("bye"));
+}
+
+#[tokio::test]
+async fn test_receiver_waits_for_push() {
+    let (sender, mut receiver) = outbound::channel(OutboundConfig::default());
+    let waiting = tokio::spawn(async move { receiver.recv().await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    sender.push(key(7)).unwrap();
+    assert_eq!(waiting.await.unwrap(), Some(key(7)));
+}
+
+#[tokio::test]
+async fn test_stalled_client_never_blocks_dispatch() {
+    let listen_socket = TcpSocket::new_v4().unwrap();
+    listen_socket.set_send_buffer_size(4096).unwrap();
+    listen_socket.bind("127.0.0.1:8200".parse().unwrap()).unwrap();
+    let listener = listen_socket.listen(8).unwrap();
+
+    let hub = Hub::with_outbound("desk", config(64, OverflowPolicy::Disconnect));
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let client_socket = TcpSocket::new_v4().unwrap();
+    client_socket.set_recv_buffer_size(4096).unwrap();
+    let addr: SocketAddr = "127.0.0.1:8200".parse().unwrap();
+    let mut client = NetworkConnection::new(client_socket.connect(addr).await.unwrap());
+    client.client_handshake(Hello::new("laptop")).await.unwrap();
+    while hub.screens().is_empty() {
+        hub_events.recv().await.unwrap();
+    }
+    hub.dispatch(Event::ScreenSwitch { to_screen: "laptop".to_string() });
+
+    // The client never reads; keys pile up until its queue overflows
+    let mut dispatched = 0;
+    while hub.dispatch(key(dispatched as u16)) {
+        dispatched += 1;
+        if dispatched % 100 == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(dispatched < 1_000_000, "queue never overflowed");
+    }
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let HubEvent::Left { screen, reason } = hub_events.recv().await.unwrap() {
+                assert_eq!(screen, "laptop");
+                assert_eq!(reason, NetworkError::QueueFull(64).to_string());
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(hub.active_screen(), None);
+    assert!(hub.screens().is_empty());
+}
@@ -1,7 +1,8 @@
 use rust_barrier::event::Event;
+use rust_barrier::network::latency::LatencyConfig;
 use rust_barrier::network::NetworkConnection;
 use tokio::net::{TcpListener, TcpStream};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[tokio::test]
 async fn test_event_throughput() {
@@ -30,4 +31,39 @@ async fn test_event_throughput() {
     let duration = start.elapsed();
     println!("Processed {} events in {:?}", event_count, duration);
     println!("Events per second: {}", event_count as f64 / duration.as_secs_f64());
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_round_trip_latency() {
+    let listener = TcpListener::bind("127.0.0.1:8212").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8212").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+
+    let mut client_conn = NetworkConnection::new(client_stream);
+    let mut server_conn = NetworkConnection::new(server_stream);
+    client_conn.set_latency(LatencyConfig { ping_interval: Duration::from_millis(1), window: 200 });
+
+    // The server only answers pings, from its heartbeat branch
+    tokio::spawn(async move {
+        loop {
+            let next = server_conn.next_heartbeat();
+            tokio::select! {
+                received = server_conn.receive_event() => if received.is_err() { return },
+                _ = tokio::time::sleep_until(next.unwrap_or_else(|| tokio::time::Instant::now() + Duration::from_secs(60))) => {
+                    server_conn.send_heartbeat_if_due().await.unwrap();
+                }
+            }
+        }
+    });
+
+    while client_conn.latency_stats().count() < 200 {
+        let next = client_conn.next_heartbeat().unwrap();
+        tokio::select! {
+            received = client_conn.receive_event() => { received.unwrap(); }
+            _ = tokio::time::sleep_until(next) => { client_conn.send_heartbeat_if_due().await.unwrap(); }
+        }
+    }
+
+    let report = client_conn.latency().unwrap();
+    println!("Round trip over loopback: p50 {:?}, p95 {:?}, p99 {:?}", report.p50, report.p95, report.p99);
+    println!("Clock offset: {}us", report.clock_offset);
+}
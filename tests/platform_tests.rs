@@ -1,29 +1,33 @@
 #[cfg(target_os = "linux")]
 mod linux_tests {
     use rust_barrier::event::{platform::*, Event};
+    use rust_barrier::keycode::KeyCode;
 
     #[test]
     fn test_linux_key_mapping() {
         // Test X11 keycodes to our Event mapping
-        let x11_key = Keycode::from(65); // 'A' in X11
+        let x11_key = Keycode::from(38); // 'A' in X11, evdev code 30
 
         // Convert using the library helper
-        let event = Event::new_key_press(x11_key.into(), "A".to_string()).unwrap();
+        let code = KeyCode::from_evdev(u16::from(x11_key) - 8).unwrap();
+        let event = Event::new_key_press(code, 0x61, "a".to_string()).unwrap();
 
-        assert_eq!(event, Event::KeyPress { code: 65, name: "A".to_string() });
+        assert_eq!(event, Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
     }
 }
 
 #[cfg(target_os = "windows")]
 mod windows_tests {
     use rust_barrier::event::{platform::*, Event};
+    use rust_barrier::keycode::KeyCode;
 
     #[test]
     fn test_windows_key_mapping() {
         let vk = VIRTUAL_KEY(0x41); // 'A' in Windows
 
-        let event = Event::new_key_press(vk.0 as u16, "A".to_string()).unwrap();
+        // Virtual keys are not physical keys; the letters match their HID usage by offset
+        let event = Event::new_key_press(KeyCode(vk.0 - 0x41 + 0x04), 0x61, "a".to_string()).unwrap();
 
-        assert_eq!(event, Event::KeyPress { code: 0x41, name: "A".to_string() });
+        assert_eq!(event, Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
     }
 }
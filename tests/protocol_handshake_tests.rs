@@ -0,0 +1,147 @@
+use rust_barrier::event::Event;
+use rust_barrier::network::codec::CodecKind;
+use rust_barrier::network::handshake::{negotiate, Capabilities, HandshakeError, Hello, ScreenInfo, PROTOCOL_VERSION};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn connect(port: u16) -> (NetworkConnection, NetworkConnection) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let client_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    (NetworkConnection::new(client_stream), NetworkConnection::new(server_stream))
+}
+
+fn hello_with_codecs(name: &str, codecs: Vec<CodecKind>) -> Hello {
+    Hello {
+        capabilities: Capabilities { codecs, ..Capabilities::default() },
+        ..Hello::new(name)
+    }
+}
+
+#[tokio::test]
+async fn test_handshake_success() {
+    let (mut client, mut server) = connect(8110).await;
+
+    let client_hello = Hello {
+        screen: ScreenInfo { width: 1920, height: 1080 },
+        ..Hello::new("laptop")
+    };
+    let (client_session, server_session) = tokio::join!(
+        client.client_handshake(client_hello),
+        server.server_handshake(Hello::new("desk")),
+    );
+    let client_session = client_session.unwrap();
+    let server_session = server_session.unwrap();
+
+    assert_eq!(client_session.peer.name, "desk");
+    assert_eq!(server_session.peer.name, "laptop");
+    assert_eq!(server_session.peer.screen, ScreenInfo { width: 1920, height: 1080 });
+    assert_eq!(client_session.version, PROTOCOL_VERSION);
+    assert_eq!(client_session.codec, server_session.codec);
+
+    // Events flow normally once the handshake is done
+    client.send_event(Event::Heartbeat).await.unwrap();
+    assert_eq!(server.receive_event().await.unwrap(), Event::Heartbeat);
+}
+
+#[tokio::test]
+async fn test_client_codec_preference_wins() {
+    let (mut client, mut server) = connect(8111).await;
+
+    let client_hello = hello_with_codecs("laptop", vec![CodecKind::Bincode, CodecKind::Json]);
+    let server_hello = hello_with_codecs("desk", vec![CodecKind::Json, CodecKind::Bincode]);
+    let (client_session, server_session) = tokio::join!(
+        client.client_handshake(client_hello),
+        server.server_handshake(server_hello),
+    );
+
+    assert_eq!(client_session.unwrap().codec, CodecKind::Bincode);
+    assert_eq!(server_session.unwrap().codec, CodecKind::Bincode);
+}
+
+#[tokio::test]
+async fn test_incompatible_version_rejected() {
+    let (mut client, mut server) = connect(8112).await;
+
+    let client_hello = Hello {
+        version: PROTOCOL_VERSION + 5,
+        min_version: PROTOCOL_VERSION + 5,
+        ..Hello::new("future")
+    };
+    let (client_result, server_result) = tokio::join!(
+        client.client_handshake(client_hello),
+        server.server_handshake(Hello::new("desk")),
+    );
+
+    assert!(matches!(
+        server_result,
+        Err(NetworkError::Handshake(HandshakeError::VersionMismatch { .. }))
+    ));
+    match client_result {
+        Err(NetworkError::Handshake(HandshakeError::Rejected(reason))) => {
+            assert!(reason.contains("version"), "{}", reason);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_event_before_hello_rejected() {
+    let (mut client, mut server) = connect(8113).await;
+
+    // A peer from before the handshake existed starts sending events right away
+    let (_, server_result) = tokio::join!(
+        client.send_event(Event::MouseMove { x: 1, y: 1 }),
+        server.server_handshake(Hello::new("desk")),
+    );
+    assert!(matches!(
+        server_result,
+        Err(NetworkError::Handshake(HandshakeError::UnexpectedFrame { .. }))
+    ));
+}
+
+#[test]
+fn test_newer_peer_interoperates() {
+    let local = Hello::new("desk");
+    let peer = Hello {
+        version: PROTOCOL_VERSION + 1,
+        min_version: PROTOCOL_VERSION,
+        ..Hello::new("newer")
+    };
+    let session = negotiate(&local, &peer).unwrap();
+    assert_eq!(session.version, PROTOCOL_VERSION);
+}
+
+#[test]
+fn test_no_common_codec() {
+    let local = hello_with_codecs("desk", vec![CodecKind::Json]);
+    let peer = hello_with_codecs("laptop", vec![CodecKind::Cbor]);
+    assert!(matches!(negotiate(&local, &peer), Err(HandshakeError::NoCommonCodec { .. })));
+}
+
+#[test]
+fn test_capabilities_negotiated_conservatively() {
+    let mut local = Hello::new("desk");
+    local.capabilities.relative_motion = true;
+    local.capabilities.clipboard = true;
+    let mut peer = Hello::new("laptop");
+    peer.capabilities.relative_motion = true;
+
+    let session = negotiate(&local, &peer).unwrap();
+    assert!(session.relative_motion);
+    assert!(!session.clipboard);
+}
+
+#[test]
+fn test_unknown_fields_and_codecs_ignored() {
+    let json = r#"{
+        "version": 1,
+        "min_version": 1,
+        "name": "newer",
+        "capabilities": { "codecs": ["Protobuf", "Json"], "teleport": true },
+        "colour": "blue"
+    }"#;
+    let hello: Hello = serde_json::from_str(json).unwrap();
+    assert_eq!(hello.capabilities.codecs, vec![CodecKind::Json]);
+    assert_eq!(hello.screen, ScreenInfo::default());
+}
@@ -0,0 +1,145 @@
+#![cfg(feature = "quic")]
+
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::Hub;
+use rust_barrier::network::quic::{QuicConnection, QuicEndpoint};
+use rust_barrier::network::tls::{Fingerprint, TlsConfig, TlsIdentity};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::net::SocketAddr;
+
+struct Peers {
+    _server_endpoint: QuicEndpoint,
+    client_endpoint: QuicEndpoint,
+    server: (NetworkConnection, QuicConnection),
+    client: (NetworkConnection, QuicConnection),
+    fingerprints: (Fingerprint, Fingerprint),
+}
+
+fn configs() -> (TlsConfig, TlsConfig) {
+    let server_identity = TlsIdentity::generate("desk").unwrap();
+    let client_identity = TlsIdentity::generate("laptop").unwrap();
+    (
+        TlsConfig::new(server_identity.clone()).pin(client_identity.fingerprint()),
+        TlsConfig::new(client_identity).pin(server_identity.fingerprint()),
+    )
+}
+
+async fn connect(port: u16) -> Peers {
+    let (server_config, client_config) = configs();
+    let fingerprints = (server_config.identity().fingerprint(), client_config.identity().fingerprint());
+    let server_addr: SocketAddr = ([127, 0, 0, 1], port).into();
+    let server_endpoint = QuicEndpoint::server(server_addr, &server_config).unwrap();
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), &client_config).unwrap();
+
+    // The server only sees the event stream once the client sends on it,
+    // so both sides run through to the end of the handshake together
+    let (client, server) = tokio::join!(
+        async {
+            let (mut conn, quic) = client_endpoint.connect(server_addr).await.unwrap();
+            assert_eq!(conn.client_handshake(Hello::new("laptop")).await.unwrap().peer.name, "desk");
+            (conn, quic)
+        },
+        async {
+            let (mut conn, quic) = server_endpoint.accept().await.unwrap();
+            conn.server_handshake(Hello::new("desk")).await.unwrap();
+            (conn, quic)
+        }
+    );
+
+    Peers { _server_endpoint: server_endpoint, client_endpoint, server, client, fingerprints }
+}
+
+#[tokio::test]
+async fn test_events_over_quic() {
+    let mut peers = connect(8170).await;
+    let event = Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() };
+    peers.server.0.send_event(event.clone()).await.unwrap();
+    assert_eq!(peers.client.0.receive_event().await.unwrap(), event);
+
+    // Each side sees the certificate the other one pinned
+    let (server_fingerprint, client_fingerprint) = peers.fingerprints;
+    assert_eq!(peers.client.1.peer_fingerprint().unwrap(), server_fingerprint);
+    assert_eq!(peers.server.1.peer_fingerprint().unwrap(), client_fingerprint);
+}
+
+#[tokio::test]
+async fn test_bulk_does_not_block_events() {
+    let mut peers = connect(8171).await;
+    let clipboard = vec![0x5a; 4 * 1024 * 1024];
+
+    let bulk = peers.server.1.clone();
+    let payload = clipboard.clone();
+    let transfer = tokio::spawn(async move { bulk.send_bulk(&payload).await });
+
+    // Input keeps flowing while the transfer is in progress
+    for x in 0..50 {
+        peers.server.0.send_event(Event::MouseMove { x, y: 0 }).await.unwrap();
+    }
+    for x in 0..50 {
+        assert_eq!(peers.client.0.receive_event().await.unwrap(), Event::MouseMove { x, y: 0 });
+    }
+
+    assert_eq!(peers.client.1.receive_bulk().await.unwrap(), clipboard);
+    transfer.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_connection_migrates_to_new_address() {
+    let mut peers = connect(8172).await;
+    let before = peers.server.1.remote_address();
+
+    let after = peers.client_endpoint.rebind("127.0.0.1:0".parse().unwrap()).unwrap();
+    assert_ne!(before, after);
+
+    // The client speaks first so the server learns the new path
+    peers.client.0.send_event(Event::MouseMove { x: 1, y: 1 }).await.unwrap();
+    assert_eq!(peers.server.0.receive_event().await.unwrap(), Event::MouseMove { x: 1, y: 1 });
+    peers.server.0.send_event(Event::MouseMove { x: 2, y: 2 }).await.unwrap();
+    assert_eq!(peers.client.0.receive_event().await.unwrap(), Event::MouseMove { x: 2, y: 2 });
+    assert_eq!(peers.server.1.remote_address(), after);
+}
+
+#[tokio::test]
+async fn test_unpinned_server_rejected() {
+    let (server_config, _) = configs();
+    let (_, client_config) = configs();
+    let server_addr: SocketAddr = "127.0.0.1:8173".parse().unwrap();
+    let server_endpoint = QuicEndpoint::server(server_addr, &server_config).unwrap();
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), &client_config).unwrap();
+
+    let (client, _) = tokio::join!(client_endpoint.connect(server_addr), server_endpoint.accept());
+    assert!(matches!(client, Err(NetworkError::Quic(_))));
+}
+
+#[tokio::test]
+async fn test_hub_serves_quic_clients() {
+    let (server_config, client_config) = configs();
+    let server_addr: SocketAddr = "127.0.0.1:8174".parse().unwrap();
+    let server_endpoint = QuicEndpoint::server(server_addr, &server_config).unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(server_endpoint, |incoming| async {
+                let (mut conn, _quic) = incoming.establish().await?;
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), &client_config).unwrap();
+    let (mut conn, _quic) = client_endpoint.connect(server_addr).await.unwrap();
+    conn.client_handshake(Hello::new("laptop")).await.unwrap();
+    while hub.screens().is_empty() {
+        hub_events.recv().await.unwrap();
+    }
+
+    hub.dispatch(Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    hub.dispatch(Event::MouseButton { button: MouseButton::Left, pressed: true });
+    assert_eq!(conn.receive_event().await.unwrap(), Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    assert_eq!(conn.receive_event().await.unwrap(), Event::MouseButton { button: MouseButton::Left, pressed: true });
+}
@@ -0,0 +1,130 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::handshake::{HandshakeError, Hello};
+use rust_barrier::network::reconnect::{Backoff, BackoffConfig, Reconnector, SessionState};
+use rust_barrier::network::{NetworkConnection, NetworkError};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const QUICK: BackoffConfig = BackoffConfig {
+    initial: Duration::from_millis(10),
+    max: Duration::from_millis(40),
+    multiplier: 2.0,
+    jitter: 0.0,
+    connect_timeout: Duration::from_millis(500),
+};
+
+#[test]
+fn test_backoff_grows_to_max_and_resets() {
+    let mut backoff = Backoff::new(QUICK);
+    let delays: Vec<_> = (0..4).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays, [10, 20, 40, 40].map(Duration::from_millis));
+
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_millis(10));
+}
+
+#[test]
+fn test_backoff_jitter_stays_in_bounds() {
+    let mut backoff = Backoff::new(BackoffConfig { jitter: 0.5, ..QUICK });
+    for _ in 0..100 {
+        let delay = backoff.next_delay();
+        assert!(delay >= Duration::from_millis(5) && delay <= Duration::from_millis(60));
+    }
+}
+
+#[tokio::test]
+async fn test_falls_over_to_next_address() {
+    // Nothing listens on 8140, the "wired" address
+    let listener = TcpListener::bind("127.0.0.1:8141").await.unwrap();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let addresses = vec!["127.0.0.1:8140".to_string(), "127.0.0.1:8141".to_string()];
+    let mut reconnector = Reconnector::new(addresses, QUICK);
+    let mut failures = Vec::new();
+    let conn = reconnector
+        .connect(|stream| async { Ok(NetworkConnection::new(stream)) }, |address, _| {
+            failures.push(address.to_string())
+        })
+        .await;
+    assert!(conn.is_ok());
+    assert_eq!(failures, ["127.0.0.1:8140"]);
+    assert_eq!(reconnector.connected_address(), Some("127.0.0.1:8141"));
+    accept.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_retries_until_server_returns() {
+    let addresses = vec!["127.0.0.1:8142".to_string()];
+    let mut reconnector = Reconnector::new(addresses, QUICK);
+
+    // The server comes back after a few failed passes
+    let server = tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let listener = TcpListener::bind("127.0.0.1:8142").await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut conn = NetworkConnection::new(stream);
+        conn.server_handshake(Hello::new("desk")).await.unwrap()
+    });
+
+    let mut attempts = 0;
+    let session = reconnector
+        .connect(
+            |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                conn.client_handshake(Hello::new("laptop")).await
+            },
+            |_, _| attempts += 1,
+        )
+        .await
+        .unwrap();
+    assert!(attempts > 0);
+    assert_eq!(session.peer.name, "desk");
+    assert_eq!(server.await.unwrap().peer.name, "laptop");
+}
+
+#[tokio::test]
+async fn test_rejection_is_not_retried() {
+    let listener = TcpListener::bind("127.0.0.1:8143").await.unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut conn = NetworkConnection::new(stream);
+        let mut hello = Hello::new("desk");
+        hello.capabilities.auth = true;
+        let _ = conn.server_handshake(hello).await;
+    });
+
+    let mut reconnector = Reconnector::new(vec!["127.0.0.1:8143".to_string()], QUICK);
+    let result = reconnector
+        .connect(
+            |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                conn.client_handshake(Hello::new("laptop")).await
+            },
+            |_, e| panic!("retried after {}", e),
+        )
+        .await;
+    assert!(matches!(result, Err(NetworkError::Handshake(HandshakeError::Rejected(_)))));
+}
+
+#[test]
+fn test_session_state_releases_held_input() {
+    let mut state = SessionState::default();
+    state.observe(&Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    state.observe(&Event::KeyPress { code: KeyCode(0xE1), keysym: 0xFFE1, text: String::new() });
+    state.observe(&Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
+    state.observe(&Event::KeyRelease { code: KeyCode(0x04), keysym: 0x61 });
+    state.observe(&Event::MouseButton { button: MouseButton::Left, pressed: true });
+
+    assert_eq!(
+        state.release_held(),
+        vec![
+            Event::KeyRelease { code: KeyCode(0xE1), keysym: 0xFFE1 },
+            Event::MouseButton { button: MouseButton::Left, pressed: false },
+        ]
+    );
+    assert!(state.release_held().is_empty());
+
+    // The current screen survives the disconnect
+    assert_eq!(state.current_screen(), Some("laptop"));
+}
@@ -0,0 +1,93 @@
+use rust_barrier::event::{Event, MouseButton};
+use rust_barrier::network::coalesce::MotionCoalescer;
+use rust_barrier::network::handshake::{Capabilities, Hello};
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::NetworkConnection;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+
+fn addr() -> SocketAddr {
+    "127.0.0.1:1".parse().unwrap()
+}
+
+fn delta(dx: i32, dy: i32) -> Event {
+    Event::MouseMoveRelative { dx, dy }
+}
+
+fn relative_hello(name: &str) -> Hello {
+    Hello {
+        capabilities: Capabilities { relative_motion: true, ..Capabilities::default() },
+        ..Hello::new(name)
+    }
+}
+
+#[tokio::test]
+async fn test_hub_sends_each_client_its_kind_of_motion() {
+    let hub = Hub::new("desk");
+    let mut absolute = hub.register("absolute", addr(), false).unwrap();
+    let mut relative = hub.register("relative", addr(), true).unwrap();
+    hub.dispatch(Event::ScreenSwitch { to_screen: "absolute".to_string() });
+    assert_eq!(absolute.recv().await, Some(Event::ScreenSwitch { to_screen: "absolute".to_string() }));
+
+    assert!(!hub.dispatch(delta(1, 1)));
+    assert!(hub.dispatch(Event::MouseMove { x: 5, y: 5 }));
+    hub.dispatch(Event::ScreenSwitch { to_screen: "relative".to_string() });
+    assert!(hub.dispatch(delta(2, -3)));
+    assert!(!hub.dispatch(Event::MouseMove { x: 7, y: 2 }));
+    // Everything else goes to either kind
+    assert!(hub.dispatch(Event::MouseButton { button: MouseButton::Left, pressed: true }));
+
+    assert_eq!(absolute.recv().await, Some(Event::MouseMove { x: 5, y: 5 }));
+    assert_eq!(relative.recv().await, Some(Event::ScreenSwitch { to_screen: "relative".to_string() }));
+    assert_eq!(relative.recv().await, Some(delta(2, -3)));
+    assert_eq!(relative.recv().await, Some(Event::MouseButton { button: MouseButton::Left, pressed: true }));
+}
+
+#[test]
+fn test_coalescer_sums_deltas() {
+    let mut coalescer = MotionCoalescer::default();
+    coalescer.push(delta(1, 2));
+    coalescer.push(delta(3, -4));
+    coalescer.push(delta(-1, 0));
+    // Deltas and positions never merge with each other
+    coalescer.push(Event::MouseMove { x: 10, y: 10 });
+    coalescer.push(delta(5, 5));
+
+    let sent: Vec<_> = std::iter::from_fn(|| coalescer.pop()).collect();
+    assert_eq!(sent, [delta(3, -2), Event::MouseMove { x: 10, y: 10 }, delta(5, 5)]);
+    assert_eq!(coalescer.merged(), 2);
+}
+
+#[tokio::test]
+async fn test_relative_mode_is_negotiated() {
+    let listener = TcpListener::bind("127.0.0.1:8190").await.unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::new(stream);
+                let session = conn.server_handshake(relative_hello("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    // The server offers relative motion but this client does not ask for it
+    let mut absolute = NetworkConnection::new(TcpStream::connect("127.0.0.1:8190").await.unwrap());
+    assert!(!absolute.client_handshake(Hello::new("absolute")).await.unwrap().relative_motion);
+    let mut relative = NetworkConnection::new(TcpStream::connect("127.0.0.1:8190").await.unwrap());
+    assert!(relative.client_handshake(relative_hello("relative")).await.unwrap().relative_motion);
+    while hub.screens().len() < 2 {
+        hub_events.recv().await.unwrap();
+    }
+
+    hub.dispatch(Event::ScreenSwitch { to_screen: "relative".to_string() });
+    while hub_events.recv().await.unwrap() != (HubEvent::Switched { to: Some("relative".to_string()) }) {}
+    hub.dispatch(Event::MouseMove { x: 100, y: 100 });
+    hub.dispatch(delta(4, -2));
+
+    assert_eq!(relative.receive_event().await.unwrap(), Event::ScreenSwitch { to_screen: "relative".to_string() });
+    assert_eq!(relative.receive_event().await.unwrap(), delta(4, -2));
+}
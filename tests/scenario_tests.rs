@@ -1,4 +1,5 @@
 use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
 use rust_barrier::network::NetworkConnection;
 use tokio::net::{TcpListener, TcpStream};
 
@@ -33,8 +34,8 @@ async fn test_keyboard_combinations() {
     let mut client_conn = NetworkConnection::new(client_stream);
     let mut server_conn = NetworkConnection::new(server_stream);
 
-    let ctrl_press = Event::KeyPress { code: 37, name: "Control_L".to_string() };
-    let c_press = Event::KeyPress { code: 54, name: "c".to_string() };
+    let ctrl_press = Event::KeyPress { code: KeyCode(0xE0), keysym: 0xFFE3, text: String::new() };
+    let c_press = Event::KeyPress { code: KeyCode(0x06), keysym: 0x63, text: "c".to_string() };
     
     client_conn.send_event(ctrl_press.clone()).await.unwrap();
     client_conn.send_event(c_press.clone()).await.unwrap();
@@ -0,0 +1,98 @@
+#![cfg(unix)]
+
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::codec::JsonCodec;
+use rust_barrier::network::handshake::Hello;
+use rust_barrier::network::hub::Hub;
+use rust_barrier::network::reconnect::{BackoffConfig, Reconnector};
+use rust_barrier::network::transport::Unix;
+use rust_barrier::network::NetworkConnection;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::net::UnixListener;
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::MouseMove { x: 10, y: 20 },
+        Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() },
+        Event::ScreenSwitch { to_screen: "laptop".to_string() },
+    ]
+}
+
+#[tokio::test]
+async fn test_handshake_over_duplex_pipe() {
+    let (client_end, server_end) = tokio::io::duplex(64);
+    let mut server = NetworkConnection::from_stream(server_end);
+    let mut client = NetworkConnection::from_stream(client_end);
+
+    let accepting = tokio::spawn(async move {
+        let session = server.server_handshake(Hello::new("desk")).await.unwrap();
+        assert_eq!(session.peer.name, "laptop");
+        for event in events() {
+            server.send_event(event).await.unwrap();
+        }
+    });
+    let session = client.client_handshake(Hello::new("laptop")).await.unwrap();
+    assert_eq!(session.peer.name, "desk");
+    for event in events() {
+        assert_eq!(client.receive_event().await.unwrap(), event);
+    }
+    accepting.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_child_process_pipes() {
+    // `cat` hands every frame straight back
+    let mut child = tokio::process::Command::new("cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut conn = NetworkConnection::from_halves(child.stdout.take().unwrap(), child.stdin.take().unwrap(), JsonCodec);
+
+    for event in events() {
+        conn.send_event(event.clone()).await.unwrap();
+        assert_eq!(conn.receive_event().await.unwrap(), event);
+    }
+}
+
+#[tokio::test]
+async fn test_hub_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("rust-barrier-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let serving = hub.clone();
+    tokio::spawn(async move {
+        serving
+            .serve(listener, |stream| async {
+                let mut conn = NetworkConnection::from_stream(stream);
+                let session = conn.server_handshake(Hello::new("desk")).await?;
+                Ok((conn, session))
+            })
+            .await
+    });
+
+    let mut reconnector = Reconnector::new(vec![path.to_string_lossy().into_owned()], BackoffConfig::default());
+    let mut client = reconnector
+        .connect_via(&Unix, |stream| async {
+            let mut conn = NetworkConnection::from_stream(stream);
+            conn.client_handshake(Hello::new("laptop")).await?;
+            Ok(conn)
+        }, |_, _| {})
+        .await
+        .unwrap();
+
+    while hub.screens().is_empty() {
+        hub_events.recv().await.unwrap();
+    }
+    hub.dispatch(Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    assert_eq!(client.receive_event().await.unwrap(), Event::ScreenSwitch { to_screen: "laptop".to_string() });
+    assert!(hub.dispatch(Event::MouseMove { x: 1, y: 2 }));
+    let received = tokio::time::timeout(Duration::from_secs(5), client.receive_event()).await.unwrap();
+    assert_eq!(received.unwrap(), Event::MouseMove { x: 1, y: 2 });
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,132 @@
+use rust_barrier::event::{DeviceId, Event, EventClock, TimedEvent};
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::handshake::{Capabilities, Hello, Session};
+use rust_barrier::network::hub::{Hub, HubEvent};
+use rust_barrier::network::sequence::{Delivery, SequenceTracker};
+use rust_barrier::network::NetworkConnection;
+use std::time::Duration;
+
+fn hello(name: &str, timed_events: bool) -> Hello {
+    Hello {
+        capabilities: Capabilities { timed_events, ..Capabilities::default() },
+        ..Hello::new(name)
+    }
+}
+
+/// Both ends of a handshaken in-memory link, with the server's session.
+async fn pair(server_timed: bool, client_timed: bool) -> (NetworkConnection, Session, NetworkConnection) {
+    let (client_end, server_end) = tokio::io::duplex(1 << 16);
+    let mut server = NetworkConnection::from_stream(server_end);
+    let mut client = NetworkConnection::from_stream(client_end);
+    let accepting = tokio::spawn(async move {
+        let session = server.server_handshake(hello("desk", server_timed)).await.unwrap();
+        (server, session)
+    });
+    client.client_handshake(hello("laptop", client_timed)).await.unwrap();
+    let (server, session) = accepting.await.unwrap();
+    (server, session, client)
+}
+
+fn event(source: u32, seq: u64) -> TimedEvent {
+    TimedEvent { source: DeviceId(source), ..TimedEvent::new(Event::MouseMove { x: 0, y: 0 }, seq) }
+}
+
+#[tokio::test]
+async fn test_timing_travels_with_the_event() {
+    let (mut server, session, mut client) = pair(true, true).await;
+    assert!(session.timed_events);
+
+    let mut clock = EventClock::new(DeviceId(7));
+    let sent = clock.stamp(Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() });
+    server.send_timed(sent.clone()).await.unwrap();
+    server.send_timed(clock.stamp(Event::MouseMove { x: 1, y: 2 })).await.unwrap();
+
+    assert_eq!(client.receive_timed().await.unwrap(), sent);
+    let received = client.receive_timed().await.unwrap();
+    assert_eq!((received.seq, received.source), (2, DeviceId(7)));
+    assert!(received.captured_at >= sent.captured_at);
+}
+
+#[tokio::test]
+async fn test_untimed_peer_gets_plain_events() {
+    let (mut server, session, mut client) = pair(true, false).await;
+    assert!(!session.timed_events);
+
+    server.send_timed(EventClock::new(DeviceId(7)).stamp(Event::MouseMove { x: 1, y: 2 })).await.unwrap();
+    let received = client.receive_timed().await.unwrap();
+    assert_eq!(received.event, Event::MouseMove { x: 1, y: 2 });
+    assert!(!received.is_sequenced());
+}
+
+#[test]
+fn test_tracker_classifies_deliveries() {
+    let mut tracker = SequenceTracker::new();
+    assert_eq!(tracker.observe(&event(1, 5)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&event(1, 6)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&event(1, 9)), Delivery::Gap { missed: 2 });
+    assert_eq!(tracker.observe(&event(1, 7)), Delivery::Late);
+    assert_eq!(tracker.observe(&event(1, 7)), Delivery::Duplicate);
+    assert_eq!(tracker.observe(&event(1, 6)), Delivery::Duplicate);
+    // Sources are counted separately
+    assert_eq!(tracker.observe(&event(2, 1)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&TimedEvent::untimed(Event::Heartbeat)), Delivery::Unsequenced);
+
+    assert_eq!(tracker.missing(), 1);
+    assert_eq!(tracker.late(), 1);
+    assert_eq!(tracker.duplicates(), 2);
+}
+
+#[test]
+fn test_tracker_survives_hostile_sequence_numbers() {
+    let mut tracker = SequenceTracker::new();
+    assert_eq!(tracker.observe(&event(1, u64::MAX)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&event(1, 3)), Delivery::Duplicate);
+
+    // Gaps as large as they come still add up without overflowing
+    assert_eq!(tracker.observe(&event(2, 1)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&event(2, u64::MAX)), Delivery::Gap { missed: u64::MAX - 2 });
+    assert_eq!(tracker.observe(&event(3, 1)), Delivery::InOrder);
+    assert_eq!(tracker.observe(&event(3, u64::MAX)), Delivery::Gap { missed: u64::MAX - 2 });
+    assert_eq!(tracker.missing(), u64::MAX);
+}
+
+#[test]
+fn test_age_corrects_for_clock_offset() {
+    let mut event = TimedEvent::new(Event::Heartbeat, 1);
+    event.captured_at -= 50_000;
+    let age = event.age(0);
+    assert!(age >= Duration::from_millis(50) && age < Duration::from_secs(5));
+    // The same timestamp from a clock running 30ms ahead of ours is older
+    let age = event.age(30_000);
+    assert!(age >= Duration::from_millis(80) && age < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_hub_keeps_counting_across_reconnects() {
+    let hub = Hub::new("desk");
+    let mut hub_events = hub.subscribe();
+    let mut sequence = SequenceTracker::new();
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        let (server, session, mut client) = pair(true, true).await;
+        let serving = hub.clone();
+        let task = tokio::spawn(async move { serving.serve_client(server, session, "127.0.0.1:1".parse().unwrap()).await });
+
+        while !matches!(hub_events.recv().await.unwrap(), HubEvent::Joined { .. }) {}
+        hub.dispatch(Event::ScreenSwitch { to_screen: "laptop".to_string() });
+        let joined = client.receive_timed().await.unwrap();
+        assert_eq!(joined.event, Event::ScreenSwitch { to_screen: "laptop".to_string() });
+        assert!(hub.dispatch(Event::MouseMove { x: 1, y: 2 }));
+        let moved = tokio::time::timeout(Duration::from_secs(5), client.receive_timed()).await.unwrap().unwrap();
+        for timed in [joined, moved] {
+            assert_eq!(sequence.observe(&timed), Delivery::InOrder);
+            received.push(timed);
+        }
+        drop(client);
+        task.await.unwrap();
+    }
+    let seqs: Vec<_> = received.iter().map(|timed| timed.seq).collect();
+    assert_eq!(seqs, [1, 2, 3, 4]);
+    // Anything replayed from before the reconnect is recognized
+    assert_eq!(sequence.observe(&received[1]), Delivery::Duplicate);
+}
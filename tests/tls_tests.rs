@@ -0,0 +1,166 @@
+use rust_barrier::event::Event;
+use rust_barrier::keycode::KeyCode;
+use rust_barrier::network::tls::{self, Fingerprint, KnownServers, TlsConfig, TlsIdentity};
+use rust_barrier::network::NetworkError;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[tokio::test]
+async fn test_pinned_connection() {
+    let server_identity = TlsIdentity::generate("desk").unwrap();
+    let client_identity = TlsIdentity::generate("laptop").unwrap();
+    let server_config = TlsConfig::new(server_identity.clone()).pin(client_identity.fingerprint());
+    let client_config = TlsConfig::new(client_identity.clone()).pin(server_identity.fingerprint());
+
+    let listener = TcpListener::bind("127.0.0.1:8120").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8120").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        tls::connect(client_stream, &client_config),
+        tls::accept(server_stream, &server_config),
+    );
+    let (mut client_conn, seen_server) = client.unwrap();
+    let (mut server_conn, seen_client) = server.unwrap();
+    assert_eq!(seen_server, server_identity.fingerprint());
+    assert_eq!(seen_client, client_identity.fingerprint());
+
+    let event = Event::KeyPress { code: KeyCode(0x04), keysym: 0x61, text: "a".to_string() };
+    client_conn.send_event(event.clone()).await.unwrap();
+    assert_eq!(server_conn.receive_event().await.unwrap(), event);
+}
+
+#[tokio::test]
+async fn test_unpinned_peer_rejected() {
+    let server_config = TlsConfig::new(TlsIdentity::generate("desk").unwrap());
+    let impostor = TlsIdentity::generate("impostor").unwrap();
+    let client_config = TlsConfig::new(TlsIdentity::generate("laptop").unwrap()).pin(impostor.fingerprint());
+
+    let listener = TcpListener::bind("127.0.0.1:8121").await.unwrap();
+    let client_stream = TcpStream::connect("127.0.0.1:8121").await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        tls::connect(client_stream, &client_config),
+        tls::accept(server_stream, &server_config),
+    );
+    match client {
+        Err(NetworkError::Tls(message)) => assert!(message.contains("not pinned"), "{}", message),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("connected to an unpinned server"),
+    }
+    assert!(server.is_err());
+}
+
+#[tokio::test]
+async fn test_traffic_is_encrypted() {
+    let server_identity = TlsIdentity::generate("desk").unwrap();
+    let server_config = TlsConfig::new(server_identity.clone());
+    let client_config = TlsConfig::new(TlsIdentity::generate("laptop").unwrap()).pin(server_identity.fingerprint());
+
+    // Relay client -> server bytes through a tap so we can inspect the wire
+    let listener = TcpListener::bind("127.0.0.1:8122").await.unwrap();
+    let tap = TcpListener::bind("127.0.0.1:8123").await.unwrap();
+    let relay = tokio::spawn(async move {
+        let (mut from_client, _) = tap.accept().await.unwrap();
+        let mut to_server = TcpStream::connect("127.0.0.1:8122").await.unwrap();
+        let (mut server_read, mut server_write) = to_server.split();
+        let (mut client_read, mut client_write) = from_client.split();
+        let mut captured = Vec::new();
+        let upstream = async {
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = client_read.read(&mut buf).await.unwrap_or(0);
+                if n == 0 {
+                    break;
+                }
+                captured.extend_from_slice(&buf[..n]);
+                server_write.write_all(&buf[..n]).await.unwrap();
+            }
+        };
+        let downstream = tokio::io::copy(&mut server_read, &mut client_write);
+        let _ = tokio::join!(upstream, downstream);
+        captured
+    });
+
+    let client_stream = TcpStream::connect("127.0.0.1:8123").await.unwrap();
+    let accept = async {
+        let (server_stream, _) = listener.accept().await.unwrap();
+        tls::accept(server_stream, &server_config).await
+    };
+    let (client, server) = tokio::join!(tls::connect(client_stream, &client_config), accept);
+    let (mut client_conn, _) = client.unwrap();
+    let (mut server_conn, _) = server.unwrap();
+
+    let event = Event::ScreenSwitch { to_screen: "hunter2-password-screen".to_string() };
+    client_conn.send_event(event.clone()).await.unwrap();
+    assert_eq!(server_conn.receive_event().await.unwrap(), event);
+    drop(client_conn);
+    drop(server_conn);
+
+    let captured = relay.await.unwrap();
+    assert!(!captured.is_empty());
+    assert!(!captured.windows(8).any(|window| window == b"hunter2-"));
+}
+
+async fn handshake(port: u16, server_config: &TlsConfig, client_config: &TlsConfig) -> Result<Fingerprint, NetworkError> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let client_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (server_stream, _) = listener.accept().await.unwrap();
+    let (client, _) = tokio::join!(tls::connect(client_stream, client_config), tls::accept(server_stream, server_config));
+    client.map(|(_, fingerprint)| fingerprint)
+}
+
+#[tokio::test]
+async fn test_unpinned_server_refused() {
+    let server_config = TlsConfig::new(TlsIdentity::generate("desk").unwrap());
+    let client_config = TlsConfig::new(TlsIdentity::generate("laptop").unwrap());
+
+    match handshake(8252, &server_config, &client_config).await {
+        Err(NetworkError::Tls(message)) => assert!(message.contains("no certificate pinned"), "{}", message),
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_server_trusted_on_first_use() {
+    let path = std::env::temp_dir().join(format!("rust-barrier-known-{}", std::process::id()));
+    let known = KnownServers::new(&path);
+    let server = TlsIdentity::generate("desk").unwrap();
+    let client_config = TlsConfig::new(TlsIdentity::generate("laptop").unwrap()).trust_on_first_use(known.clone());
+
+    // The first certificate is remembered and accepted again
+    let server_config = TlsConfig::new(server.clone());
+    assert_eq!(handshake(8253, &server_config, &client_config).await.unwrap(), server.fingerprint());
+    assert_eq!(known.get("127.0.0.1:8253").unwrap(), Some(server.fingerprint()));
+    assert_eq!(handshake(8253, &server_config, &client_config).await.unwrap(), server.fingerprint());
+
+    // Another certificate at the same address is not
+    let impostor_config = TlsConfig::new(TlsIdentity::generate("impostor").unwrap());
+    match handshake(8253, &impostor_config, &client_config).await {
+        Err(NetworkError::Tls(message)) => assert!(message.contains("not pinned"), "{}", message),
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+    // Other addresses start afresh
+    assert_eq!(known.get("127.0.0.1:8254").unwrap(), None);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_fingerprint_format() {
+    let fingerprint = TlsIdentity::generate("desk").unwrap().fingerprint();
+    let text = fingerprint.to_string();
+    assert_eq!(text.len(), 32 * 3 - 1);
+    assert_eq!(text.parse::<Fingerprint>().unwrap(), fingerprint);
+    assert_eq!(text.replace(':', "").to_lowercase().parse::<Fingerprint>().unwrap(), fingerprint);
+    assert!("AB:CD".parse::<Fingerprint>().is_err());
+}
+
+#[test]
+fn test_identity_persisted() {
+    let dir = std::env::temp_dir().join(format!("rust-barrier-tls-{}", std::process::id()));
+    let first = TlsIdentity::load_or_generate(&dir, "desk").unwrap();
+    let second = TlsIdentity::load_or_generate(&dir, "desk").unwrap();
+    assert_eq!(first.fingerprint(), second.fingerprint());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
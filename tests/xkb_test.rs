@@ -3,16 +3,15 @@ use xkbcommon::xkb;
 #[test]
 fn test_xkb_init() {
     let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
-    println!("Context: {:?}", context);
     
     let keymap = xkb::Keymap::new_from_names(
         &context,
-        None,
-        None,
-        None,
-        None,
+        "",
+        "",
+        "",
+        "",
         None,
         xkb::KEYMAP_COMPILE_NO_FLAGS
     );
-    println!("Keymap: {:?}", keymap);
+    println!("Keymap compiled: {}", keymap.is_some());
 } 
\ No newline at end of file